@@ -0,0 +1,223 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::sync::mpsc;
+use tracing::error;
+use uuid::Uuid;
+
+// Durable record of a single invocation. Payloads are only captured for
+// functions that opted in with `audit_payloads`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    pub invocation_id: Uuid,
+    pub timestamp: String,
+    pub namespace: String,
+    pub function: String,
+    pub version: u32,
+    pub caller: Option<String>, // authenticated identity, when known
+    pub test: bool,             // from the test endpoint, not live traffic
+    pub duration_ms: u64,
+    pub outcome: AuditOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replay_of: Option<Uuid>, // the invocation this one re-ran
+}
+
+// The parts of a logged event needed to replay it
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecordedInvocation {
+    pub invocation_id: Uuid,
+    pub namespace: String,
+    pub function: String,
+    pub version: u32,
+    pub payload: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    Error { message: String },
+}
+
+// Destination for audit events. Sinks run on the audit writer thread, so
+// they may block.
+pub trait AuditSink: Send {
+    fn record(&mut self, event: &AuditEvent) -> Result<()>;
+}
+
+// Appends one JSON object per line
+pub struct FileAuditSink {
+    writer: BufWriter<File>,
+}
+
+impl FileAuditSink {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open audit log: {}", path))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+}
+
+impl AuditSink for FileAuditSink {
+    fn record(&mut self, event: &AuditEvent) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, event)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// Emits each event as a structured tracing event under the `audit` target
+pub struct TracingAuditSink;
+
+impl AuditSink for TracingAuditSink {
+    fn record(&mut self, event: &AuditEvent) -> Result<()> {
+        tracing::info!(target: "audit", event = %serde_json::to_string(event)?);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditSinkKind {
+    File,
+    Tracing,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub sink: AuditSinkKind,
+    pub path: String, // used by the file sink
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            sink: AuditSinkKind::Tracing,
+            path: "/var/log/hyperdrive/audit.log".to_string(),
+        }
+    }
+}
+
+// Hands events to a dedicated writer thread so invocations never wait on
+// the sink
+pub struct AuditLog {
+    sender: mpsc::Sender<AuditEvent>,
+    path: Option<String>, // where events can be read back, for the file sink
+}
+
+impl AuditLog {
+    pub fn from_config(config: &AuditConfig) -> Result<Self> {
+        let sink: Box<dyn AuditSink> = match config.sink {
+            AuditSinkKind::File => Box::new(FileAuditSink::open(&config.path)?),
+            AuditSinkKind::Tracing => Box::new(TracingAuditSink),
+        };
+        let mut log = Self::new(sink)?;
+        if config.sink == AuditSinkKind::File {
+            log.path = Some(config.path.clone());
+        }
+        Ok(log)
+    }
+
+    pub fn new(mut sink: Box<dyn AuditSink>) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel::<AuditEvent>();
+
+        std::thread::Builder::new()
+            .name("audit-writer".to_string())
+            .spawn(move || {
+                for event in receiver {
+                    if let Err(e) = sink.record(&event) {
+                        error!("Failed to write audit event {}: {:#}", event.invocation_id, e);
+                    }
+                }
+            })
+            .context("Failed to start audit writer")?;
+
+        Ok(Self { sender, path: None })
+    }
+
+    pub fn record(&self, event: AuditEvent) {
+        if self.sender.send(event).is_err() {
+            error!("Audit writer has stopped; dropping audit event");
+        }
+    }
+
+    // Whether `find` can look events up
+    pub fn readable(&self) -> bool {
+        self.path.is_some()
+    }
+
+    // Look an invocation up in the file sink's log. Scans the whole file,
+    // so it blocks; lines that don't parse (say, one still being written)
+    // are skipped.
+    pub fn find(&self, invocation_id: Uuid) -> Result<Option<RecordedInvocation>> {
+        let Some(path) = &self.path else {
+            anyhow::bail!("The tracing audit sink can't be read back; set audit.sink = \"file\"");
+        };
+        let file = File::open(path).with_context(|| format!("Failed to open audit log: {}", path))?;
+        for line in BufReader::new(file).lines() {
+            let line = line.with_context(|| format!("Failed to read audit log: {}", path))?;
+            let Ok(recorded) = serde_json::from_str::<RecordedInvocation>(&line) else {
+                continue;
+            };
+            if recorded.invocation_id == invocation_id {
+                return Ok(Some(recorded));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(payload: Option<serde_json::Value>) -> AuditEvent {
+        AuditEvent {
+            invocation_id: Uuid::new_v4(),
+            timestamp: "2024-01-01T00:00:00Z".to_string(),
+            namespace: "default".to_string(),
+            function: "echo".to_string(),
+            version: 3,
+            caller: None,
+            test: false,
+            duration_ms: 5,
+            outcome: AuditOutcome::Success,
+            payload,
+            replay_of: None,
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AuditConfig {
+            sink: AuditSinkKind::File,
+            path: dir.path().join("audit.log").to_string_lossy().into_owned(),
+        };
+        let captured = event(Some(serde_json::json!({"order": 42})));
+        let bare = event(None);
+        let mut sink = FileAuditSink::open(&config.path).unwrap();
+        sink.record(&bare).unwrap();
+        sink.record(&captured).unwrap();
+
+        let log = AuditLog::from_config(&config).unwrap();
+        let found = log.find(captured.invocation_id).unwrap().unwrap();
+        assert_eq!((found.function.as_str(), found.version), ("echo", 3));
+        assert_eq!(found.payload, captured.payload);
+        assert_eq!(log.find(bare.invocation_id).unwrap().unwrap().payload, None);
+        assert!(log.find(Uuid::new_v4()).unwrap().is_none());
+
+        let tracing = AuditLog::from_config(&AuditConfig::default()).unwrap();
+        assert!(tracing.find(captured.invocation_id).is_err());
+    }
+}
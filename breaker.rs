@@ -0,0 +1,196 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BreakerConfig {
+    pub failure_threshold: u32, // consecutive failures that open the circuit; 0 disables
+    pub cooldown_secs: u64,     // how long an open circuit rejects before probing
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown_secs: 30,
+        }
+    }
+}
+
+// Returned instead of running a function whose circuit is open
+#[derive(Debug, thiserror::Error)]
+#[error("Circuit open for {function}; retry in {}s", retry_after.as_secs().max(1))]
+pub struct CircuitOpen {
+    pub function: String,
+    pub retry_after: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CircuitState {
+    Closed,
+    Open { until: Instant },
+    HalfOpen { probe_started: Instant }, // one probe allowed through
+}
+
+#[derive(Debug)]
+struct Circuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitStatus {
+    pub state: &'static str, // "closed", "open" or "half_open"
+    pub consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+}
+
+// Per-function circuit breakers, so a function failing every invocation
+// stops tying up pool capacity. After `failure_threshold` consecutive
+// failures the circuit opens and calls are rejected for `cooldown_secs`;
+// the next call is then let through as a probe, and its outcome decides
+// whether the circuit closes or opens again.
+pub struct CircuitBreakers {
+    circuits: Mutex<HashMap<String, Circuit>>,
+    failure_threshold: u32,
+    cooldown: Duration,
+}
+
+impl CircuitBreakers {
+    pub fn new(config: &BreakerConfig) -> Self {
+        Self {
+            circuits: Mutex::new(HashMap::new()),
+            failure_threshold: config.failure_threshold,
+            cooldown: Duration::from_secs(config.cooldown_secs),
+        }
+    }
+
+    // Whether a call may proceed. A probe that never reports back (e.g. the
+    // caller went away) stops blocking others after another cooldown.
+    pub fn check(&self, function: &str) -> Result<(), CircuitOpen> {
+        let mut circuits = self.circuits.lock();
+        let Some(circuit) = circuits.get_mut(function) else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        match circuit.state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::Open { until } if now < until => Err(CircuitOpen {
+                function: function.to_string(),
+                retry_after: until - now,
+            }),
+            CircuitState::HalfOpen { probe_started } if now < probe_started + self.cooldown => {
+                Err(CircuitOpen {
+                    function: function.to_string(),
+                    retry_after: probe_started + self.cooldown - now,
+                })
+            }
+            CircuitState::Open { .. } | CircuitState::HalfOpen { .. } => {
+                circuit.state = CircuitState::HalfOpen { probe_started: now };
+                Ok(())
+            }
+        }
+    }
+
+    pub fn record(&self, function: &str, success: bool) {
+        if self.failure_threshold == 0 {
+            return;
+        }
+
+        let mut circuits = self.circuits.lock();
+        if success {
+            circuits.remove(function);
+            return;
+        }
+
+        let circuit = circuits.entry(function.to_string()).or_insert(Circuit {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+        });
+        circuit.consecutive_failures += 1;
+
+        let probe_failed = matches!(circuit.state, CircuitState::HalfOpen { .. });
+        if probe_failed || circuit.consecutive_failures >= self.failure_threshold {
+            circuit.state = CircuitState::Open {
+                until: Instant::now() + self.cooldown,
+            };
+        }
+    }
+
+    pub fn status(&self, function: &str) -> CircuitStatus {
+        let circuits = self.circuits.lock();
+        let Some(circuit) = circuits.get(function) else {
+            return CircuitStatus {
+                state: "closed",
+                consecutive_failures: 0,
+                retry_after_secs: None,
+            };
+        };
+
+        let (state, retry_after_secs) = match circuit.state {
+            CircuitState::Closed => ("closed", None),
+            CircuitState::Open { until } => {
+                let remaining = until.saturating_duration_since(Instant::now());
+                ("open", Some(remaining.as_secs()))
+            }
+            CircuitState::HalfOpen { .. } => ("half_open", None),
+        };
+
+        CircuitStatus {
+            state,
+            consecutive_failures: circuit.consecutive_failures,
+            retry_after_secs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_opens_and_recovers() {
+        let breakers = CircuitBreakers::new(&BreakerConfig {
+            failure_threshold: 2,
+            cooldown_secs: 0,
+        });
+
+        breakers.record("f", false);
+        assert!(breakers.check("f").is_ok());
+        assert_eq!(breakers.status("f").state, "closed");
+
+        breakers.record("f", false);
+        assert_eq!(breakers.status("f").state, "open");
+
+        // Zero cooldown: the next call is the probe, and a failed probe
+        // reopens the circuit immediately
+        assert!(breakers.check("f").is_ok());
+        assert_eq!(breakers.status("f").state, "half_open");
+        breakers.record("f", false);
+        assert_eq!(breakers.status("f").state, "open");
+
+        assert!(breakers.check("f").is_ok());
+        breakers.record("f", true);
+        assert_eq!(breakers.status("f").state, "closed");
+        assert_eq!(breakers.status("f").consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_open_circuit_rejects() {
+        let breakers = CircuitBreakers::new(&BreakerConfig {
+            failure_threshold: 1,
+            cooldown_secs: 60,
+        });
+
+        breakers.record("f", false);
+        let rejected = breakers.check("f").unwrap_err();
+        assert!(rejected.retry_after > Duration::from_secs(59));
+
+        // Other functions are unaffected
+        assert!(breakers.check("g").is_ok());
+    }
+}
@@ -0,0 +1,55 @@
+// Build metadata for GET /version. Anything that can't be determined (e.g.
+// building from a source tarball without git) is reported as "unknown".
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    // Docker builds don't copy .git in, so the commit can be passed instead
+    let git_commit = std::env::var("HYPERDRIVE_GIT_COMMIT")
+        .ok()
+        .filter(|commit| !commit.is_empty())
+        .or_else(|| command_output("git", &["rev-parse", "--short=12", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_string());
+
+    // SOURCE_DATE_EPOCH keeps reproducible builds reproducible
+    let build_timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs())
+        });
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=HYPERDRIVE_GIT_COMMIT={}", git_commit);
+    println!("cargo:rustc-env=HYPERDRIVE_BUILD_TIMESTAMP={}", build_timestamp);
+    println!("cargo:rustc-env=HYPERDRIVE_RUSTC_VERSION={}", rustc_version);
+
+    // Only the server side is served; clients generate their own stubs
+    #[cfg(feature = "grpc")]
+    tonic_prost_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/hyperdrive.proto"], &["proto"])
+        .expect("Failed to compile proto/hyperdrive.proto");
+
+    println!("cargo:rerun-if-env-changed=HYPERDRIVE_GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    // Cargo reruns every build for paths that don't exist
+    for path in [".git/HEAD", ".git/refs"] {
+        if std::path::Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.trim().to_string()).filter(|s| !s.is_empty())
+}
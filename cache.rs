@@ -0,0 +1,281 @@
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::types::Function;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub ttl_secs: u64, // 0 disables caching
+    pub max_entries: usize,
+    // Cap on the serialized size of all cached results; unset bounds the
+    // cache by entry count alone
+    pub max_bytes: Option<usize>,
+    pub sweep_interval_secs: u64, // how often expired results are dropped
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 60,
+            max_entries: 10_000,
+            max_bytes: Some(64 * 1024 * 1024),
+            sweep_interval_secs: 30,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn enabled(&self) -> bool {
+        self.ttl_secs > 0 && self.max_entries > 0
+    }
+
+    pub fn sweep_interval(&self) -> Duration {
+        Duration::from_secs(self.sweep_interval_secs)
+    }
+}
+
+// Identifies one invocation of one function version. Keyed on the whole
+// payload rather than a hash of it, so two payloads can never collide and
+// be served each other's results.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    function: String, // affinity key, so new versions never see old results
+    payload: String,  // serialized with object keys sorted, so canonical
+}
+
+impl CacheKey {
+    pub fn new(function: &Function, payload: &serde_json::Value) -> Self {
+        Self {
+            function: function.affinity_key(),
+            payload: payload.to_string(),
+        }
+    }
+}
+
+struct CachedResult {
+    result: serde_json::Value,
+    bytes: usize, // serialized size, counted against max_bytes
+    expires_at: Instant,
+    last_used: u64, // CacheState::clock at the last hit or insert
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, CachedResult>,
+    bytes: usize,
+    clock: u64, // ticks on every use, ordering entries by recency
+}
+
+// Results of idempotent functions, reused until they expire. Bounded by
+// entry count and total size: when full, expired results go first, then
+// the least recently used. A background sweep (see `sweep`) drops expired
+// results that are never looked up again.
+pub struct ResultCache {
+    state: Mutex<CacheState>,
+    ttl: Duration,
+    max_entries: usize,
+    max_bytes: Option<usize>,
+}
+
+impl ResultCache {
+    pub fn new(config: &CacheConfig) -> Self {
+        Self {
+            state: Mutex::new(CacheState::default()),
+            ttl: Duration::from_secs(config.ttl_secs),
+            max_entries: config.max_entries,
+            max_bytes: config.max_bytes,
+        }
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<serde_json::Value> {
+        let mut state = self.state.lock();
+        state.clock += 1;
+        let clock = state.clock;
+        match state.entries.get_mut(key) {
+            Some(cached) if cached.expires_at > Instant::now() => {
+                cached.last_used = clock;
+                Some(cached.result.clone())
+            }
+            Some(_) => {
+                state.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    // Results bigger than max_bytes on their own aren't cached
+    pub fn insert(&self, key: CacheKey, result: serde_json::Value) {
+        if self.ttl.is_zero() || self.max_entries == 0 {
+            return;
+        }
+        let bytes = result.to_string().len();
+        if self.max_bytes.is_some_and(|max| bytes > max) {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut state = self.state.lock();
+        state.remove(&key);
+        let full = |state: &CacheState| {
+            state.entries.len() >= self.max_entries || self.max_bytes.is_some_and(|max| state.bytes + bytes > max)
+        };
+        if full(&state) {
+            state.sweep(now);
+        }
+        while full(&state) {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => state.remove(&oldest),
+                None => break,
+            }
+        }
+
+        state.clock += 1;
+        state.bytes += bytes;
+        let last_used = state.clock;
+        state.entries.insert(
+            key,
+            CachedResult {
+                result,
+                bytes,
+                expires_at: now + self.ttl,
+                last_used,
+            },
+        );
+    }
+
+    // Drop expired results, returning how many were dropped
+    pub fn sweep(&self) -> usize {
+        self.state.lock().sweep(Instant::now())
+    }
+
+    pub fn count(&self) -> usize {
+        self.state.lock().entries.len()
+    }
+
+    // Serialized size of everything cached
+    pub fn bytes(&self) -> usize {
+        self.state.lock().bytes
+    }
+}
+
+impl CacheState {
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(cached) = self.entries.remove(key) {
+            self.bytes -= cached.bytes;
+        }
+    }
+
+    fn sweep(&mut self, now: Instant) -> usize {
+        let before = self.entries.len();
+        let mut freed = 0;
+        self.entries.retain(|_, cached| {
+            let live = cached.expires_at > now;
+            if !live {
+                freed += cached.bytes;
+            }
+            live
+        });
+        self.bytes -= freed;
+        before - self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(version: u32) -> Function {
+        Function {
+            version,
+            idempotent: true,
+            ..Function::fixture("pure", "export default function handler(event) { return event; }")
+        }
+    }
+
+    #[test]
+    fn test_result_cache() {
+        let cache = ResultCache::new(&CacheConfig {
+            ttl_secs: 60,
+            max_entries: 2,
+            ..Default::default()
+        });
+        let payload = serde_json::json!({ "n": 1 });
+
+        let key = CacheKey::new(&function(1), &payload);
+        assert!(cache.get(&key).is_none());
+        cache.insert(key.clone(), serde_json::json!(2));
+        assert_eq!(cache.get(&key), Some(serde_json::json!(2)));
+
+        // Same payload against a new version is a different entry
+        let v2 = CacheKey::new(&function(2), &payload);
+        assert!(cache.get(&v2).is_none());
+
+        // Key order doesn't matter, but every value does
+        let reordered = serde_json::json!({ "b": 1, "a": 2 });
+        let key = CacheKey::new(&function(1), &serde_json::json!({ "a": 2, "b": 1 }));
+        assert_eq!(CacheKey::new(&function(1), &reordered), key);
+        assert_ne!(CacheKey::new(&function(1), &serde_json::json!({ "a": 2, "b": 2 })), key);
+
+        // Capacity is enforced
+        cache.insert(v2, serde_json::json!(3));
+        cache.insert(CacheKey::new(&function(1), &serde_json::json!({ "n": 2 })), serde_json::json!(4));
+        assert_eq!(cache.count(), 2);
+    }
+
+    #[test]
+    fn test_result_cache_eviction() {
+        let key = |n: u32| CacheKey::new(&function(1), &serde_json::json!({ "n": n }));
+        let cache = ResultCache::new(&CacheConfig {
+            ttl_secs: 60,
+            max_entries: 2,
+            max_bytes: Some(10),
+            ..Default::default()
+        });
+
+        // The least recently used entry is evicted, not the oldest insert
+        cache.insert(key(1), serde_json::json!("a"));
+        cache.insert(key(2), serde_json::json!("b"));
+        assert!(cache.get(&key(1)).is_some());
+        cache.insert(key(3), serde_json::json!("c"));
+        assert!(cache.get(&key(1)).is_some());
+        assert!(cache.get(&key(2)).is_none());
+        assert_eq!(cache.bytes(), 6);
+
+        // Total size is bounded too, and oversized results are never cached
+        cache.insert(key(4), serde_json::json!("1234567"));
+        assert_eq!((cache.count(), cache.bytes()), (1, 9));
+        cache.insert(key(5), serde_json::json!("123456789"));
+        assert!(cache.get(&key(5)).is_none());
+
+        // Expired results are swept without being looked up
+        let cache = ResultCache::new(&CacheConfig {
+            ttl_secs: 60,
+            ..Default::default()
+        });
+        cache.insert(key(1), serde_json::json!(1));
+        cache.state.lock().entries.values_mut().for_each(|cached| cached.expires_at = Instant::now());
+        assert_eq!(cache.sweep(), 1);
+        assert_eq!(cache.count(), 0);
+        assert_eq!(cache.bytes(), 0);
+    }
+
+    #[test]
+    fn test_disabled_cache() {
+        let cache = ResultCache::new(&CacheConfig {
+            ttl_secs: 0,
+            ..Default::default()
+        });
+        let key = CacheKey::new(&function(1), &serde_json::json!(null));
+        cache.insert(key.clone(), serde_json::json!(1));
+        assert!(cache.get(&key).is_none());
+    }
+}
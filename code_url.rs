@@ -0,0 +1,165 @@
+use anyhow::Result;
+use reqwest::{redirect, Url};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Redirects followed per fetch; each hop must also be on the allowlist
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CodeUrlConfig {
+    // Exact hosts or `*.example.com` wildcards; empty disables `code_url`
+    pub allowed_hosts: Vec<String>,
+    pub timeout_secs: u64,
+    pub max_bytes: usize,
+}
+
+impl Default for CodeUrlConfig {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: Vec::new(),
+            timeout_secs: 10,
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CodeFetchError {
+    #[error("code_url is disabled; no hosts are allowed")]
+    Disabled,
+    #[error("Invalid code_url: {0}")]
+    InvalidUrl(String),
+    #[error("code_url host is not allowed: {0}")]
+    HostNotAllowed(String),
+    #[error("Code at code_url exceeds {0} bytes")]
+    TooLarge(usize),
+    #[error("Failed to fetch code_url: {0}")]
+    Fetch(String),
+}
+
+// Fetches function code from artifact stores so CI pipelines don't have to
+// inline large bundles in the create request
+pub struct CodeFetcher {
+    client: reqwest::Client,
+    allowed_hosts: Arc<Vec<String>>,
+    max_bytes: usize,
+}
+
+impl CodeFetcher {
+    pub fn new(config: &CodeUrlConfig) -> Result<Self> {
+        let allowed_hosts: Arc<Vec<String>> =
+            Arc::new(config.allowed_hosts.iter().map(|h| h.trim().to_lowercase()).collect());
+
+        let redirect_hosts = allowed_hosts.clone();
+        let policy = redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if host_allowed(&redirect_hosts, attempt.url()) {
+                attempt.follow()
+            } else {
+                let host = attempt.url().host_str().unwrap_or_default().to_string();
+                attempt.error(format!("redirect to disallowed host {}", host))
+            }
+        });
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .redirect(policy)
+            .build()?;
+
+        Ok(Self {
+            client,
+            allowed_hosts,
+            max_bytes: config.max_bytes,
+        })
+    }
+
+    pub async fn fetch(&self, url: &str) -> Result<String, CodeFetchError> {
+        if self.allowed_hosts.is_empty() {
+            return Err(CodeFetchError::Disabled);
+        }
+
+        let url = Url::parse(url).map_err(|e| CodeFetchError::InvalidUrl(e.to_string()))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(CodeFetchError::InvalidUrl("scheme must be http or https".to_string()));
+        }
+        if !host_allowed(&self.allowed_hosts, &url) {
+            return Err(CodeFetchError::HostNotAllowed(url.host_str().unwrap_or_default().to_string()));
+        }
+
+        let fetch_error = |e: reqwest::Error| CodeFetchError::Fetch(e.to_string());
+        let mut response = self.client.get(url).send().await.map_err(fetch_error)?;
+        if !response.status().is_success() {
+            return Err(CodeFetchError::Fetch(format!("server returned {}", response.status())));
+        }
+
+        if response.content_length().is_some_and(|len| len > self.max_bytes as u64) {
+            return Err(CodeFetchError::TooLarge(self.max_bytes));
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(fetch_error)? {
+            if body.len() + chunk.len() > self.max_bytes {
+                return Err(CodeFetchError::TooLarge(self.max_bytes));
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        String::from_utf8(body).map_err(|_| CodeFetchError::Fetch("code is not valid UTF-8".to_string()))
+    }
+}
+
+fn host_allowed(allowed_hosts: &[String], url: &Url) -> bool {
+    let Some(host) = url.host_str().map(|h| h.to_lowercase()) else {
+        return false;
+    };
+
+    allowed_hosts.iter().any(|allowed| match allowed.strip_prefix("*.") {
+        Some(domain) => host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.')),
+        None => host == *allowed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_allowlist() {
+        let allowed = vec!["artifacts.example.com".to_string(), "*.cdn.example.net".to_string()];
+        let check = |url: &str| host_allowed(&allowed, &Url::parse(url).unwrap());
+
+        assert!(check("https://artifacts.example.com/build/fn.js"));
+        assert!(check("https://ARTIFACTS.example.com/fn.js"));
+        assert!(check("https://eu.cdn.example.net/fn.js"));
+        assert!(!check("https://cdn.example.net/fn.js"));
+        assert!(!check("https://evilcdn.example.net/fn.js"));
+        assert!(!check("https://artifacts.example.com.evil.io/fn.js"));
+        assert!(!check("http://10.0.0.1/fn.js"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rejects_before_connecting() {
+        let disabled = CodeFetcher::new(&CodeUrlConfig::default()).unwrap();
+        assert!(matches!(
+            disabled.fetch("https://artifacts.example.com/fn.js").await,
+            Err(CodeFetchError::Disabled)
+        ));
+
+        let fetcher = CodeFetcher::new(&CodeUrlConfig {
+            allowed_hosts: vec!["artifacts.example.com".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(matches!(
+            fetcher.fetch("https://other.example.com/fn.js").await,
+            Err(CodeFetchError::HostNotAllowed(_))
+        ));
+        assert!(matches!(
+            fetcher.fetch("file:///etc/passwd").await,
+            Err(CodeFetchError::InvalidUrl(_))
+        ));
+    }
+}
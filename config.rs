@@ -0,0 +1,660 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::audit::AuditConfig;
+use crate::breaker::BreakerConfig;
+use crate::cache::CacheConfig;
+use crate::code_url::CodeUrlConfig;
+use crate::cors::CorsSettings;
+use crate::ip_access::TrustedProxies;
+use crate::output_policy::{OutputPolicy, OutputPolicyConfig};
+use crate::ratelimit::RateLimitConfig;
+use crate::signing::SigningConfig;
+use crate::telemetry::TelemetryConfig;
+use crate::function::{DEFAULT_FORBIDDEN_MODULES, DEFAULT_FORBIDDEN_PATTERNS, DEFAULT_MAX_VERSIONS};
+use crate::types::{LargeIntegers, VmConfig};
+
+// Application configuration.
+//
+// Values are layered: built-in defaults, then the config file (TOML or YAML,
+// from `--config <path>` or `HYPERDRIVE_CONFIG`), then environment variables.
+// Environment variables use the `HYPERDRIVE_` prefix and `__` between
+// sections, e.g. `HYPERDRIVE_POOL__MAX_VMS=20` or
+// `HYPERDRIVE_CORS__ALLOWED_ORIGINS=https://a.example,https://b.example`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub pool: PoolConfig,
+    pub vm: VmConfig,
+    pub timeouts: TimeoutConfig,
+    pub invoke: InvokeConfig,
+    pub functions: FunctionsConfig,
+    pub cors: CorsSettings,
+    pub audit: AuditConfig,
+    pub cache: CacheConfig,
+    pub breaker: BreakerConfig,
+    pub rate_limit: RateLimitConfig,
+    pub telemetry: TelemetryConfig,
+    pub signing: SigningConfig,
+    pub output_policy: OutputPolicyConfig,
+    pub admin: AdminConfig,
+    pub code_url: CodeUrlConfig,
+    pub execution: ExecutionConfig,
+}
+
+// The subset of settings that can change on SIGHUP without a restart
+#[derive(Debug, Clone)]
+pub struct Tunables {
+    pub pool: PoolConfig,
+    pub timeouts: TimeoutConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    // CIDR blocks of reverse proxies whose X-Forwarded-For is believed when
+    // checking a function's IP rules; other peers are taken at their word
+    pub trusted_proxies: Vec<String>,
+    // Where the gRPC API listens, alongside HTTP; needs the grpc feature
+    pub grpc_address: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "0.0.0.0:8090".to_string(),
+            trusted_proxies: Vec::new(),
+            grpc_address: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PoolConfig {
+    pub min_vms: usize, // kept warm
+    pub max_vms: usize,
+    pub affinity: bool, // prefer VMs that already ran the function
+    pub max_invocations_per_vm: u64, // recycle after this many; 0 = never
+    pub vm_concurrency: usize, // invocations a VM runs at once; >1 needs a pipelining V8 host
+    pub saturation_threshold: f64, // busy fraction of max_vms that warns when sustained
+    pub saturation_window_secs: u64,
+    pub boot_backoff_initial_ms: u64, // wait after the first failed boot, doubling per failure
+    pub boot_backoff_max_secs: u64,
+    pub idle_ping_interval_secs: u64, // health-ping idle VMs this often; 0 = never
+    pub failed_vm_retention_secs: u64, // how long failed VMs stay listed
+    pub max_waiting: usize, // callers queued for a full pool before new ones get PoolExhausted
+    pub max_concurrent_boots: usize, // VMs booting at once; the rest wait their turn
+    pub premium_reserved_vms: usize, // warm VMs held back for premium-tier functions
+    // Size the warm pool from recent demand rather than min_vms alone: a
+    // smoothed average of busy VMs times the margin, kept within
+    // min_vms..=max_vms
+    pub predictive_warming: bool,
+    pub predictive_half_life_secs: u64, // how fast the forecast forgets old traffic
+    pub predictive_margin: f64, // headroom over the forecast; 1.25 warms 25% extra
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            min_vms: 2,
+            max_vms: 10,
+            affinity: true,
+            max_invocations_per_vm: 0,
+            vm_concurrency: 1,
+            saturation_threshold: 0.9,
+            saturation_window_secs: 30,
+            boot_backoff_initial_ms: 500,
+            boot_backoff_max_secs: 60,
+            idle_ping_interval_secs: 60,
+            failed_vm_retention_secs: 3600,
+            max_waiting: 256,
+            max_concurrent_boots: 4,
+            premium_reserved_vms: 0,
+            predictive_warming: false,
+            predictive_half_life_secs: 60,
+            predictive_margin: 1.25,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    pub acquire_timeout_secs: u64,
+    pub execution_timeout_secs: u64,
+    pub max_invocation_timeout_secs: u64, // cap on caller-supplied deadlines
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            acquire_timeout_secs: 10,
+            execution_timeout_secs: 30,
+            max_invocation_timeout_secs: 60,
+        }
+    }
+}
+
+impl PoolConfig {
+    pub fn saturation_window(&self) -> Duration {
+        Duration::from_secs(self.saturation_window_secs)
+    }
+
+    pub fn predictive_half_life(&self) -> Duration {
+        Duration::from_secs(self.predictive_half_life_secs)
+    }
+
+    pub fn boot_backoff_initial(&self) -> Duration {
+        Duration::from_millis(self.boot_backoff_initial_ms)
+    }
+
+    pub fn boot_backoff_max(&self) -> Duration {
+        Duration::from_secs(self.boot_backoff_max_secs)
+    }
+
+    pub fn idle_ping_interval(&self) -> Option<Duration> {
+        (self.idle_ping_interval_secs > 0).then(|| Duration::from_secs(self.idle_ping_interval_secs))
+    }
+
+    pub fn failed_vm_retention(&self) -> Duration {
+        Duration::from_secs(self.failed_vm_retention_secs)
+    }
+
+    // Invocations the pool can run at once when fully scaled out
+    pub fn capacity(&self) -> usize {
+        self.max_vms * self.vm_concurrency
+    }
+}
+
+impl TimeoutConfig {
+    pub fn acquire_timeout(&self) -> Duration {
+        Duration::from_secs(self.acquire_timeout_secs)
+    }
+
+    pub fn execution_timeout(&self) -> Duration {
+        Duration::from_secs(self.execution_timeout_secs)
+    }
+
+    pub fn max_invocation_timeout(&self) -> Duration {
+        Duration::from_secs(self.max_invocation_timeout_secs)
+    }
+}
+
+// serde_json's recursion limit when parsing
+const MAX_JSON_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct InvokeConfig {
+    pub max_payload_bytes: usize,
+    pub max_batch_bytes: usize, // whole batch request body
+    pub max_stream_bytes: usize, // raw bodies streamed through to the V8 host
+    pub max_response_bytes: usize, // buffered function results
+    // Array/object nesting allowed in JSON payloads. serde_json refuses
+    // anything past 128 while parsing, so that's the ceiling.
+    pub max_json_depth: usize,
+    // JSON payload integers the V8 host can't hold exactly: "pass" (the
+    // default) lets it round them, "string" sends their digits as a string
+    // and "reject" fails the invocation
+    pub large_integers: LargeIntegers,
+}
+
+impl Default for InvokeConfig {
+    fn default() -> Self {
+        Self {
+            max_payload_bytes: 1024 * 1024,
+            max_batch_bytes: 8 * 1024 * 1024,
+            max_stream_bytes: 256 * 1024 * 1024,
+            max_response_bytes: 16 * 1024 * 1024,
+            max_json_depth: 64,
+            large_integers: LargeIntegers::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct FunctionsConfig {
+    pub max_versions: usize, // retained per function
+    // Validation policy: tighten for untrusted code (e.g. add "crypto"),
+    // loosen for trusted internal code (e.g. drop "fs")
+    pub forbidden_modules: Vec<String>,
+    pub forbidden_patterns: Vec<String>,
+    pub require_default_export: bool,
+    // Reject code with a longer line, the mark of a minified bundle; unset
+    // only warns about long lines on create
+    pub max_line_bytes: Option<usize>,
+    pub max_import_bytes: usize, // whole import request body
+    pub quotas: Vec<FunctionQuota>,
+    // Cap on functions stored across all namespaces; `eviction` decides
+    // what a create at the cap does
+    pub max_functions: Option<usize>,
+    pub eviction: FunctionEviction,
+    // How long a deleted function sits in the recycle bin before it's purged
+    // for good; 0 makes every delete a hard delete. The bin holds at most
+    // max_functions deletions.
+    pub deleted_retention_secs: u64,
+}
+
+// What creating a function does once functions.max_functions is reached
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FunctionEviction {
+    #[default]
+    Reject, // refuse the create; nothing is deleted behind the operator's back
+    Lru, // delete the least recently invoked function to make room
+}
+
+// Caps for one tenant on a shared instance. The prefix is matched against
+// "namespace/name", so "acme/" covers a namespace and "default/acme-" a
+// naming convention. A function counts toward every quota it matches.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FunctionQuota {
+    pub prefix: String,
+    pub max_functions: Option<usize>,
+    pub max_code_bytes: Option<usize>, // latest version of each function
+}
+
+impl Default for FunctionsConfig {
+    fn default() -> Self {
+        Self {
+            max_versions: DEFAULT_MAX_VERSIONS,
+            forbidden_modules: DEFAULT_FORBIDDEN_MODULES.iter().map(|m| m.to_string()).collect(),
+            forbidden_patterns: DEFAULT_FORBIDDEN_PATTERNS.iter().map(|p| p.to_string()).collect(),
+            require_default_export: true,
+            max_line_bytes: None,
+            max_import_bytes: 64 * 1024 * 1024,
+            quotas: Vec::new(),
+            max_functions: None,
+            eviction: FunctionEviction::default(),
+            deleted_retention_secs: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl FunctionsConfig {
+    pub fn deleted_retention(&self) -> Option<Duration> {
+        (self.deleted_retention_secs > 0).then(|| Duration::from_secs(self.deleted_retention_secs))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ExecutionConfig {
+    pub mode: ExecutionMode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionMode {
+    #[default]
+    Vm,
+    // In-process on an embedded engine, with NO isolation between functions
+    // and the server. Development only; needs the `local-runtime` feature.
+    Local,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    // Bearer token for /api/v1/admin endpoints; unset disables them
+    pub token: Option<String>,
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let mut builder = config::Config::builder();
+
+        if let Some(path) = config_path() {
+            if !Path::new(&path).exists() {
+                return Err(anyhow::anyhow!("Config file not found: {}", path));
+            }
+            builder = builder.add_source(config::File::from(Path::new(&path)));
+        }
+
+        let config: Config = builder
+            .add_source(
+                config::Environment::with_prefix("HYPERDRIVE")
+                    .prefix_separator("_")
+                    .separator("__")
+                    .list_separator(",")
+                    .with_list_parse_key("cors.allowed_origins")
+                    .with_list_parse_key("cors.allowed_methods")
+                    .with_list_parse_key("cors.allowed_headers")
+                    .with_list_parse_key("functions.forbidden_modules")
+                    .with_list_parse_key("functions.forbidden_patterns")
+                    .with_list_parse_key("code_url.allowed_hosts")
+                    .try_parsing(true),
+            )
+            .build()
+            .context("Failed to read configuration")?
+            .try_deserialize()
+            .context("Failed to parse configuration")?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.bind_address()?;
+        if self.grpc_address()?.is_some() && !cfg!(feature = "grpc") {
+            return Err(anyhow::anyhow!("server.grpc_address requires building with the grpc feature"));
+        }
+        TrustedProxies::new(&self.server.trusted_proxies)
+            .map_err(|e| anyhow::anyhow!("Invalid server.trusted_proxies: {}", e))?;
+
+        if self.pool.max_vms == 0 {
+            return Err(anyhow::anyhow!("pool.max_vms must be at least 1"));
+        }
+
+        if self.pool.min_vms > self.pool.max_vms {
+            return Err(anyhow::anyhow!(
+                "pool.min_vms ({}) cannot exceed pool.max_vms ({})",
+                self.pool.min_vms,
+                self.pool.max_vms
+            ));
+        }
+
+        // Standard functions keep at least one VM to themselves
+        if self.pool.premium_reserved_vms >= self.pool.max_vms {
+            return Err(anyhow::anyhow!(
+                "pool.premium_reserved_vms ({}) must be less than pool.max_vms ({})",
+                self.pool.premium_reserved_vms,
+                self.pool.max_vms
+            ));
+        }
+
+        if self.pool.max_concurrent_boots == 0 {
+            return Err(anyhow::anyhow!("pool.max_concurrent_boots must be at least 1"));
+        }
+
+        if self.pool.vm_concurrency == 0 {
+            return Err(anyhow::anyhow!("pool.vm_concurrency must be at least 1"));
+        }
+
+        if !(self.pool.saturation_threshold > 0.0 && self.pool.saturation_threshold <= 1.0) {
+            return Err(anyhow::anyhow!("pool.saturation_threshold must be in (0, 1]"));
+        }
+
+        if self.pool.predictive_half_life_secs == 0 {
+            return Err(anyhow::anyhow!("pool.predictive_half_life_secs must be greater than zero"));
+        }
+
+        if !(1.0..).contains(&self.pool.predictive_margin) {
+            return Err(anyhow::anyhow!("pool.predictive_margin must be at least 1.0"));
+        }
+
+        if self.pool.saturation_window_secs == 0 {
+            return Err(anyhow::anyhow!("pool.saturation_window_secs must be greater than zero"));
+        }
+
+        if self.pool.boot_backoff_initial_ms == 0 || self.pool.boot_backoff_initial() > self.pool.boot_backoff_max() {
+            return Err(anyhow::anyhow!(
+                "pool.boot_backoff_initial_ms must be greater than zero and no more than pool.boot_backoff_max_secs"
+            ));
+        }
+
+        if self.vm.vcpu_count == 0 {
+            return Err(anyhow::anyhow!("vm.vcpu_count must be at least 1"));
+        }
+
+        if self.vm.mem_size_mib < 32 {
+            return Err(anyhow::anyhow!("vm.mem_size_mib must be at least 32"));
+        }
+
+        if self.vm.port_range_start == 0 || self.vm.port_range_start > self.vm.port_range_end {
+            return Err(anyhow::anyhow!(
+                "vm.port_range_start ({}) must be non-zero and not above vm.port_range_end ({})",
+                self.vm.port_range_start,
+                self.vm.port_range_end
+            ));
+        }
+
+        for (key, path) in [
+            ("vm.kernel_path", &self.vm.kernel_path),
+            ("vm.rootfs_path", &self.vm.rootfs_path),
+            ("vm.v8_host_path", &self.vm.v8_host_path),
+            ("vm.work_dir_base", &self.vm.work_dir_base),
+        ] {
+            if path.is_empty() {
+                return Err(anyhow::anyhow!("{} cannot be empty", key));
+            }
+        }
+
+        if self.timeouts.acquire_timeout_secs == 0
+            || self.timeouts.execution_timeout_secs == 0
+            || self.timeouts.max_invocation_timeout_secs == 0
+        {
+            return Err(anyhow::anyhow!("timeouts must be greater than zero"));
+        }
+
+        if self.invoke.max_payload_bytes == 0
+            || self.invoke.max_batch_bytes == 0
+            || self.invoke.max_stream_bytes == 0
+            || self.invoke.max_response_bytes == 0
+        {
+            return Err(anyhow::anyhow!("invoke payload limits must be greater than zero"));
+        }
+
+        if !(1..=MAX_JSON_DEPTH).contains(&self.invoke.max_json_depth) {
+            return Err(anyhow::anyhow!("invoke.max_json_depth must be between 1 and {}", MAX_JSON_DEPTH));
+        }
+
+        if self.functions.max_line_bytes == Some(0) {
+            return Err(anyhow::anyhow!("functions.max_line_bytes must be greater than zero"));
+        }
+
+        if self.functions.max_import_bytes == 0 {
+            return Err(anyhow::anyhow!("functions.max_import_bytes must be greater than zero"));
+        }
+
+        if self.cache.sweep_interval_secs == 0 || self.cache.max_bytes == Some(0) {
+            return Err(anyhow::anyhow!(
+                "cache.sweep_interval_secs and cache.max_bytes must be greater than zero"
+            ));
+        }
+
+        if self.admin.token.as_deref().is_some_and(|token| token.trim().is_empty()) {
+            return Err(anyhow::anyhow!("admin.token cannot be empty; unset it to disable the admin API"));
+        }
+
+        if self.code_url.timeout_secs == 0 || self.code_url.max_bytes == 0 {
+            return Err(anyhow::anyhow!("code_url timeout and size limit must be greater than zero"));
+        }
+
+        if self.execution.mode == ExecutionMode::Local && !cfg!(feature = "local-runtime") {
+            return Err(anyhow::anyhow!(
+                "execution.mode = \"local\" requires building with the local-runtime feature"
+            ));
+        }
+
+        if self.functions.max_functions == Some(0) {
+            return Err(anyhow::anyhow!("functions.max_functions must be greater than zero"));
+        }
+
+        if self.functions.max_versions == 0 {
+            return Err(anyhow::anyhow!("functions.max_versions must be at least 1"));
+        }
+
+        if self.telemetry.otlp_endpoint.as_deref().is_some_and(|endpoint| endpoint.trim().is_empty()) {
+            return Err(anyhow::anyhow!("telemetry.otlp_endpoint cannot be empty; unset it to disable export"));
+        }
+
+        if self.signing.key.as_deref().is_some_and(str::is_empty) {
+            return Err(anyhow::anyhow!("signing.key cannot be empty; unset it to disable signing"));
+        }
+        if self.signing.all_functions && self.signing.key.is_none() {
+            return Err(anyhow::anyhow!("signing.all_functions requires signing.key"));
+        }
+
+        OutputPolicy::new(&self.output_policy).context("Invalid output_policy.redact path")?;
+        if self.output_policy.max_result_bytes == Some(0) {
+            return Err(anyhow::anyhow!("output_policy.max_result_bytes must be greater than zero"));
+        }
+
+        if self.rate_limit.invocations > 0 && self.rate_limit.window_secs == 0 {
+            return Err(anyhow::anyhow!("rate_limit.window_secs must be greater than zero"));
+        }
+
+        if self.functions.quotas.iter().any(|quota| quota.prefix.is_empty()) {
+            return Err(anyhow::anyhow!("functions.quotas entries need a non-empty prefix"));
+        }
+
+        // Surfaces bad origins/methods/headers at startup
+        let _ = self.cors.layer().context("Invalid cors settings")?;
+
+        Ok(())
+    }
+
+    pub fn tunables(&self) -> Tunables {
+        Tunables {
+            pool: self.pool.clone(),
+            timeouts: self.timeouts.clone(),
+        }
+    }
+
+    // Settings that differ in `new` but only take effect after a restart
+    pub fn restart_required_changes(&self, new: &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.server != new.server {
+            changed.push("server");
+        }
+        // Boot settings roll the pool to a new generation instead
+        if self.vm.with_boot_settings(&new.vm) != new.vm {
+            changed.push("vm");
+        }
+        if self.invoke != new.invoke {
+            changed.push("invoke");
+        }
+        if self.functions != new.functions {
+            changed.push("functions");
+        }
+        if self.cors != new.cors {
+            changed.push("cors");
+        }
+        if self.audit != new.audit {
+            changed.push("audit");
+        }
+        if self.cache != new.cache {
+            changed.push("cache");
+        }
+        if self.breaker != new.breaker {
+            changed.push("breaker");
+        }
+        if self.rate_limit != new.rate_limit {
+            changed.push("rate_limit");
+        }
+        if self.telemetry != new.telemetry {
+            changed.push("telemetry");
+        }
+        if self.signing != new.signing {
+            changed.push("signing");
+        }
+        if self.output_policy != new.output_policy {
+            changed.push("output_policy");
+        }
+        if self.admin != new.admin {
+            changed.push("admin");
+        }
+        if self.code_url != new.code_url {
+            changed.push("code_url");
+        }
+        if self.execution != new.execution {
+            changed.push("execution");
+        }
+        changed
+    }
+
+    pub fn bind_address(&self) -> Result<SocketAddr> {
+        self.server
+            .bind_address
+            .parse()
+            .with_context(|| format!("Invalid server.bind_address: {}", self.server.bind_address))
+    }
+
+    pub fn grpc_address(&self) -> Result<Option<SocketAddr>> {
+        let Some(address) = &self.server.grpc_address else {
+            return Ok(None);
+        };
+        let address = address
+            .parse()
+            .with_context(|| format!("Invalid server.grpc_address: {}", address))?;
+        Ok(Some(address))
+    }
+}
+
+// `--config <path>` / `--config=<path>` take precedence over HYPERDRIVE_CONFIG
+fn config_path() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next();
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(path.to_string());
+        }
+    }
+
+    std::env::var("HYPERDRIVE_CONFIG").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config_is_valid() {
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let mut config = Config::default();
+        config.pool.min_vms = 5;
+        config.pool.max_vms = 2;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.server.bind_address = "not-an-address".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.server.grpc_address = Some("not-an-address".to_string());
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.timeouts.execution_timeout_secs = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.invoke.max_json_depth = MAX_JSON_DEPTH + 1;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.vm.port_range_start = 9000;
+        config.vm.port_range_end = 8000;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_restart_required_changes() {
+        let config = Config::default();
+
+        // A new image rolls the pool instead of needing a restart
+        let mut new = Config::default();
+        new.vm.rootfs_path = "/opt/firecracker/rootfs-v2.ext4".to_string();
+        new.vm.port_range_start += 1000;
+        new.vm.port_range_end += 1000;
+        assert!(config.restart_required_changes(&new).is_empty());
+
+        new.vm.work_dir_base = "/var/lib/hyperdrive".to_string();
+        new.cache.ttl_secs = 5;
+        assert_eq!(config.restart_required_changes(&new), ["vm", "cache"]);
+    }
+}
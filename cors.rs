@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use serde::Deserialize;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+// CORS policy for browser-based clients, configured under `[cors]`.
+//
+// Cross-origin requests are refused unless origins are configured:
+//
+//   [cors]
+//   allowed_origins = ["https://app.example.com"]   # or ["*"] for any origin
+//   allowed_methods = ["GET", "POST"]                # default: GET, POST, PUT, DELETE
+//   allowed_headers = ["content-type", "authorization"]
+//
+// or via environment, comma separated:
+//
+//   HYPERDRIVE_CORS__ALLOWED_ORIGINS="https://app.example.com,https://admin.example.com"
+//
+// Preflight OPTIONS requests are answered by the layer itself, so routes
+// don't need OPTIONS handlers.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CorsSettings {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+}
+
+impl Default for CorsSettings {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec![
+                "GET".to_string(),
+                "POST".to_string(),
+                "PUT".to_string(),
+                "DELETE".to_string(),
+            ],
+            allowed_headers: vec![
+                "content-type".to_string(),
+                "authorization".to_string(),
+                "x-invocation-deadline".to_string(),
+                "x-priority".to_string(),
+            ],
+        }
+    }
+}
+
+impl CorsSettings {
+    pub fn layer(&self) -> Result<CorsLayer> {
+        let origins = if self.allowed_origins.iter().any(|o| o.trim() == "*") {
+            AllowOrigin::any()
+        } else {
+            let origins = self
+                .allowed_origins
+                .iter()
+                .map(|o| HeaderValue::from_str(o.trim()).with_context(|| format!("Invalid CORS origin: {}", o)))
+                .collect::<Result<Vec<_>>>()?;
+            AllowOrigin::list(origins)
+        };
+
+        let methods = self
+            .allowed_methods
+            .iter()
+            .map(|m| {
+                Method::from_bytes(m.trim().to_uppercase().as_bytes())
+                    .with_context(|| format!("Invalid CORS method: {}", m))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let headers = self
+            .allowed_headers
+            .iter()
+            .map(|h| HeaderName::from_bytes(h.trim().as_bytes()).with_context(|| format!("Invalid CORS header: {}", h)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .expose_headers([
+                HeaderName::from_static("x-function-version"),
+                HeaderName::from_static("x-cold-start"),
+                HeaderName::from_static("x-cache"),
+                HeaderName::from_static("x-peak-memory-bytes"),
+                HeaderName::from_static("x-cpu-time-ms"),
+                HeaderName::from_static("x-ratelimit-limit"),
+                HeaderName::from_static("x-ratelimit-remaining"),
+                HeaderName::from_static("x-ratelimit-reset"),
+                HeaderName::from_static("x-signature"),
+                HeaderName::from_static("x-invocation-id"),
+                header::RETRY_AFTER,
+                header::ETAG,
+                header::ALLOW,
+                header::LOCATION,
+            ]))
+    }
+}
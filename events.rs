@@ -0,0 +1,81 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::types::VmState;
+
+// Events buffered per subscriber before slow ones start missing events
+const EVENT_BUFFER: usize = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlatformEvent {
+    FunctionCreated {
+        namespace: String,
+        name: String,
+        version: u32,
+    },
+    FunctionUpdated {
+        namespace: String,
+        name: String,
+        version: u32,
+    },
+    FunctionDeleted {
+        namespace: String,
+        name: String,
+    },
+    VmStateChanged {
+        vm_id: Uuid,
+        state: VmState,
+    },
+    Invocation {
+        namespace: String,
+        function: String,
+        version: u32,
+        success: bool,
+        duration_ms: u64,
+    },
+}
+
+impl PlatformEvent {
+    pub const KINDS: &'static [&'static str] = &[
+        "function_created",
+        "function_updated",
+        "function_deleted",
+        "vm_state_changed",
+        "invocation",
+    ];
+
+    // Matches the serialized `type` tag
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PlatformEvent::FunctionCreated { .. } => "function_created",
+            PlatformEvent::FunctionUpdated { .. } => "function_updated",
+            PlatformEvent::FunctionDeleted { .. } => "function_deleted",
+            PlatformEvent::VmStateChanged { .. } => "vm_state_changed",
+            PlatformEvent::Invocation { .. } => "invocation",
+        }
+    }
+}
+
+// Fan-out of platform events to live subscribers. Publishing never blocks
+// and events published with no subscribers are dropped.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<PlatformEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        Self { sender }
+    }
+
+    pub fn publish(&self, event: PlatformEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PlatformEvent> {
+        self.sender.subscribe()
+    }
+}
@@ -1,154 +1,858 @@
 use anyhow::Result;
-use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use regex::Regex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::OnceLock;
 use tokio::sync::RwLock;
 use tracing::{info, warn};
 
-use crate::types::{CreateFunctionRequest, Function};
+use crate::config::{FunctionEviction, FunctionQuota, FunctionsConfig};
+use crate::events::{EventBus, PlatformEvent};
+use crate::ip_access::IpRules;
+use crate::scheduler;
+use crate::transform::Transform;
+use crate::runtimes::Runtimes;
+use crate::types::{
+    CreateFunctionRequest, DeletedFunction, ExportedFunction, Function, FunctionReadiness, ImportItemResult, ImportOutcome,
+    InputSchema, VersionWeight,
+};
 
+// Namespace for functions created without one
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+// Versions retained per function unless configured otherwise
+pub const DEFAULT_MAX_VERSIONS: usize = 10;
+
+// Modules functions may not import, matched after normalizing quotes,
+// whitespace, `node:` prefixes and subpaths (`fs/promises` counts as `fs`)
+pub const DEFAULT_FORBIDDEN_MODULES: &[&str] = &[
+    "fs",
+    "child_process",
+    "net",
+    "dgram",
+    "cluster",
+    "worker_threads",
+];
+
+// Substrings that have no business in a sandboxed handler
+pub const DEFAULT_FORBIDDEN_PATTERNS: &[&str] = &["process.exit", "__dirname", "__filename"];
+
+// Largest function source accepted on create
+pub const MAX_CODE_BYTES: usize = 1024 * 1024;
+
+// Lines longer than this get a lint warning; hand-written code rarely
+// comes close, minified bundles blow far past it
+const LONG_LINE_WARN_BYTES: usize = 1000;
+
+// Tag limits keep labels organizational rather than a place to stash data
+const MAX_TAGS: usize = 32;
+const MAX_TAG_KEY_LEN: usize = 64;
+const MAX_TAG_VALUE_LEN: usize = 256;
+
+// Machine-readable reason a function definition was rejected, returned as
+// `code` next to the human-readable message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationErrorKind {
+    EmptyName,
+    NameTooLong,
+    InvalidNameChars,
+    InvalidNamespace,
+    EmptyCode,
+    InvalidCodeSource,
+    CodeTooLarge,
+    UnsupportedRuntime,
+    MissingDefaultExport,
+    ForbiddenModule,
+    ForbiddenPattern,
+    InvalidTags,
+    InvalidSchedule,
+    InvalidPayloadLimit,
+    InvalidInputSchema,
+    InvalidTransform,
+    InvalidIpRule,
+    LineTooLong,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct ValidationError {
+    pub kind: ValidationErrorKind,
+    pub message: String,
+}
+
+// A clone refused because its target name is taken
+#[derive(Debug, thiserror::Error)]
+#[error("Function {0} already exists")]
+pub struct AlreadyExists(pub String);
+
+// A create refused because the tenant is at its quota
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct QuotaExceeded(pub String);
+
+pub(crate) fn invalid(kind: ValidationErrorKind, message: impl Into<String>) -> anyhow::Error {
+    ValidationError {
+        kind,
+        message: message.into(),
+    }
+    .into()
+}
+
+// Functions keyed by namespace, then name
 pub struct FunctionStore {
-    functions: RwLock<HashMap<String, Function>>,
+    functions: RwLock<HashMap<String, HashMap<String, FunctionVersions>>>,
+    max_versions: usize,
+    runtimes: Runtimes,
+    max_payload_bytes: Option<usize>, // ceiling for per-function payload limits
+    quotas: Vec<FunctionQuota>,
+    max_functions: Option<usize>,
+    eviction: FunctionEviction,
+    // The recycle bin: soft-deleted functions keyed by (namespace, name)
+    deleted: RwLock<HashMap<(String, String), RecycledFunction>>,
+    deleted_retention: Option<chrono::Duration>, // None when deletes are always hard
+    events: Option<EventBus>,
+}
+
+// A function in the recycle bin, kept whole so a restore brings back every
+// retained version and the traffic split
+struct RecycledFunction {
+    versions: FunctionVersions,
+    deleted_at: DateTime<Utc>,
+}
+
+// Retained versions of a single function, oldest first
+struct FunctionVersions {
+    versions: VecDeque<Function>,
+    next_version: u32,
+    traffic_split: Vec<VersionWeight>,
+}
+
+impl FunctionVersions {
+    fn new() -> Self {
+        Self {
+            versions: VecDeque::new(),
+            next_version: 1,
+            traffic_split: Vec::new(),
+        }
+    }
+
+    fn latest(&self) -> Option<&Function> {
+        self.versions.back()
+    }
+
+    fn get(&self, version: u32) -> Option<&Function> {
+        self.versions.iter().find(|f| f.version == version)
+    }
 }
 
 impl FunctionStore {
     pub fn new() -> Self {
+        Self::with_config(&FunctionsConfig::default())
+    }
+
+    pub fn with_config(config: &FunctionsConfig) -> Self {
         Self {
             functions: RwLock::new(HashMap::new()),
+            max_versions: config.max_versions.max(1),
+            runtimes: Runtimes::with_config(config),
+            max_payload_bytes: None,
+            quotas: config.quotas.clone(),
+            max_functions: config.max_functions,
+            eviction: config.eviction,
+            deleted: RwLock::new(HashMap::new()),
+            deleted_retention: config
+                .deleted_retention()
+                .map(|retention| chrono::Duration::from_std(retention).unwrap_or(chrono::Duration::MAX)),
+            events: None,
         }
     }
 
-    pub async fn create(&self, request: CreateFunctionRequest) -> Result<Function> {
-        // Validate function
-        self.validate_function(&request)?;
+    // Publish function lifecycle events to the bus
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
 
-        let function = Function {
-            name: request.name.clone(),
-            code: request.code,
-            runtime: request.runtime,
-            created_at: chrono::Utc::now().to_rfc3339(),
-        };
+    // Reject per-function payload limits above the server-wide one
+    pub fn with_max_payload_bytes(mut self, max_payload_bytes: usize) -> Self {
+        self.max_payload_bytes = Some(max_payload_bytes);
+        self
+    }
 
-        // Store function
-        {
-            let mut functions = self.functions.write().await;
-            functions.insert(request.name.clone(), function.clone());
+    fn publish(&self, event: PlatformEvent) {
+        if let Some(events) = &self.events {
+            events.publish(event);
         }
+    }
 
-        info!("Created function: {}", request.name);
+    pub async fn create(&self, namespace: &str, request: CreateFunctionRequest) -> Result<Function> {
+        self.insert(namespace, request, true).await
+    }
+
+    // Create a short-lived function outside functions.max_functions and the
+    // quotas, e.g. the self-test canary, so it can neither be refused at the
+    // cap nor evict someone's function to make room
+    pub async fn create_unmetered(&self, namespace: &str, request: CreateFunctionRequest) -> Result<Function> {
+        self.insert(namespace, request, false).await
+    }
+
+    async fn insert(&self, namespace: &str, request: CreateFunctionRequest, metered: bool) -> Result<Function> {
+        // Validate function
+        validate_namespace(namespace)?;
+        let code = self.validate_function(&request)?;
+
+        let name = request.name.clone();
+        let function = self.push_version(namespace, &name, request, code, metered).await?;
+
+        info!("Created function: {}/{} (version {})", namespace, name, function.version);
+        self.publish(PlatformEvent::FunctionCreated {
+            namespace: namespace.to_string(),
+            name,
+            version: function.version,
+        });
         Ok(function)
     }
 
-    pub async fn get(&self, name: &str) -> Option<Function> {
+    // Latest version of the function
+    pub async fn get(&self, namespace: &str, name: &str) -> Option<Function> {
+        let functions = self.functions.read().await;
+        lookup(&functions, namespace, name).and_then(|v| v.latest()).cloned()
+    }
+
+    pub async fn get_version(&self, namespace: &str, name: &str, version: u32) -> Option<Function> {
         let functions = self.functions.read().await;
-        functions.get(name).cloned()
+        lookup(&functions, namespace, name).and_then(|v| v.get(version)).cloned()
     }
 
-    pub async fn list(&self) -> Vec<Function> {
+    // All retained versions of the function, oldest first
+    pub async fn list_versions(&self, namespace: &str, name: &str) -> Option<Vec<Function>> {
         let functions = self.functions.read().await;
-        functions.values().cloned().collect()
+        lookup(&functions, namespace, name).map(|v| v.versions.iter().cloned().collect())
     }
 
-    pub async fn delete(&self, name: &str) -> Result<bool> {
+    // Version to serve for an unpinned invocation: weighted by the traffic
+    // split when one is set, otherwise the latest
+    pub async fn resolve(&self, namespace: &str, name: &str) -> Option<Function> {
+        let functions = self.functions.read().await;
+        let entry = lookup(&functions, namespace, name)?;
+
+        if let Some(version) = choose_weighted(&entry.traffic_split) {
+            match entry.get(version) {
+                Some(function) => return Some(function.clone()),
+                None => warn!(
+                    "Traffic split for {}/{} references evicted version {}, using latest",
+                    namespace, name, version
+                ),
+            }
+        }
+
+        entry.latest().cloned()
+    }
+
+    pub async fn get_traffic_split(&self, namespace: &str, name: &str) -> Option<Vec<VersionWeight>> {
+        let functions = self.functions.read().await;
+        lookup(&functions, namespace, name).map(|v| v.traffic_split.clone())
+    }
+
+    // Replace the traffic split as a whole; an empty split routes everything
+    // to the latest version
+    pub async fn set_traffic_split(
+        &self,
+        namespace: &str,
+        name: &str,
+        weights: Vec<VersionWeight>,
+    ) -> Result<()> {
         let mut functions = self.functions.write().await;
-        match functions.remove(name) {
-            Some(_) => {
-                info!("Deleted function: {}", name);
-                Ok(true)
+        let entry = functions
+            .get_mut(namespace)
+            .and_then(|namespaced| namespaced.get_mut(name))
+            .ok_or_else(|| anyhow::anyhow!("Function not found: {}/{}", namespace, name))?;
+
+        for weight in &weights {
+            if entry.get(weight.version).is_none() {
+                return Err(anyhow::anyhow!("Unknown version {} for function {}", weight.version, name));
             }
-            None => {
-                warn!("Attempted to delete non-existent function: {}", name);
-                Ok(false)
+        }
+
+        if !weights.is_empty() && weights.iter().all(|w| w.weight == 0) {
+            return Err(anyhow::anyhow!("Traffic split weights cannot all be zero"));
+        }
+
+        entry.traffic_split = weights;
+        info!("Updated traffic split for function: {}/{}", namespace, name);
+        Ok(())
+    }
+
+    // Latest version of every function in the namespace
+    pub async fn list(&self, namespace: &str) -> Vec<Function> {
+        let functions = self.functions.read().await;
+        functions
+            .get(namespace)
+            .into_iter()
+            .flat_map(|namespaced| namespaced.values())
+            .filter_map(|v| v.latest())
+            .cloned()
+            .collect()
+    }
+
+    // Latest version of every enabled function, across namespaces, that
+    // runs on a schedule
+    pub async fn list_scheduled(&self) -> Vec<Function> {
+        let functions = self.functions.read().await;
+        functions
+            .values()
+            .flat_map(|namespaced| namespaced.values())
+            .filter_map(|v| v.latest())
+            .filter(|f| f.enabled && f.schedule.is_some())
+            .cloned()
+            .collect()
+    }
+
+    // Latest version of every function in the namespace whose latest version
+    // carries the tag
+    pub async fn list_tagged(&self, namespace: &str, key: &str, value: &str) -> Vec<Function> {
+        let functions = self.functions.read().await;
+        functions
+            .get(namespace)
+            .into_iter()
+            .flat_map(|namespaced| namespaced.values())
+            .filter_map(|v| v.latest())
+            .filter(|f| f.tags.get(key).map(String::as_str) == Some(value))
+            .cloned()
+            .collect()
+    }
+
+    // Latest version of every function in the namespace as it was submitted,
+    // so TypeScript functions round-trip as TypeScript
+    pub async fn export(&self, namespace: &str) -> Vec<ExportedFunction> {
+        let mut exported: Vec<ExportedFunction> = self
+            .list(namespace)
+            .await
+            .into_iter()
+            .map(|function| ExportedFunction {
+                invocation_count: function.counters.invocations(),
+                error_count: function.counters.errors(),
+                function: as_submitted(function),
+            })
+            .collect();
+        exported.sort_by(|a, b| a.function.name.cmp(&b.function.name));
+        exported
+    }
+
+    // Copy the latest version of `name` to a new function called `target`.
+    // The source is read and the copy stored under one write lock, so an
+    // update landing mid-clone can't mix versions. Counters and traffic
+    // splits start fresh. None if the source doesn't exist.
+    pub async fn clone_function(&self, namespace: &str, name: &str, target: &str) -> Result<Option<Function>> {
+        let mut functions = self.functions.write().await;
+        let Some(source) = lookup(&functions, namespace, name).and_then(|v| v.latest()).cloned() else {
+            return Ok(None);
+        };
+        if lookup(&functions, namespace, target).is_some() {
+            return Err(AlreadyExists(format!("{}/{}", namespace, target)).into());
+        }
+
+        let request = CreateFunctionRequest {
+            name: target.to_string(),
+            ..as_submitted(source)
+        };
+        let code = self.validate_function(&request)?;
+        let function = self.store_version(&mut functions, namespace, target, request, code, true)?;
+
+        info!("Cloned function {}/{} to {}", namespace, name, target);
+        self.publish(PlatformEvent::FunctionCreated {
+            namespace: namespace.to_string(),
+            name: target.to_string(),
+            version: function.version,
+        });
+        Ok(Some(function))
+    }
+
+    // Create each function independently so one bad entry doesn't sink the
+    // rest. Existing functions are left alone unless `overwrite` is set, in
+    // which case the import becomes their latest version. Exported counts
+    // only seed functions the import creates; existing ones keep their own.
+    pub async fn import(
+        &self,
+        namespace: &str,
+        items: Vec<ExportedFunction>,
+        overwrite: bool,
+    ) -> Vec<ImportItemResult> {
+        let mut results = Vec::with_capacity(items.len());
+
+        for item in items {
+            let name = item.function.name.clone();
+            let exists = self.get(namespace, &name).await.is_some();
+            let outcome = if exists && !overwrite {
+                ImportOutcome::Skipped {
+                    reason: "Function already exists".to_string(),
+                }
+            } else {
+                match self.create(namespace, item.function).await {
+                    Ok(function) => {
+                        if !exists {
+                            function.counters.add(item.invocation_count, item.error_count);
+                        }
+                        ImportOutcome::Imported {
+                            version: function.version,
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Failed to import function {}/{}: {}", namespace, name, e);
+                        ImportOutcome::Error { error: e.to_string() }
+                    }
+                }
+            };
+            results.push(ImportItemResult { name, outcome });
+        }
+
+        results
+    }
+
+    // The version stored with exactly this code, across every namespace.
+    // When several match, an enabled one wins, then the most recent.
+    pub async fn find_by_hash(&self, hash: &str) -> Option<Function> {
+        let functions = self.functions.read().await;
+        functions
+            .values()
+            .flat_map(|namespaced| namespaced.values())
+            .flat_map(|versions| versions.versions.iter())
+            .filter(|f| f.code_hash.eq_ignore_ascii_case(hash))
+            .max_by_key(|f| (f.enabled, f.updated_at))
+            .cloned()
+    }
+
+    // Take a function out of service, or put it back, without touching its
+    // versions. New versions inherit the setting. Returns the latest version.
+    pub async fn set_enabled(&self, namespace: &str, name: &str, enabled: bool) -> Option<Function> {
+        let mut functions = self.functions.write().await;
+        let entry = functions.get_mut(namespace)?.get_mut(name)?;
+        for version in entry.versions.iter_mut() {
+            version.enabled = enabled;
+        }
+
+        let state = if enabled { "Enabled" } else { "Disabled" };
+        info!("{} function: {}/{}", state, namespace, name);
+        entry.latest().cloned()
+    }
+
+    // Remove a function for good, along with any copy in the recycle bin
+    pub async fn delete(&self, namespace: &str, name: &str) -> Result<bool> {
+        let mut functions = self.functions.write().await;
+        let removed = take(&mut functions, namespace, name).is_some();
+        let key = (namespace.to_string(), name.to_string());
+        let purged = self.deleted.write().await.remove(&key).is_some();
+
+        if removed {
+            info!("Deleted function: {}/{}", namespace, name);
+            self.publish(PlatformEvent::FunctionDeleted {
+                namespace: namespace.to_string(),
+                name: name.to_string(),
+            });
+        } else if purged {
+            info!("Purged deleted function: {}/{}", namespace, name);
+        } else {
+            warn!("Attempted to delete non-existent function: {}/{}", namespace, name);
+        }
+        Ok(removed || purged)
+    }
+
+    // Move a function to the recycle bin, from where it can be restored
+    // until the retention window passes. Replaces an earlier deletion of
+    // the same name. A hard delete when the bin is disabled. The bin holds
+    // at most functions.max_functions, purging the oldest deletion first.
+    pub async fn soft_delete(&self, namespace: &str, name: &str) -> Result<bool> {
+        if self.deleted_retention.is_none() {
+            return self.delete(namespace, name).await;
+        }
+
+        let mut functions = self.functions.write().await;
+        let Some(versions) = take(&mut functions, namespace, name) else {
+            warn!("Attempted to delete non-existent function: {}/{}", namespace, name);
+            return Ok(false);
+        };
+        let recycled = RecycledFunction {
+            versions,
+            deleted_at: Utc::now(),
+        };
+        let mut deleted = self.deleted.write().await;
+        deleted.insert((namespace.to_string(), name.to_string()), recycled);
+        if let Some(max) = self.max_functions {
+            while deleted.len() > max {
+                let Some((ns, n)) = deleted
+                    .iter()
+                    .min_by_key(|(_, recycled)| recycled.deleted_at)
+                    .map(|(key, _)| key.clone())
+                else {
+                    break;
+                };
+                deleted.remove(&(ns.clone(), n.clone()));
+                info!("Purged deleted function {}/{} to keep the recycle bin within {}", ns, n, max);
             }
         }
+
+        info!("Moved function to the recycle bin: {}/{}", namespace, name);
+        self.publish(PlatformEvent::FunctionDeleted {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+        });
+        Ok(true)
     }
 
-    pub async fn update(&self, name: &str, request: CreateFunctionRequest) -> Result<Function> {
+    pub async fn list_deleted(&self, namespace: &str) -> Vec<DeletedFunction> {
+        let deleted = self.deleted.read().await;
+        deleted
+            .iter()
+            .filter(|((ns, _), _)| ns == namespace)
+            .filter_map(|((ns, name), recycled)| {
+                Some(DeletedFunction {
+                    namespace: ns.clone(),
+                    name: name.clone(),
+                    version: recycled.versions.latest()?.version,
+                    deleted_at: recycled.deleted_at,
+                    purge_at: self.purge_at(recycled),
+                })
+            })
+            .collect()
+    }
+
+    // Bring a function back out of the recycle bin. Refused if the name has
+    // been reused since, or if the function no longer fits the quotas.
+    // Returns the latest version, or None if there's nothing to restore.
+    pub async fn restore(&self, namespace: &str, name: &str) -> Result<Option<Function>> {
+        let mut functions = self.functions.write().await;
+        let mut deleted = self.deleted.write().await;
+        let key = (namespace.to_string(), name.to_string());
+        let Some(latest) = deleted.get(&key).and_then(|r| r.versions.latest()).cloned() else {
+            return Ok(None);
+        };
+        if lookup(&functions, namespace, name).is_some() {
+            return Err(AlreadyExists(format!("{}/{}", namespace, name)).into());
+        }
+
+        self.check_quotas(&functions, namespace, name, latest.code.len())?;
+        self.make_room(&mut functions, namespace, name)?;
+        if let Some(recycled) = deleted.remove(&key) {
+            functions
+                .entry(namespace.to_string())
+                .or_default()
+                .insert(name.to_string(), recycled.versions);
+        }
+
+        info!("Restored function: {}/{} (version {})", namespace, name, latest.version);
+        self.publish(PlatformEvent::FunctionCreated {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            version: latest.version,
+        });
+        Ok(Some(latest))
+    }
+
+    // Drop recycle bin entries past the retention window. Returns how many
+    // went.
+    pub async fn purge_deleted(&self) -> usize {
+        let now = Utc::now();
+        let mut deleted = self.deleted.write().await;
+        let before = deleted.len();
+        deleted.retain(|(namespace, name), recycled| {
+            let keep = self.purge_at(recycled) > now;
+            if !keep {
+                info!("Purged deleted function: {}/{}", namespace, name);
+            }
+            keep
+        });
+        before - deleted.len()
+    }
+
+    fn purge_at(&self, recycled: &RecycledFunction) -> DateTime<Utc> {
+        self.deleted_retention
+            .and_then(|retention| recycled.deleted_at.checked_add_signed(retention))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+
+    pub async fn update(&self, namespace: &str, name: &str, request: CreateFunctionRequest) -> Result<Function> {
         // Validate function
-        self.validate_function(&request)?;
+        validate_namespace(namespace)?;
+        let code = self.validate_function(&request)?;
+
+        let function = self.push_version(namespace, name, request, code, true).await?;
+
+        info!("Updated function: {}/{} (version {})", namespace, name, function.version);
+        self.publish(PlatformEvent::FunctionUpdated {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            version: function.version,
+        });
+        Ok(function)
+    }
+
+    // Store the request as a new immutable version, evicting the oldest
+    // versions beyond the retention cap. Unless `metered` is false, the
+    // store's cap and quotas are enforced first.
+    async fn push_version(
+        &self,
+        namespace: &str,
+        name: &str,
+        request: CreateFunctionRequest,
+        code: String,
+        metered: bool,
+    ) -> Result<Function> {
+        let mut functions = self.functions.write().await;
+        self.store_version(&mut functions, namespace, name, request, code, metered)
+    }
+
+    // push_version for callers already holding the write lock
+    fn store_version(
+        &self,
+        functions: &mut HashMap<String, HashMap<String, FunctionVersions>>,
+        namespace: &str,
+        name: &str,
+        request: CreateFunctionRequest,
+        code: String,
+        metered: bool,
+    ) -> Result<Function> {
+        let input_schema = request
+            .input_schema
+            .map(InputSchema::compile)
+            .transpose()
+            .map_err(|e| invalid(ValidationErrorKind::InvalidInputSchema, format!("Invalid input_schema: {}", e)))?;
+        let transform = |template: Option<serde_json::Value>, field: &str| {
+            template
+                .map(Transform::compile)
+                .transpose()
+                .map_err(|e| invalid(ValidationErrorKind::InvalidTransform, format!("Invalid {}: {}", field, e)))
+        };
+        let input_transform = transform(request.input_transform, "input_transform")?;
+        let output_transform = transform(request.output_transform, "output_transform")?;
+        let ip_rules = IpRules::parse(&request.allowed_ips, &request.denied_ips)
+            .map_err(|e| invalid(ValidationErrorKind::InvalidIpRule, e))?;
+        // Checked under the write lock so concurrent creates can't both
+        // squeeze under a cap
+        if metered {
+            self.check_quotas(functions, namespace, name, code.len())?;
+            self.make_room(functions, namespace, name)?;
+        }
+        let entry = functions
+            .entry(namespace.to_string())
+            .or_default()
+            .entry(name.to_string())
+            .or_insert_with(FunctionVersions::new);
+
+        // Keep the original source only when it differs from what runs
+        let code_hash = code_hash(&request.code);
+        let source = (code != request.code).then_some(request.code);
+
+        let now = chrono::Utc::now();
+        let created_at = entry.latest().map_or(now, |f| f.created_at);
+        let enabled = entry.latest().is_none_or(|f| f.enabled);
+        let counters = entry.latest().map(|f| f.counters.clone()).unwrap_or_default();
 
         let function = Function {
+            namespace: namespace.to_string(),
             name: name.to_string(),
-            code: request.code,
+            version: entry.next_version,
+            code,
+            source,
+            code_hash,
             runtime: request.runtime,
-            created_at: chrono::Utc::now().to_rfc3339(),
+            audit_payloads: request.audit_payloads,
+            http_response: request.http_response,
+            idempotent: request.idempotent,
+            sign_responses: request.sign_responses,
+            tier: request.tier,
+            max_priority: request.max_priority,
+            debug: request.debug,
+            tags: request.tags,
+            schedule: request.schedule,
+            schedule_payload: request.schedule_payload,
+            max_payload_bytes: request.max_payload_bytes,
+            input_schema,
+            input_transform,
+            output_transform,
+            ip_rules,
+            enabled,
+            status: FunctionReadiness::default(),
+            counters,
+            created_at,
+            updated_at: now,
         };
 
-        // Update function
-        {
-            let mut functions = self.functions.write().await;
-            functions.insert(name.to_string(), function.clone());
+        entry.next_version += 1;
+        entry.versions.push_back(function.clone());
+        while entry.versions.len() > self.max_versions {
+            entry.versions.pop_front();
         }
 
-        info!("Updated function: {}", name);
         Ok(function)
     }
 
-    fn validate_function(&self, request: &CreateFunctionRequest) -> Result<()> {
+    // Refuse a version that would take a quota covering the function past
+    // its cap. A new version of an existing function replaces that
+    // function's code rather than adding to it.
+    fn check_quotas(
+        &self,
+        functions: &HashMap<String, HashMap<String, FunctionVersions>>,
+        namespace: &str,
+        name: &str,
+        code_bytes: usize,
+    ) -> Result<()> {
+        let qualified_name = format!("{}/{}", namespace, name);
+        for quota in self.quotas.iter().filter(|q| qualified_name.starts_with(&q.prefix)) {
+            let mut count = 1;
+            let mut total_bytes = code_bytes;
+            let mut exists = false;
+            for (ns, namespaced) in functions {
+                for (n, versions) in namespaced {
+                    if ns == namespace && n == name {
+                        exists = true;
+                    } else if format!("{}/{}", ns, n).starts_with(&quota.prefix) {
+                        count += 1;
+                        total_bytes += versions.latest().map_or(0, |f| f.code.len());
+                    }
+                }
+            }
+
+            if let Some(max) = quota.max_functions.filter(|&max| !exists && count > max) {
+                return Err(QuotaExceeded(format!(
+                    "Quota for '{}' allows at most {} functions",
+                    quota.prefix, max
+                ))
+                .into());
+            }
+            if let Some(max) = quota.max_code_bytes.filter(|&max| total_bytes > max) {
+                return Err(QuotaExceeded(format!(
+                    "Quota for '{}' allows at most {} bytes of code; this function would bring it to {}",
+                    quota.prefix, max, total_bytes
+                ))
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    // Hold the store to functions.max_functions. A new function at the cap
+    // is refused, or with LRU eviction the function invoked longest ago
+    // (going by its last deploy if it never was) is deleted to make room.
+    fn make_room(
+        &self,
+        functions: &mut HashMap<String, HashMap<String, FunctionVersions>>,
+        namespace: &str,
+        name: &str,
+    ) -> Result<()> {
+        let Some(max) = self.max_functions else {
+            return Ok(());
+        };
+        if lookup(functions, namespace, name).is_some() {
+            return Ok(());
+        }
+
+        while functions.values().map(HashMap::len).sum::<usize>() >= max {
+            if self.eviction == FunctionEviction::Reject {
+                return Err(QuotaExceeded(format!("This instance allows at most {} functions", max)).into());
+            }
+            let Some((_, ns, n)) = functions
+                .iter()
+                .flat_map(|(ns, namespaced)| namespaced.iter().map(move |(n, versions)| (ns, n, versions)))
+                .filter_map(|(ns, n, versions)| {
+                    let latest = versions.latest()?;
+                    let invoked = latest.counters.last_invoked().unwrap_or(latest.updated_at);
+                    Some((invoked.max(latest.updated_at), ns.clone(), n.clone()))
+                })
+                .min()
+            else {
+                break;
+            };
+
+            take(functions, &ns, &n);
+            warn!("Evicted function {}/{} to stay within {} functions", ns, n, max);
+            self.publish(PlatformEvent::FunctionDeleted { namespace: ns, name: n });
+        }
+        Ok(())
+    }
+
+    // Returns the JavaScript the V8 host will execute
+    fn validate_function(&self, request: &CreateFunctionRequest) -> Result<String> {
         // Validate name
         if request.name.is_empty() {
-            return Err(anyhow::anyhow!("Function name cannot be empty"));
+            return Err(invalid(ValidationErrorKind::EmptyName, "Function name cannot be empty"));
         }
 
         if !request.name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
-            return Err(anyhow::anyhow!("Function name can only contain alphanumeric characters, hyphens, and underscores"));
+            return Err(invalid(
+                ValidationErrorKind::InvalidNameChars,
+                "Function name can only contain alphanumeric characters, hyphens, and underscores",
+            ));
         }
 
         if request.name.len() > 64 {
-            return Err(anyhow::anyhow!("Function name cannot exceed 64 characters"));
+            return Err(invalid(
+                ValidationErrorKind::NameTooLong,
+                "Function name cannot exceed 64 characters",
+            ));
         }
 
-        // Validate code
-        if request.code.is_empty() {
-            return Err(anyhow::anyhow!("Function code cannot be empty"));
+        // Validate code. The create API resolves `code_url` before this point.
+        if request.code_url.is_some() {
+            return Err(invalid(
+                ValidationErrorKind::InvalidCodeSource,
+                "code_url is not supported here; provide code inline",
+            ));
         }
 
-        if request.code.len() > 1024 * 1024 {
-            return Err(anyhow::anyhow!("Function code cannot exceed 1MB"));
+        if request.code.is_empty() {
+            return Err(invalid(ValidationErrorKind::EmptyCode, "Function code cannot be empty"));
         }
 
-        // Validate runtime
-        if request.runtime != "v8" {
-            return Err(anyhow::anyhow!("Only 'v8' runtime is currently supported"));
+        if request.code.len() > MAX_CODE_BYTES {
+            return Err(invalid(
+                ValidationErrorKind::CodeTooLarge,
+                "Function code cannot exceed 1MB",
+            ));
         }
 
-        // Basic JavaScript syntax validation
-        self.validate_javascript_syntax(&request.code)?;
-
-        Ok(())
-    }
+        validate_tags(&request.tags)?;
 
-    fn validate_javascript_syntax(&self, code: &str) -> Result<()> {
-        // Basic validation - check for export default
-        if !code.contains("export default") && !code.contains("module.exports") {
-            return Err(anyhow::anyhow!("Function must export a default function"));
+        if let Some(limit) = request.max_payload_bytes {
+            if limit == 0 {
+                return Err(invalid(
+                    ValidationErrorKind::InvalidPayloadLimit,
+                    "max_payload_bytes must be greater than zero",
+                ));
+            }
+            if let Some(max) = self.max_payload_bytes.filter(|max| limit > *max) {
+                return Err(invalid(
+                    ValidationErrorKind::InvalidPayloadLimit,
+                    format!("max_payload_bytes cannot exceed the server limit of {} bytes", max),
+                ));
+            }
         }
 
-        // Check for forbidden patterns
-        let forbidden_patterns = [
-            "require('fs')",
-            "require(\"fs\")",
-            "import fs",
-            "process.exit",
-            "__dirname",
-            "__filename",
-        ];
-
-        for pattern in &forbidden_patterns {
-            if code.contains(pattern) {
-                return Err(anyhow::anyhow!("Function contains forbidden pattern: {}", pattern));
-            }
+        if let Some(schedule) = &request.schedule {
+            scheduler::parse_schedule(schedule)
+                .map_err(|e| invalid(ValidationErrorKind::InvalidSchedule, e.to_string()))?;
         }
 
-        Ok(())
+        // Each runtime checks the code by its own rules
+        self.runtimes.validate(&request.runtime, &request.code)
     }
 
     pub async fn get_function_stats(&self) -> FunctionStats {
         let functions = self.functions.read().await;
+        let latest: Vec<&Function> = functions
+            .values()
+            .flat_map(|namespaced| namespaced.values())
+            .filter_map(|v| v.latest())
+            .collect();
         FunctionStats {
-            total_functions: functions.len(),
-            total_code_size: functions.values().map(|f| f.code.len()).sum(),
+            total_functions: latest.len(),
+            total_code_size: latest.iter().map(|f| f.code.len()).sum(),
             runtimes: {
                 let mut runtimes = HashMap::new();
-                for function in functions.values() {
+                for function in &latest {
                     *runtimes.entry(function.runtime.clone()).or_insert(0) += 1;
                 }
                 runtimes
@@ -157,6 +861,219 @@ impl FunctionStore {
     }
 }
 
+fn lookup<'a>(
+    functions: &'a HashMap<String, HashMap<String, FunctionVersions>>,
+    namespace: &str,
+    name: &str,
+) -> Option<&'a FunctionVersions> {
+    functions.get(namespace).and_then(|namespaced| namespaced.get(name))
+}
+
+// Remove a function, dropping its namespace once empty
+fn take(
+    functions: &mut HashMap<String, HashMap<String, FunctionVersions>>,
+    namespace: &str,
+    name: &str,
+) -> Option<FunctionVersions> {
+    let namespaced = functions.get_mut(namespace)?;
+    let removed = namespaced.remove(name);
+    if namespaced.is_empty() {
+        functions.remove(namespace);
+    }
+    removed
+}
+
+// Namespaces follow the same rules as function names
+fn validate_namespace(namespace: &str) -> Result<()> {
+    if namespace.is_empty() {
+        return Err(invalid(ValidationErrorKind::InvalidNamespace, "Namespace cannot be empty"));
+    }
+
+    if !namespace.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err(invalid(
+            ValidationErrorKind::InvalidNamespace,
+            "Namespace can only contain alphanumeric characters, hyphens, and underscores",
+        ));
+    }
+
+    if namespace.len() > 64 {
+        return Err(invalid(
+            ValidationErrorKind::InvalidNamespace,
+            "Namespace cannot exceed 64 characters",
+        ));
+    }
+
+    Ok(())
+}
+
+fn validate_tags(tags: &HashMap<String, String>) -> Result<()> {
+    if tags.len() > MAX_TAGS {
+        return Err(invalid(
+            ValidationErrorKind::InvalidTags,
+            format!("Functions cannot have more than {} tags", MAX_TAGS),
+        ));
+    }
+
+    for (key, value) in tags {
+        if key.is_empty() || key.len() > MAX_TAG_KEY_LEN {
+            return Err(invalid(
+                ValidationErrorKind::InvalidTags,
+                format!("Tag keys must be 1-{} characters", MAX_TAG_KEY_LEN),
+            ));
+        }
+        if key.contains(':') {
+            return Err(invalid(
+                ValidationErrorKind::InvalidTags,
+                format!("Tag key cannot contain ':': {}", key),
+            ));
+        }
+        if value.len() > MAX_TAG_VALUE_LEN {
+            return Err(invalid(
+                ValidationErrorKind::InvalidTags,
+                format!("Tag value for {} cannot exceed {} characters", key, MAX_TAG_VALUE_LEN),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// The request that would recreate this version, with the code as it was
+// submitted so TypeScript stays TypeScript
+fn as_submitted(function: Function) -> CreateFunctionRequest {
+    CreateFunctionRequest {
+        name: function.name,
+        code: function.source.unwrap_or(function.code),
+        code_url: None,
+        runtime: function.runtime,
+        audit_payloads: function.audit_payloads,
+        http_response: function.http_response,
+        idempotent: function.idempotent,
+        sign_responses: function.sign_responses,
+        tier: function.tier,
+        max_priority: function.max_priority,
+        debug: function.debug,
+        tags: function.tags,
+        schedule: function.schedule,
+        schedule_payload: function.schedule_payload,
+        max_payload_bytes: function.max_payload_bytes,
+        input_schema: function.input_schema.map(|schema| schema.schema().clone()),
+        input_transform: function.input_transform.map(|transform| transform.template().clone()),
+        output_transform: function.output_transform.map(|transform| transform.template().clone()),
+        allowed_ips: function.ip_rules.allowed(),
+        denied_ips: function.ip_rules.denied(),
+    }
+}
+
+// Lowercase hex SHA-256, as `sha256sum` prints it
+pub fn code_hash(code: &str) -> String {
+    Sha256::digest(code.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// How much of the size limit a function's source uses, returned on create
+// so authors of large bundles see the limit coming before they hit it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CodeBudget {
+    pub bytes: usize,
+    pub lines: usize,
+    pub limit_bytes: usize,
+    pub percent_used: f64, // to one decimal place
+}
+
+impl CodeBudget {
+    pub fn measure(code: &str) -> Self {
+        Self {
+            bytes: code.len(),
+            lines: code.lines().count(),
+            limit_bytes: MAX_CODE_BYTES,
+            percent_used: (code.len() as f64 * 1000.0 / MAX_CODE_BYTES as f64).round() / 10.0,
+        }
+    }
+}
+
+// Advisory findings about a function's source. Unlike validation these
+// never block a deploy; they're returned alongside a successful create.
+pub fn lint(code: &str) -> Vec<String> {
+    static DEPRECATED: OnceLock<Vec<(Regex, &str)>> = OnceLock::new();
+    static ASYNC_HANDLER: OnceLock<Regex> = OnceLock::new();
+    static ASYNC_WORK: OnceLock<Regex> = OnceLock::new();
+    static ERROR_HANDLING: OnceLock<Regex> = OnceLock::new();
+
+    let deprecated = DEPRECATED.get_or_init(|| {
+        [
+            (r"\bvar\s", "`var` declarations are discouraged; use `let` or `const`"),
+            (r"\bnew\s+Buffer\s*\(", "`new Buffer()` is deprecated; use `Buffer.from()` or `Buffer.alloc()`"),
+            (r"\barguments\.callee\b", "`arguments.callee` is deprecated and fails in strict mode"),
+            (r"\bwith\s*\(", "`with` statements are deprecated and fail in strict mode"),
+            (r"\.substr\s*\(", "`String.prototype.substr` is deprecated; use `slice`"),
+        ]
+        .iter()
+        .map(|(p, message)| (Regex::new(p).expect("valid lint pattern"), *message))
+        .collect()
+    });
+    let async_handler = ASYNC_HANDLER.get_or_init(|| {
+        Regex::new(r"(export\s+default|module\.exports\s*=)\s*async\b").expect("valid lint pattern")
+    });
+    let async_work = ASYNC_WORK
+        .get_or_init(|| Regex::new(r"\bfetch\s*\(|\.then\s*\(").expect("valid lint pattern"));
+    let error_handling = ERROR_HANDLING
+        .get_or_init(|| Regex::new(r"\btry\s*\{|\.catch\s*\(").expect("valid lint pattern"));
+
+    let mut warnings = Vec::new();
+
+    if !error_handling.is_match(code) {
+        warnings.push("No error handling detected; uncaught exceptions fail the invocation".to_string());
+    }
+
+    if async_work.is_match(code) && !async_handler.is_match(code) {
+        warnings.push(
+            "Handler looks synchronous but starts async work; declare it `async` and await results".to_string(),
+        );
+    }
+
+    for (pattern, message) in deprecated {
+        if pattern.is_match(code) {
+            warnings.push(message.to_string());
+        }
+    }
+
+    if let Some((line, bytes)) = longest_line(code).filter(|&(_, bytes)| bytes > LONG_LINE_WARN_BYTES) {
+        warnings.push(format!(
+            "Line {} is {} bytes long; minified or bundled code bloats the function and hides error locations",
+            line, bytes
+        ));
+    }
+
+    warnings
+}
+
+// The longest line's 1-based number and length in bytes
+pub(crate) fn longest_line(code: &str) -> Option<(usize, usize)> {
+    code.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.len()))
+        .max_by_key(|&(line, bytes)| (bytes, std::cmp::Reverse(line)))
+}
+
+fn choose_weighted(weights: &[VersionWeight]) -> Option<u32> {
+    let total: u32 = weights.iter().map(|w| w.weight).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut roll = rand::thread_rng().gen_range(0..total);
+    for weight in weights {
+        if roll < weight.weight {
+            return Some(weight.version);
+        }
+        roll -= weight.weight;
+    }
+    None
+}
+
 #[derive(Debug)]
 pub struct FunctionStats {
     pub total_functions: usize,
@@ -167,6 +1084,7 @@ pub struct FunctionStats {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::body_etag;
 
     #[tokio::test]
     async fn test_function_creation() {
@@ -176,12 +1094,13 @@ mod tests {
             name: "test-function".to_string(),
             code: "export default function handler(event) { return { message: 'Hello!' }; }".to_string(),
             runtime: "v8".to_string(),
+            ..Default::default()
         };
 
-        let result = store.create(request).await;
+        let result = store.create(DEFAULT_NAMESPACE, request).await;
         assert!(result.is_ok());
 
-        let function = store.get("test-function").await;
+        let function = store.get(DEFAULT_NAMESPACE, "test-function").await;
         assert!(function.is_some());
     }
 
@@ -194,24 +1113,327 @@ mod tests {
             name: "".to_string(),
             code: "export default function handler(event) { return {}; }".to_string(),
             runtime: "v8".to_string(),
+            ..Default::default()
         };
-        assert!(store.create(request).await.is_err());
+        assert!(store.create(DEFAULT_NAMESPACE, request).await.is_err());
 
         // Test invalid runtime
         let request = CreateFunctionRequest {
             name: "test".to_string(),
             code: "export default function handler(event) { return {}; }".to_string(),
             runtime: "python".to_string(),
+            ..Default::default()
         };
-        assert!(store.create(request).await.is_err());
+        assert!(store.create(DEFAULT_NAMESPACE, request).await.is_err());
 
         // Test forbidden pattern
         let request = CreateFunctionRequest {
             name: "test".to_string(),
             code: "const fs = require('fs'); export default function handler(event) { return {}; }".to_string(),
             runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        assert!(store.create(DEFAULT_NAMESPACE, request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validation_error_kinds() {
+        let store = FunctionStore::new();
+        let kind = |result: Result<Function>| {
+            result.unwrap_err().downcast_ref::<ValidationError>().map(|e| e.kind)
+        };
+
+        let request = CreateFunctionRequest {
+            name: "bad name".to_string(),
+            code: "export default function handler(event) { return {}; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(kind(store.create(DEFAULT_NAMESPACE, request).await), Some(ValidationErrorKind::InvalidNameChars));
+
+        let request = CreateFunctionRequest {
+            name: "test".to_string(),
+            code: "function handler(event) { return {}; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(kind(store.create(DEFAULT_NAMESPACE, request).await), Some(ValidationErrorKind::MissingDefaultExport));
+
+        let request = CreateFunctionRequest {
+            name: "test".to_string(),
+            code: "import fs from 'fs'; export default function handler(event) { return {}; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
         };
-        assert!(store.create(request).await.is_err());
+        assert_eq!(kind(store.create(DEFAULT_NAMESPACE, request).await), Some(ValidationErrorKind::ForbiddenModule));
+
+        let request = CreateFunctionRequest {
+            name: "test".to_string(),
+            code: "export default function handler(event) { return {}; }".to_string(),
+            runtime: "v8".to_string(),
+            schedule: Some("every five minutes".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(kind(store.create(DEFAULT_NAMESPACE, request).await), Some(ValidationErrorKind::InvalidSchedule));
+
+        let request = CreateFunctionRequest {
+            name: "test".to_string(),
+            code_url: Some("https://example.com/handler.js".to_string()),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        let rejected = store.create(DEFAULT_NAMESPACE, request).await;
+        assert_eq!(kind(rejected), Some(ValidationErrorKind::InvalidCodeSource));
+
+        assert_eq!(serde_json::to_value(ValidationErrorKind::CodeTooLarge).unwrap(), "code_too_large");
+    }
+
+    #[tokio::test]
+    async fn test_payload_limit_validation() {
+        let store = FunctionStore::new().with_max_payload_bytes(1024);
+        let request = |limit: usize| CreateFunctionRequest {
+            name: "small-inputs".to_string(),
+            code: "export default function handler(event) { return event; }".to_string(),
+            runtime: "v8".to_string(),
+            max_payload_bytes: Some(limit),
+            ..Default::default()
+        };
+
+        let function = store.create(DEFAULT_NAMESPACE, request(256)).await.unwrap();
+        assert_eq!(function.max_payload_bytes, Some(256));
+        for limit in [0, 2048] {
+            let error = store.create(DEFAULT_NAMESPACE, request(limit)).await.unwrap_err();
+            let kind = error.downcast_ref::<ValidationError>().map(|e| e.kind);
+            assert_eq!(kind, Some(ValidationErrorKind::InvalidPayloadLimit));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quotas() {
+        let store = FunctionStore::with_config(&FunctionsConfig {
+            quotas: vec![
+                FunctionQuota {
+                    prefix: "acme/".to_string(),
+                    max_functions: Some(2),
+                    max_code_bytes: None,
+                },
+                FunctionQuota {
+                    prefix: "default/big-".to_string(),
+                    max_functions: None,
+                    max_code_bytes: Some(150),
+                },
+            ],
+            ..Default::default()
+        });
+        let request = |name: &str| CreateFunctionRequest {
+            name: name.to_string(),
+            code: "export default function handler(event) { return event; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        let is_quota = |result: Result<Function>| result.unwrap_err().downcast_ref::<QuotaExceeded>().is_some();
+
+        store.create("acme", request("a")).await.unwrap();
+        store.create("acme", request("b")).await.unwrap();
+        assert!(is_quota(store.create("acme", request("c")).await));
+        // New versions of existing functions and other tenants are unaffected
+        store.create("acme", request("a")).await.unwrap();
+        store.create("other", request("c")).await.unwrap();
+
+        // Each function's latest version counts toward the code size cap
+        store.create(DEFAULT_NAMESPACE, request("big-one")).await.unwrap();
+        store.create(DEFAULT_NAMESPACE, request("big-one")).await.unwrap();
+        store.create(DEFAULT_NAMESPACE, request("big-two")).await.unwrap();
+        assert!(is_quota(store.create(DEFAULT_NAMESPACE, request("big-three")).await));
+        store.create(DEFAULT_NAMESPACE, request("small")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_max_functions() {
+        let request = |name: &str| CreateFunctionRequest {
+            name: name.to_string(),
+            code: "export default function handler(event) { return event; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        let config = |eviction| FunctionsConfig {
+            max_functions: Some(3),
+            eviction,
+            ..Default::default()
+        };
+
+        let store = FunctionStore::with_config(&config(FunctionEviction::Reject));
+        for name in ["a", "b", "c"] {
+            store.create(DEFAULT_NAMESPACE, request(name)).await.unwrap();
+        }
+        let error = store.create("other", request("d")).await.unwrap_err();
+        assert!(error.downcast_ref::<QuotaExceeded>().is_some());
+        store.update(DEFAULT_NAMESPACE, "a", request("a")).await.unwrap();
+        // Unmetered functions go in regardless
+        store.create_unmetered("canary", request("canary")).await.unwrap();
+        assert!(store.delete("canary", "canary").await.unwrap());
+
+        let store = FunctionStore::with_config(&config(FunctionEviction::Lru));
+        for name in ["a", "b", "c"] {
+            store.create(DEFAULT_NAMESPACE, request(name)).await.unwrap();
+        }
+        // Invoking `a` leaves `b` as the least recently used
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        store.get(DEFAULT_NAMESPACE, "a").await.unwrap().counters.record(true);
+        store.create("other", request("d")).await.unwrap();
+        assert!(store.get(DEFAULT_NAMESPACE, "b").await.is_none());
+        // and don't evict anyone to make room
+        store.create_unmetered("canary", request("canary")).await.unwrap();
+        for (namespace, name) in [(DEFAULT_NAMESPACE, "a"), (DEFAULT_NAMESPACE, "c"), ("other", "d")] {
+            assert!(store.get(namespace, name).await.is_some(), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recycle_bin() {
+        let request = |name: &str| CreateFunctionRequest {
+            name: name.to_string(),
+            code: "export default function handler(event) { return event; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+
+        let store = FunctionStore::new();
+        store.create(DEFAULT_NAMESPACE, request("a")).await.unwrap();
+        store.update(DEFAULT_NAMESPACE, "a", request("a")).await.unwrap();
+        assert!(store.soft_delete(DEFAULT_NAMESPACE, "a").await.unwrap());
+        assert!(store.get(DEFAULT_NAMESPACE, "a").await.is_none());
+        assert!(!store.soft_delete(DEFAULT_NAMESPACE, "a").await.unwrap());
+
+        let deleted = store.list_deleted(DEFAULT_NAMESPACE).await;
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].version, 2);
+        assert!(deleted[0].purge_at > deleted[0].deleted_at);
+        assert!(store.list_deleted("other").await.is_empty());
+
+        // A reused name blocks the restore; deleting it again replaces the
+        // earlier deletion
+        store.create(DEFAULT_NAMESPACE, request("a")).await.unwrap();
+        let error = store.restore(DEFAULT_NAMESPACE, "a").await.unwrap_err();
+        assert!(error.is::<AlreadyExists>());
+        assert!(store.soft_delete(DEFAULT_NAMESPACE, "a").await.unwrap());
+        assert_eq!(store.list_deleted(DEFAULT_NAMESPACE).await[0].version, 1);
+
+        // Restoring brings back every retained version
+        store.create(DEFAULT_NAMESPACE, request("b")).await.unwrap();
+        store.update(DEFAULT_NAMESPACE, "b", request("b")).await.unwrap();
+        store.soft_delete(DEFAULT_NAMESPACE, "b").await.unwrap();
+        assert_eq!(store.restore(DEFAULT_NAMESPACE, "b").await.unwrap().unwrap().version, 2);
+        assert!(store.get_version(DEFAULT_NAMESPACE, "b", 1).await.is_some());
+        assert!(store.restore(DEFAULT_NAMESPACE, "b").await.unwrap().is_none());
+
+        // A hard delete empties the bin too
+        assert!(store.delete(DEFAULT_NAMESPACE, "a").await.unwrap());
+        assert!(store.list_deleted(DEFAULT_NAMESPACE).await.is_empty());
+        assert_eq!(store.purge_deleted().await, 0);
+
+        let store = FunctionStore::with_config(&FunctionsConfig {
+            deleted_retention_secs: 0,
+            ..Default::default()
+        });
+        store.create(DEFAULT_NAMESPACE, request("a")).await.unwrap();
+        assert!(store.soft_delete(DEFAULT_NAMESPACE, "a").await.unwrap());
+        assert!(store.list_deleted(DEFAULT_NAMESPACE).await.is_empty());
+
+        // The bin holds no more than max_functions, oldest deletion out first
+        let store = FunctionStore::with_config(&FunctionsConfig {
+            max_functions: Some(2),
+            ..Default::default()
+        });
+        for name in ["a", "b", "c"] {
+            store.create(DEFAULT_NAMESPACE, request(name)).await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            store.soft_delete(DEFAULT_NAMESPACE, name).await.unwrap();
+        }
+        let mut deleted: Vec<_> = store.list_deleted(DEFAULT_NAMESPACE).await.into_iter().map(|d| d.name).collect();
+        deleted.sort();
+        assert_eq!(deleted, vec!["b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_find_by_hash() {
+        let store = FunctionStore::new();
+        let code = "export default function handler(event) { return event; }";
+        let request = |name: &str, code: &str| CreateFunctionRequest {
+            name: name.to_string(),
+            code: code.to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+
+        let pinned = store.create(DEFAULT_NAMESPACE, request("pinned", code)).await.unwrap();
+        assert_eq!(pinned.code_hash.len(), 64);
+        assert_eq!(pinned.code_hash, code_hash(code));
+
+        // Older versions stay reachable after the name moves on
+        store
+            .create(DEFAULT_NAMESPACE, request("pinned", "export default function handler() { return 1; }"))
+            .await
+            .unwrap();
+        let found = store.find_by_hash(&pinned.code_hash.to_uppercase()).await.unwrap();
+        assert_eq!((found.name.as_str(), found.version), ("pinned", 1));
+
+        assert!(store.find_by_hash(&code_hash("unknown")).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_clone_function() {
+        let store = FunctionStore::new();
+        let request = CreateFunctionRequest {
+            name: "original".to_string(),
+            code: "export default function handler(event: any) { return event; }".to_string(),
+            runtime: "ts".to_string(),
+            idempotent: true,
+            tags: HashMap::from([("team".to_string(), "payments".to_string())]),
+            ..Default::default()
+        };
+        let original = store.create(DEFAULT_NAMESPACE, request).await.unwrap();
+        original.counters.record(true);
+
+        let copy = store.clone_function(DEFAULT_NAMESPACE, "original", "variant").await.unwrap().unwrap();
+        assert_eq!((copy.name.as_str(), copy.version), ("variant", 1));
+        assert_eq!((copy.code, copy.source), (original.code, original.source));
+        assert!(copy.idempotent);
+        assert_eq!(copy.tags.get("team").map(String::as_str), Some("payments"));
+        assert_eq!(copy.counters.invocations(), 0);
+
+        let taken = store.clone_function(DEFAULT_NAMESPACE, "original", "variant").await.unwrap_err();
+        assert!(taken.is::<AlreadyExists>());
+        assert!(store.clone_function(DEFAULT_NAMESPACE, "original", "bad name").await.is_err());
+        assert!(store.clone_function(DEFAULT_NAMESPACE, "missing", "other").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_input_schema() {
+        let store = FunctionStore::new();
+        let request = |schema: serde_json::Value| CreateFunctionRequest {
+            name: "typed".to_string(),
+            code: "export default function handler(event) { return event; }".to_string(),
+            runtime: "v8".to_string(),
+            input_schema: Some(schema),
+            ..Default::default()
+        };
+
+        let err = store.create(DEFAULT_NAMESPACE, request(serde_json::json!({"type": 12}))).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<ValidationError>().unwrap().kind, ValidationErrorKind::InvalidInputSchema);
+
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"amount": {"type": "integer"}},
+            "required": ["amount"],
+        });
+        let function = store.create(DEFAULT_NAMESPACE, request(schema.clone())).await.unwrap();
+        let input = function.input_schema.unwrap();
+        assert_eq!(input.schema(), &schema);
+        assert!(input.violations(&serde_json::json!({"amount": 5})).is_empty());
+        assert_eq!(input.violations(&serde_json::json!({})).len(), 1);
+        let violations = input.violations(&serde_json::json!({"amount": "five"}));
+        assert!(violations[0].starts_with("/amount: "), "{:?}", violations);
     }
 
     #[tokio::test]
@@ -224,23 +1446,403 @@ mod tests {
                 name: format!("test-function-{}", i),
                 code: "export default function handler(event) { return {}; }".to_string(),
                 runtime: "v8".to_string(),
+                ..Default::default()
             };
-            store.create(request).await.unwrap();
+            store.create(DEFAULT_NAMESPACE, request).await.unwrap();
         }
 
         // List functions
-        let functions = store.list().await;
+        let functions = store.list(DEFAULT_NAMESPACE).await;
         assert_eq!(functions.len(), 3);
 
         // Delete function
-        let deleted = store.delete("test-function-1").await.unwrap();
+        let deleted = store.delete(DEFAULT_NAMESPACE, "test-function-1").await.unwrap();
         assert!(deleted);
 
-        let functions = store.list().await;
+        let functions = store.list(DEFAULT_NAMESPACE).await;
         assert_eq!(functions.len(), 2);
 
         // Try to delete non-existent function
-        let deleted = store.delete("non-existent").await.unwrap();
+        let deleted = store.delete(DEFAULT_NAMESPACE, "non-existent").await.unwrap();
         assert!(!deleted);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_function_versioning() {
+        let store = FunctionStore::with_config(&FunctionsConfig {
+            max_versions: 2,
+            ..Default::default()
+        });
+
+        for i in 0..3 {
+            let request = CreateFunctionRequest {
+                name: "versioned".to_string(),
+                code: format!("export default function handler(event) {{ return {{ v: {} }}; }}", i),
+                runtime: "v8".to_string(),
+                ..Default::default()
+            };
+            store.create(DEFAULT_NAMESPACE, request).await.unwrap();
+        }
+
+        // Latest version is the default
+        let latest = store.get(DEFAULT_NAMESPACE, "versioned").await.unwrap();
+        assert_eq!(latest.version, 3);
+
+        // Oldest version was evicted by the retention cap
+        let versions = store.list_versions(DEFAULT_NAMESPACE, "versioned").await.unwrap();
+        assert_eq!(versions.iter().map(|f| f.version).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(store.get_version(DEFAULT_NAMESPACE, "versioned", 1).await.is_none());
+        assert!(store.get_version(DEFAULT_NAMESPACE, "versioned", 2).await.is_some());
+
+        // Versions don't show up as separate functions
+        assert_eq!(store.list(DEFAULT_NAMESPACE).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_preserves_created_at() {
+        let store = FunctionStore::new();
+
+        let request = CreateFunctionRequest {
+            name: "timestamps".to_string(),
+            code: "export default function handler(event) { return 1; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        let created = store.create(DEFAULT_NAMESPACE, request).await.unwrap();
+        assert_eq!(created.created_at, created.updated_at);
+
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        let request = CreateFunctionRequest {
+            name: "timestamps".to_string(),
+            code: "export default function handler(event) { return 2; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        let updated = store.update(DEFAULT_NAMESPACE, "timestamps", request).await.unwrap();
+        assert_eq!(updated.created_at, created.created_at);
+        assert!(updated.updated_at > created.updated_at);
+    }
+
+    #[tokio::test]
+    async fn test_enable_disable() {
+        let store = FunctionStore::new();
+        assert!(store.set_enabled(DEFAULT_NAMESPACE, "missing", false).await.is_none());
+
+        let request = CreateFunctionRequest {
+            name: "toggled".to_string(),
+            code: "export default function handler(event) { return 1; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        let created = store.create(DEFAULT_NAMESPACE, request.clone()).await.unwrap();
+        assert!(created.enabled);
+
+        let disabled = store.set_enabled(DEFAULT_NAMESPACE, "toggled", false).await.unwrap();
+        assert!(!disabled.enabled);
+        let etag = |function: &Function| body_etag(&serde_json::to_vec(function).unwrap());
+        assert_ne!(etag(&disabled), etag(&created));
+        let fetched = store.get(DEFAULT_NAMESPACE, "toggled").await.unwrap();
+        assert_eq!(etag(&fetched), etag(&disabled));
+        assert!(!store.get_version(DEFAULT_NAMESPACE, "toggled", 1).await.unwrap().enabled);
+
+        // Deploying a fix doesn't silently put the function back in service
+        let updated = store.update(DEFAULT_NAMESPACE, "toggled", request).await.unwrap();
+        assert!(!updated.enabled);
+
+        assert!(store.set_enabled(DEFAULT_NAMESPACE, "toggled", true).await.unwrap().enabled);
+    }
+
+    #[tokio::test]
+    async fn test_invocation_counters() {
+        let store = FunctionStore::new();
+        let mut request = CreateFunctionRequest {
+            name: "counted".to_string(),
+            code: "export default function handler(event) { return 1; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        let v1 = store.create(DEFAULT_NAMESPACE, request.clone()).await.unwrap();
+        v1.counters.record(true);
+        v1.counters.record(false);
+
+        // Totals belong to the function, not the version that served them
+        request.code = "export default function handler(event) { return 2; }".to_string();
+        let v2 = store.update(DEFAULT_NAMESPACE, "counted", request.clone()).await.unwrap();
+        v2.counters.record(true);
+        let latest = store.get(DEFAULT_NAMESPACE, "counted").await.unwrap();
+        assert_eq!((latest.counters.invocations(), latest.counters.errors()), (3, 1));
+
+        let json = serde_json::to_value(&latest).unwrap();
+        assert_eq!(json["invocation_count"], 3);
+        assert_eq!(json["error_count"], 1);
+
+        // A recreated function starts from zero
+        store.delete(DEFAULT_NAMESPACE, "counted").await.unwrap();
+        let recreated = store.create(DEFAULT_NAMESPACE, request).await.unwrap();
+        assert_eq!(recreated.counters.invocations(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_traffic_split() {
+        let store = FunctionStore::new();
+
+        for _ in 0..2 {
+            let request = CreateFunctionRequest {
+                name: "canary".to_string(),
+                code: "export default function handler(event) { return {}; }".to_string(),
+                runtime: "v8".to_string(),
+                ..Default::default()
+            };
+            store.create(DEFAULT_NAMESPACE, request).await.unwrap();
+        }
+
+        // Unknown versions and all-zero weights are rejected
+        let unknown = vec![VersionWeight { version: 7, weight: 100 }];
+        assert!(store.set_traffic_split(DEFAULT_NAMESPACE, "canary", unknown).await.is_err());
+        let zero = vec![VersionWeight { version: 1, weight: 0 }];
+        assert!(store.set_traffic_split(DEFAULT_NAMESPACE, "canary", zero).await.is_err());
+
+        // Pin all traffic to the older version
+        let split = vec![
+            VersionWeight { version: 1, weight: 100 },
+            VersionWeight { version: 2, weight: 0 },
+        ];
+        store.set_traffic_split(DEFAULT_NAMESPACE, "canary", split).await.unwrap();
+        for _ in 0..20 {
+            assert_eq!(store.resolve(DEFAULT_NAMESPACE, "canary").await.unwrap().version, 1);
+        }
+
+        // Clearing the split goes back to latest
+        store.set_traffic_split(DEFAULT_NAMESPACE, "canary", Vec::new()).await.unwrap();
+        assert_eq!(store.resolve(DEFAULT_NAMESPACE, "canary").await.unwrap().version, 2);
+    }
+
+    #[tokio::test]
+    async fn test_forbidden_module_detection() {
+        let store = FunctionStore::new();
+
+        let forbidden = [
+            "const fs = require(`fs`);",
+            "const fs = require ( 'fs' );",
+            "const cp = require(\"child_process\");",
+            "import net from 'net';",
+            "import * as fs from \"node:fs/promises\";",
+            "import 'dgram';",
+            "const fs = await import('fs');",
+        ];
+
+        for snippet in forbidden {
+            let request = CreateFunctionRequest {
+                name: "test".to_string(),
+                code: format!("{} export default function handler(event) {{ return {{}}; }}", snippet),
+                runtime: "v8".to_string(),
+                ..Default::default()
+            };
+            assert!(store.create(DEFAULT_NAMESPACE, request).await.is_err(), "accepted: {}", snippet);
+        }
+
+        // Unrelated modules are fine
+        let request = CreateFunctionRequest {
+            name: "test".to_string(),
+            code: "import _ from 'lodash'; export default function handler(event) { return {}; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        assert!(store.create(DEFAULT_NAMESPACE, request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_configurable_denylist() {
+        // Trusted deployment: fs allowed, crypto banned, no export requirement
+        let store = FunctionStore::with_config(&FunctionsConfig {
+            forbidden_modules: vec!["crypto".to_string()],
+            require_default_export: false,
+            ..Default::default()
+        });
+
+        let request = CreateFunctionRequest {
+            name: "trusted".to_string(),
+            code: "const fs = require('fs'); function handler(event) { return {}; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        assert!(store.create(DEFAULT_NAMESPACE, request).await.is_ok());
+
+        let request = CreateFunctionRequest {
+            name: "trusted".to_string(),
+            code: "const crypto = require('crypto');".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        assert!(store.create(DEFAULT_NAMESPACE, request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tags() {
+        let store = FunctionStore::new();
+
+        for (name, team) in [("charge", "payments"), ("refund", "payments"), ("signup", "growth")] {
+            let request = CreateFunctionRequest {
+                name: name.to_string(),
+                code: "export default function handler(event) { return {}; }".to_string(),
+                runtime: "v8".to_string(),
+                tags: HashMap::from([("team".to_string(), team.to_string())]),
+                ..Default::default()
+            };
+            store.create(DEFAULT_NAMESPACE, request).await.unwrap();
+        }
+
+        let mut payments: Vec<String> = store
+            .list_tagged(DEFAULT_NAMESPACE, "team", "payments")
+            .await
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        payments.sort();
+        assert_eq!(payments, vec!["charge", "refund"]);
+        assert!(store.list_tagged(DEFAULT_NAMESPACE, "team", "infra").await.is_empty());
+
+        let request = CreateFunctionRequest {
+            name: "oversized".to_string(),
+            code: "export default function handler(event) { return {}; }".to_string(),
+            runtime: "v8".to_string(),
+            tags: HashMap::from([("team".to_string(), "x".repeat(MAX_TAG_VALUE_LEN + 1))]),
+            ..Default::default()
+        };
+        assert!(store.create(DEFAULT_NAMESPACE, request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_namespaces() {
+        let store = FunctionStore::new();
+
+        for (namespace, body) in [("team-a", "'a'"), ("team-b", "'b'")] {
+            let request = CreateFunctionRequest {
+                name: "myfunc".to_string(),
+                code: format!("export default function handler(event) {{ return {}; }}", body),
+                runtime: "v8".to_string(),
+                ..Default::default()
+            };
+            store.create(namespace, request).await.unwrap();
+        }
+
+        let a = store.get("team-a", "myfunc").await.unwrap();
+        let b = store.get("team-b", "myfunc").await.unwrap();
+        assert_ne!(a.code, b.code);
+        assert_ne!(a.affinity_key(), b.affinity_key());
+        assert!(store.get(DEFAULT_NAMESPACE, "myfunc").await.is_none());
+        assert_eq!(store.list("team-a").await.len(), 1);
+
+        assert!(store.delete("team-a", "myfunc").await.unwrap());
+        assert!(store.get("team-b", "myfunc").await.is_some());
+
+        let request = CreateFunctionRequest {
+            name: "myfunc".to_string(),
+            code: "export default function handler(event) { return {}; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        assert!(store.create("team/a", request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_events() {
+        let events = EventBus::new();
+        let mut receiver = events.subscribe();
+        let store = FunctionStore::new().with_events(events);
+
+        let request = CreateFunctionRequest {
+            name: "observed".to_string(),
+            code: "export default function handler(event) { return {}; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        store.create(DEFAULT_NAMESPACE, request.clone()).await.unwrap();
+        store.update(DEFAULT_NAMESPACE, "observed", request).await.unwrap();
+        store.delete(DEFAULT_NAMESPACE, "observed").await.unwrap();
+
+        let kinds: Vec<&str> = (0..3).map(|_| receiver.try_recv().unwrap().kind()).collect();
+        assert_eq!(kinds, vec!["function_created", "function_updated", "function_deleted"]);
+    }
+
+    #[tokio::test]
+    async fn test_export_import() {
+        let source = FunctionStore::new();
+        for name in ["alpha", "beta"] {
+            let request = CreateFunctionRequest {
+                name: name.to_string(),
+                code: "export default function handler(event) { return {}; }".to_string(),
+                runtime: "v8".to_string(),
+                ..Default::default()
+            };
+            source.create(DEFAULT_NAMESPACE, request).await.unwrap();
+        }
+        let beta = source.get(DEFAULT_NAMESPACE, "beta").await.unwrap();
+        beta.counters.record(true);
+        beta.counters.record(false);
+
+        let mut exported = source.export(DEFAULT_NAMESPACE).await;
+        assert_eq!(exported.len(), 2);
+        assert_eq!((exported[1].invocation_count, exported[1].error_count), (2, 1));
+        exported.push(ExportedFunction {
+            function: CreateFunctionRequest {
+                name: "broken".to_string(),
+                code: "".to_string(),
+                runtime: "v8".to_string(),
+                ..Default::default()
+            },
+            invocation_count: 0,
+            error_count: 0,
+        });
+
+        let target = FunctionStore::new();
+        let request = CreateFunctionRequest {
+            name: "alpha".to_string(),
+            code: "export default function handler(event) { return 'kept'; }".to_string(),
+            runtime: "v8".to_string(),
+            ..Default::default()
+        };
+        target.create(DEFAULT_NAMESPACE, request).await.unwrap();
+
+        let results = target.import(DEFAULT_NAMESPACE, exported.clone(), false).await;
+        assert!(matches!(results[0].outcome, ImportOutcome::Skipped { .. }));
+        assert!(matches!(results[1].outcome, ImportOutcome::Imported { version: 1 }));
+        assert!(matches!(results[2].outcome, ImportOutcome::Error { .. }));
+        assert!(target.get(DEFAULT_NAMESPACE, "alpha").await.unwrap().code.contains("kept"));
+        let beta = target.get(DEFAULT_NAMESPACE, "beta").await.unwrap();
+        assert_eq!((beta.counters.invocations(), beta.counters.errors()), (2, 1));
+
+        let results = target.import(DEFAULT_NAMESPACE, exported, true).await;
+        assert!(matches!(results[0].outcome, ImportOutcome::Imported { version: 2 }));
+        assert!(!target.get(DEFAULT_NAMESPACE, "alpha").await.unwrap().code.contains("kept"));
+    }
+
+    #[test]
+    fn test_lint_warnings() {
+        let clean = "export default async function handler(event) {\n  try {\n    const res = await fetch(event.url);\n    return await res.json();\n  } catch (e) {\n    return { error: String(e) };\n  }\n}";
+        assert!(lint(clean).is_empty());
+
+        let warnings = lint("export default function handler(event) { var x = event.s.substr(1); return fetch(x); }");
+        assert_eq!(warnings.len(), 4);
+        assert!(warnings[0].contains("error handling"));
+        assert!(warnings[1].contains("synchronous"));
+        assert!(warnings.iter().any(|w| w.contains("`var`")));
+        assert!(warnings.iter().any(|w| w.contains("substr")));
+
+        let handler = "export default async function handler(event) { try { return 1; } catch (e) {} }";
+        let bundled = format!("{}\n{}", "var a=1;".repeat(200), handler);
+        let warnings = lint(&bundled);
+        assert!(warnings.iter().any(|w| w.starts_with("Line 1 is 1600 bytes long")));
+    }
+
+    #[test]
+    fn test_code_budget() {
+        let budget = CodeBudget::measure("export default () => 1;\n// done\n");
+        assert_eq!((budget.bytes, budget.lines, budget.limit_bytes), (32, 2, MAX_CODE_BYTES));
+        assert_eq!(budget.percent_used, 0.0);
+
+        let budget = CodeBudget::measure(&"x".repeat(MAX_CODE_BYTES * 3 / 4));
+        assert_eq!(budget.percent_used, 75.0);
+    }
+}
@@ -0,0 +1,372 @@
+// The gRPC face of the function API (proto/hyperdrive.proto), for clients
+// that would rather not speak JSON over HTTP/1. It shares AppState with the
+// HTTP server, so functions, the VM pool, rate limits, circuit breakers and
+// the audit log are one and the same whichever way a call comes in.
+use anyhow::{Context, Result};
+use axum::http::{HeaderMap, StatusCode};
+use futures::{Stream, StreamExt};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::transport::Server;
+use tonic::{Code, Request, Response, Status};
+use tracing::{error, info, warn, Instrument};
+use uuid::Uuid;
+
+use crate::function::{self, AlreadyExists, QuotaExceeded};
+use crate::types::{
+    ApiError, CreateFunctionRequest, Deadline, Function, FunctionPath, FunctionStatus, InvokeQuery, Priority,
+};
+use crate::{
+    apply_transform, check_depth, check_input, check_integers, ensure_accepting, ensure_caller_allowed,
+    ensure_enabled, ensure_ready, ensure_signable, ensure_streamable, ensure_vm_execution, execution_error,
+    invocation_deadline, invocation_priority, invocation_span, record_invocation, resolve_function, run_audited,
+    spawn_prime, stream_on_pool, transform_output, validation_error, AppState, InvocationKind,
+};
+
+pub mod proto {
+    tonic::include_proto!("hyperdrive.v1");
+}
+
+use proto::functions_server::{Functions, FunctionsServer};
+
+// Serve until shutdown begins, then let in-flight calls finish
+pub async fn serve(state: AppState, listener: TcpListener) -> Result<()> {
+    let shutdown = state.shutdown.clone();
+    Server::builder()
+        .add_service(FunctionsServer::new(FunctionService { state }))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown.cancelled_owned())
+        .await
+        .context("gRPC server failed")
+}
+
+struct FunctionService {
+    state: AppState,
+}
+
+#[tonic::async_trait]
+impl Functions for FunctionService {
+    async fn create_function(
+        &self,
+        request: Request<proto::CreateFunctionRequest>,
+    ) -> Result<Response<proto::CreateFunctionResponse>, Status> {
+        let request = request.into_inner();
+        let namespace = namespace(request.namespace);
+        let create = CreateFunctionRequest {
+            name: request.name,
+            code: request.code,
+            runtime: request.runtime,
+            tags: request.tags,
+            sign_responses: request.sign_responses,
+            ..Default::default()
+        };
+        if create.code.is_empty() {
+            return Err(Status::invalid_argument("code is required"));
+        }
+        ensure_signable(&self.state, create.sign_responses)?;
+
+        let created = self.state.function_store.create(&namespace, create).await.map_err(|e| {
+            error!("Failed to create function over gRPC: {}", e);
+            let code = if e.is::<AlreadyExists>() {
+                Code::AlreadyExists
+            } else if e.is::<QuotaExceeded>() {
+                Code::PermissionDenied
+            } else {
+                Code::InvalidArgument
+            };
+            Status::new(code, validation_error(&e).error)
+        })?;
+        spawn_prime(&self.state, &created);
+
+        let source = created.source.as_deref().unwrap_or(&created.code);
+        Ok(Response::new(proto::CreateFunctionResponse {
+            warnings: function::lint(source),
+            function: Some(to_proto(&created)),
+        }))
+    }
+
+    async fn get_function(
+        &self,
+        request: Request<proto::GetFunctionRequest>,
+    ) -> Result<Response<proto::Function>, Status> {
+        let request = request.into_inner();
+        let namespace = namespace(request.namespace);
+        let store = &self.state.function_store;
+        let found = match request.version {
+            Some(version) => store.get_version(&namespace, &request.name, version).await,
+            None => store.get(&namespace, &request.name).await,
+        };
+        let found = found.ok_or_else(|| not_found(&namespace, &request.name))?;
+        Ok(Response::new(to_proto(&found)))
+    }
+
+    async fn list_functions(
+        &self,
+        request: Request<proto::ListFunctionsRequest>,
+    ) -> Result<Response<proto::ListFunctionsResponse>, Status> {
+        let namespace = namespace(request.into_inner().namespace);
+        let mut functions = self.state.function_store.list(&namespace).await;
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Response::new(proto::ListFunctionsResponse {
+            functions: functions.iter().map(to_proto).collect(),
+        }))
+    }
+
+    async fn delete_function(
+        &self,
+        request: Request<proto::DeleteFunctionRequest>,
+    ) -> Result<Response<proto::DeleteFunctionResponse>, Status> {
+        let request = request.into_inner();
+        let namespace = namespace(request.namespace);
+        let store = &self.state.function_store;
+        let deleted = if request.hard {
+            store.delete(&namespace, &request.name).await
+        } else {
+            store.soft_delete(&namespace, &request.name).await
+        };
+        match deleted {
+            Ok(true) => Ok(Response::new(proto::DeleteFunctionResponse {})),
+            Ok(false) => Err(not_found(&namespace, &request.name)),
+            Err(e) => Err(Status::internal(format!("{:#}", e))),
+        }
+    }
+
+    async fn invoke(
+        &self,
+        request: Request<proto::InvokeRequest>,
+    ) -> Result<Response<proto::InvokeResponse>, Status> {
+        let state = &self.state;
+        let peer = request.remote_addr();
+        let headers = request.metadata().clone().into_headers();
+        let request = request.into_inner();
+        let path = FunctionPath {
+            namespace: namespace(request.namespace),
+            name: request.name,
+        };
+        info!("Invoking function over gRPC: {}/{}", path.namespace, path.name);
+
+        let (function, priority, deadline) =
+            admit(state, &path, request.version, request.timeout_ms, peer, &headers).await?;
+        let limit = function.max_payload_bytes.unwrap_or(state.config.invoke.max_payload_bytes);
+        if request.payload.len() > limit {
+            return Err(Status::resource_exhausted(format!("Payload exceeds {} bytes", limit)));
+        }
+        let mut payload: serde_json::Value = serde_json::from_slice(&request.payload)
+            .map_err(|e| Status::invalid_argument(format!("Payload is not valid JSON: {}", e)))?;
+        check_depth(state, &payload)?;
+        check_integers(state, &mut payload)?;
+        let payload = apply_transform(function.input_transform.as_ref(), payload);
+        check_input(&function, &payload)?;
+
+        let started = std::time::Instant::now();
+        let execution = run_audited(state, &function, payload, deadline, priority, InvocationKind::Live)
+            .await
+            .map_err(|e| Status::from(execution_error(e)))?;
+        let result = transform_output(&function, execution.result);
+        let result = serde_json::to_vec(&result).map_err(|e| Status::internal(e.to_string()))?;
+        // Signed over the result bytes, as X-Signature is over the HTTP body
+        let signature = state
+            .signer
+            .as_ref()
+            .filter(|signer| signer.covers(function.sign_responses))
+            .map(|signer| signer.sign(&result));
+
+        let mut response = Response::new(proto::InvokeResponse {
+            result,
+            version: function.version,
+            cold_start: execution.cold_start,
+            cached: execution.cached,
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+        if let Some(invocation_id) = execution.invocation_id {
+            response.metadata_mut().insert("x-invocation-id", invocation_id_metadata(invocation_id));
+        }
+        if let Some(signature) = signature {
+            let signature = MetadataValue::try_from(signature.as_bytes())
+                .map_err(|e| Status::internal(format!("Invalid signature: {}", e)))?;
+            response.metadata_mut().insert("x-signature", signature);
+        }
+        Ok(response)
+    }
+
+    type InvokeStreamStream = Pin<Box<dyn Stream<Item = Result<proto::InvokeChunk, Status>> + Send>>;
+
+    async fn invoke_stream(
+        &self,
+        request: Request<proto::InvokeStreamRequest>,
+    ) -> Result<Response<Self::InvokeStreamStream>, Status> {
+        let state = &self.state;
+        let peer = request.remote_addr();
+        let headers = request.metadata().clone().into_headers();
+        let request = request.into_inner();
+        let path = FunctionPath {
+            namespace: namespace(request.namespace),
+            name: request.name,
+        };
+        info!("Stream invoking function over gRPC: {}/{}", path.namespace, path.name);
+
+        ensure_vm_execution(state)?;
+        let (function, priority, deadline) =
+            admit(state, &path, request.version, request.timeout_ms, peer, &headers).await?;
+        ensure_streamable(state, &function)?;
+        let limit = function
+            .max_payload_bytes
+            .map_or(state.config.invoke.max_stream_bytes, |max| max.min(state.config.invoke.max_stream_bytes));
+        if request.body.len() > limit {
+            return Err(Status::resource_exhausted(format!("Request body exceeds {} bytes", limit)));
+        }
+        let content_type = match request.content_type.as_str() {
+            "" => "application/octet-stream".to_string(),
+            content_type => content_type.to_string(),
+        };
+
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let started = std::time::Instant::now();
+        let body = reqwest::Body::from(request.body);
+        let span = invocation_span(&function);
+        let kind = InvocationKind::Live;
+        let outcome = stream_on_pool(state, &function, &content_type, body, deadline, priority, kind)
+            .instrument(span.clone())
+            .await;
+        span.record("outcome", if outcome.is_ok() { "success" } else { "error" });
+        let invocation_id = record_invocation(state, &function, kind, timestamp, started, &outcome, None);
+        let (response, mut lease, cold_start) = outcome.map_err(|e| Status::from(execution_error(e)))?;
+
+        let response_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let mut response_type = Some(response_type.to_string());
+        let chunks = response.bytes_stream().map(move |chunk| match chunk {
+            Ok(data) => Ok(proto::InvokeChunk {
+                data: data.to_vec(),
+                content_type: response_type.take().unwrap_or_default(),
+            }),
+            Err(e) => {
+                lease.mark_failed();
+                Err(Status::unavailable(format!("Function stream failed: {}", e)))
+            }
+        });
+
+        let mut response = Response::new(Box::pin(chunks) as Self::InvokeStreamStream);
+        let metadata = response.metadata_mut();
+        metadata.insert("x-function-version", MetadataValue::from(function.version));
+        metadata.insert("x-cold-start", MetadataValue::from_static(if cold_start { "true" } else { "false" }));
+        metadata.insert("x-invocation-id", invocation_id_metadata(invocation_id));
+        Ok(response)
+    }
+}
+
+// Names the invocation's audit log entry, as X-Invocation-Id does over HTTP
+fn invocation_id_metadata(invocation_id: Uuid) -> MetadataValue<Ascii> {
+    MetadataValue::try_from(invocation_id.to_string()).expect("a UUID is valid metadata")
+}
+
+// The checks every invocation passes before it gets near a VM, as the
+// HTTP invoke route and its rate limit middleware apply them
+async fn admit(
+    state: &AppState,
+    path: &FunctionPath,
+    version: Option<u32>,
+    timeout_ms: Option<u64>,
+    peer: Option<SocketAddr>,
+    headers: &HeaderMap,
+) -> Result<(Function, Priority, Option<Deadline>), Status> {
+    ensure_accepting(state)?;
+    let key = format!("{}/{}", path.namespace, path.name);
+    if let Some(status) = state.rate_limiter.charge(&key, 1).filter(|status| !status.allowed) {
+        warn!("Rate limit exceeded for {}", key);
+        return Err(Status::resource_exhausted(format!(
+            "Rate limit of {} invocations exceeded for {}",
+            status.limit, key
+        )));
+    }
+
+    let query = InvokeQuery {
+        version,
+        timeout_ms,
+        meta: false,
+    };
+    let deadline = invocation_deadline(state, headers, &query)?;
+    let function = resolve_function(state, path, &query).await.map_err(ApiError::from)?;
+    ensure_enabled(&function)?;
+    ensure_caller_allowed(state, &function, peer, headers)?;
+    ensure_ready(&function)?;
+    let priority = invocation_priority(&function, headers)?;
+    Ok((function, priority, deadline))
+}
+
+fn namespace(namespace: String) -> String {
+    if namespace.is_empty() {
+        function::DEFAULT_NAMESPACE.to_string()
+    } else {
+        namespace
+    }
+}
+
+fn not_found(namespace: &str, name: &str) -> Status {
+    Status::not_found(format!("Function not found: {}/{}", namespace, name))
+}
+
+fn to_proto(function: &Function) -> proto::Function {
+    let status = match function.status.get() {
+        FunctionStatus::Creating => proto::FunctionStatus::Creating,
+        FunctionStatus::Ready => proto::FunctionStatus::Ready,
+        FunctionStatus::Error => proto::FunctionStatus::Error,
+    };
+    proto::Function {
+        namespace: function.namespace.clone(),
+        name: function.name.clone(),
+        version: function.version,
+        runtime: function.runtime.clone(),
+        code_hash: function.code_hash.clone(),
+        enabled: function.enabled,
+        status: status.into(),
+        tags: function.tags.clone(),
+        invocation_count: function.counters.invocations(),
+        error_count: function.counters.errors(),
+        created_at: function.created_at.to_rfc3339(),
+        updated_at: function.updated_at.to_rfc3339(),
+    }
+}
+
+// The gRPC code closest to each status the shared HTTP checks return
+impl From<ApiError> for Status {
+    fn from(error: ApiError) -> Self {
+        let code = match error.status {
+            StatusCode::BAD_REQUEST => Code::InvalidArgument,
+            StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+            StatusCode::FORBIDDEN => Code::PermissionDenied,
+            StatusCode::NOT_FOUND => Code::NotFound,
+            StatusCode::CONFLICT | StatusCode::UNSUPPORTED_MEDIA_TYPE => Code::FailedPrecondition,
+            StatusCode::PAYLOAD_TOO_LARGE | StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+            StatusCode::NOT_IMPLEMENTED => Code::Unimplemented,
+            StatusCode::SERVICE_UNAVAILABLE | StatusCode::BAD_GATEWAY => Code::Unavailable,
+            StatusCode::GATEWAY_TIMEOUT | StatusCode::REQUEST_TIMEOUT => Code::DeadlineExceeded,
+            _ => Code::Internal,
+        };
+        let message = match error.body {
+            Some(body) => body.error,
+            None => error.status.canonical_reason().unwrap_or_default().to_string(),
+        };
+        Status::new(code, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_from_api_error() {
+        let status = Status::from(ApiError::new(StatusCode::TOO_MANY_REQUESTS, "Slow down"));
+        assert_eq!((status.code(), status.message()), (Code::ResourceExhausted, "Slow down"));
+
+        let status = Status::from(ApiError::from(StatusCode::NOT_FOUND));
+        assert_eq!((status.code(), status.message()), (Code::NotFound, "Not Found"));
+        assert_eq!(Status::from(ApiError::from(StatusCode::IM_A_TEAPOT)).code(), Code::Internal);
+    }
+}
@@ -0,0 +1,164 @@
+use axum::http::HeaderMap;
+use ipnetwork::IpNetwork;
+use serde::ser::SerializeMap;
+use serde::Serialize;
+use std::net::IpAddr;
+
+// Parse CIDR blocks ("10.0.0.0/8", "2001:db8::/32"); a bare address is a
+// single host
+pub fn parse_networks(networks: &[String]) -> Result<Vec<IpNetwork>, String> {
+    networks
+        .iter()
+        .map(|network| {
+            network
+                .trim()
+                .parse()
+                .map_err(|e| format!("Invalid CIDR '{}': {}", network, e))
+        })
+        .collect()
+}
+
+// A function's caller restrictions. Denials win over the allowlist, and an
+// empty allowlist lets in anyone not denied. Serialized as `allowed_ips`
+// and `denied_ips`, each omitted when empty.
+#[derive(Debug, Clone, Default)]
+pub struct IpRules {
+    allow: Vec<IpNetwork>,
+    deny: Vec<IpNetwork>,
+}
+
+impl IpRules {
+    pub fn parse(allow: &[String], deny: &[String]) -> Result<Self, String> {
+        Ok(Self {
+            allow: parse_networks(allow)?,
+            deny: parse_networks(deny)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        if self.deny.iter().any(|network| network.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|network| network.contains(ip))
+    }
+
+    pub fn allowed(&self) -> Vec<String> {
+        self.allow.iter().map(ToString::to_string).collect()
+    }
+
+    pub fn denied(&self) -> Vec<String> {
+        self.deny.iter().map(ToString::to_string).collect()
+    }
+}
+
+impl Serialize for IpRules {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        if !self.allow.is_empty() {
+            map.serialize_entry("allowed_ips", &self.allowed())?;
+        }
+        if !self.deny.is_empty() {
+            map.serialize_entry("denied_ips", &self.denied())?;
+        }
+        map.end()
+    }
+}
+
+// Proxies whose X-Forwarded-For is believed (server.trusted_proxies)
+pub struct TrustedProxies {
+    networks: Vec<IpNetwork>,
+}
+
+impl TrustedProxies {
+    pub fn new(networks: &[String]) -> Result<Self, String> {
+        Ok(Self {
+            networks: parse_networks(networks)?,
+        })
+    }
+
+    fn trusts(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(ip.to_canonical()))
+    }
+
+    // The caller's address. A connection from a trusted proxy is attributed
+    // to the nearest X-Forwarded-For hop that isn't itself a trusted proxy,
+    // reading right to left; hops further left are client-supplied and
+    // can't be trusted.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.trusts(peer) {
+            return peer;
+        }
+        let hops = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|hop| hop.trim().parse::<IpAddr>())
+            .collect::<Vec<_>>();
+
+        let mut client = peer;
+        for hop in hops.into_iter().rev() {
+            // An unparseable hop ends the chain we can vouch for
+            let Ok(hop) = hop else {
+                break;
+            };
+            client = hop;
+            if !self.trusts(hop) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_ip_rules() {
+        let allow = strings(&["10.0.0.0/8", "2001:db8::/32"]);
+        let rules = IpRules::parse(&allow, &strings(&["10.0.0.66"])).unwrap();
+        assert!(rules.permits("10.1.2.3".parse().unwrap()));
+        assert!(rules.permits("2001:db8::1".parse().unwrap()));
+        assert!(rules.permits("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!rules.permits("10.0.0.66".parse().unwrap()));
+        assert!(!rules.permits("192.168.0.1".parse().unwrap()));
+
+        let deny_only = IpRules::parse(&[], &strings(&["192.168.0.0/16"])).unwrap();
+        assert!(deny_only.permits("10.1.2.3".parse().unwrap()));
+        assert!(!deny_only.permits("192.168.4.4".parse().unwrap()));
+        assert!(IpRules::default().permits("192.168.4.4".parse().unwrap()));
+
+        assert!(IpRules::parse(&strings(&["10.0.0.0/33"]), &[]).is_err());
+        assert!(IpRules::parse(&strings(&["not-an-ip"]), &[]).is_err());
+
+        assert_eq!(
+            serde_json::to_value(&deny_only).unwrap(),
+            serde_json::json!({"denied_ips": ["192.168.0.0/16"]})
+        );
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let proxies = TrustedProxies::new(&strings(&["10.0.0.0/8"])).unwrap();
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "1.1.1.1, 203.0.113.7, 10.0.0.2".parse().unwrap());
+
+        // Spoofed hops left of the first untrusted address are ignored
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &headers), ip("203.0.113.7"));
+        // Only trusted peers get to forward
+        assert_eq!(proxies.client_ip(ip("198.51.100.1"), &headers), ip("198.51.100.1"));
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &HeaderMap::new()), ip("10.0.0.1"));
+    }
+}
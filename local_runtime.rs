@@ -0,0 +1,158 @@
+// In-process execution on an embedded JavaScript engine, for development
+// without Firecracker.
+//
+// THIS PROVIDES NO ISOLATION. Functions share the server's process, memory
+// and privileges, and a runaway handler is only stopped by the engine's
+// loop and recursion limits; the execution timeout returns to the caller
+// but can't interrupt the engine thread. Never run untrusted code in this
+// mode or expose a server using it.
+use anyhow::Result;
+use boa_engine::{
+    builtins::promise::PromiseState, js_string, object::builtins::JsPromise, Context, JsError, JsValue, Module,
+    Source,
+};
+use regex::Regex;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::types::{Function, HyperdriveError};
+
+// Backstops for handlers that never return, since the engine thread can't
+// be cancelled
+const LOOP_ITERATION_LIMIT: u64 = 100_000_000;
+const RECURSION_LIMIT: usize = 2048;
+
+pub async fn execute(
+    function: &Function,
+    payload: serde_json::Value,
+    timeout: Duration,
+    max_response_bytes: usize,
+) -> Result<serde_json::Value> {
+    // A Context isn't Send, so each invocation gets a fresh one on a
+    // blocking thread; nothing carries over between invocations
+    let code = function.code.clone();
+    let run = tokio::task::spawn_blocking(move || run_handler(&code, payload));
+    let result = tokio::time::timeout(timeout, run)
+        .await
+        .map_err(|_| anyhow::anyhow!("Function execution timed out after {:?}", timeout))??
+        .map_err(|e| anyhow::anyhow!("Function execution failed: {}", e))?;
+
+    if serde_json::to_vec(&result)?.len() > max_response_bytes {
+        return Err(HyperdriveError::ResponseTooLarge(max_response_bytes).into());
+    }
+    Ok(result)
+}
+
+fn run_handler(code: &str, payload: serde_json::Value) -> Result<serde_json::Value, String> {
+    let mut context = Context::default();
+    context.runtime_limits_mut().set_loop_iteration_limit(LOOP_ITERATION_LIMIT);
+    context.runtime_limits_mut().set_recursion_limit(RECURSION_LIMIT);
+
+    let handler = load_handler(code, &mut context)?;
+    let handler = handler
+        .as_callable()
+        .ok_or("Function must export a handler function")?
+        .clone();
+
+    let event = JsValue::from_json(&payload, &mut context).map_err(js_error)?;
+    let mut result = handler
+        .call(&JsValue::undefined(), &[event], &mut context)
+        .map_err(js_error)?;
+
+    // Async handlers: drain the job queue and unwrap the promise
+    if let Some(promise) = result.as_object().and_then(|o| JsPromise::from_object(o.clone()).ok()) {
+        context.run_jobs();
+        result = settled(promise.state())?;
+    }
+
+    result.to_json(&mut context).map_err(js_error)
+}
+
+// ES modules and CommonJS scripts. The default export (or module.exports
+// itself) wins; otherwise an export named `handler` is used.
+fn load_handler(code: &str, context: &mut Context) -> Result<JsValue, String> {
+    static ESM: OnceLock<Regex> = OnceLock::new();
+    let esm = ESM.get_or_init(|| Regex::new(r"\bexport\b").expect("valid export pattern"));
+
+    let exports = if esm.is_match(code) {
+        let module = Module::parse(Source::from_bytes(code), None, context).map_err(js_error)?;
+        let evaluated = module.load_link_evaluate(context);
+        context.run_jobs();
+        settled(evaluated.state())?;
+
+        let namespace = module.namespace(context);
+        let default = namespace.get(js_string!("default"), context).map_err(js_error)?;
+        if !default.is_undefined() {
+            return Ok(default);
+        }
+        JsValue::from(namespace)
+    } else {
+        context
+            .eval(Source::from_bytes("var module = { exports: {} }; var exports = module.exports;"))
+            .map_err(js_error)?;
+        context.eval(Source::from_bytes(code)).map_err(js_error)?;
+        let exports = context
+            .eval(Source::from_bytes("module.exports"))
+            .map_err(js_error)?;
+        if exports.is_callable() {
+            return Ok(exports);
+        }
+        exports
+    };
+
+    match exports.as_object() {
+        Some(exports) => exports.get(js_string!("handler"), context).map_err(js_error),
+        None => Ok(exports),
+    }
+}
+
+fn settled(state: PromiseState) -> Result<JsValue, String> {
+    match state {
+        PromiseState::Fulfilled(value) => Ok(value),
+        PromiseState::Rejected(error) => Err(error.display().to_string()),
+        // Nothing can resolve it later: there's no event loop or I/O
+        PromiseState::Pending => Err("Handler returned a promise that never settled".to_string()),
+    }
+}
+
+fn js_error(error: JsError) -> String {
+    error.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(code: &str) -> Function {
+        Function::fixture("local", code)
+    }
+
+    #[tokio::test]
+    async fn test_local_execution() {
+        let timeout = Duration::from_secs(5);
+        let payload = serde_json::json!({ "n": 1 });
+
+        let module = function("export default async function handler(event) { return { n: event.n + 1 }; }");
+        let result = execute(&module, payload.clone(), timeout, 1024).await.unwrap();
+        assert_eq!(result, serde_json::json!({ "n": 2 }));
+
+        let commonjs = function("module.exports = function (event) { return [event.n]; };");
+        let result = execute(&commonjs, payload.clone(), timeout, 1024).await.unwrap();
+        assert_eq!(result, serde_json::json!([1]));
+
+        let named = function("export async function handler(event) { return event.n * 10; }");
+        let result = execute(&named, payload.clone(), timeout, 1024).await.unwrap();
+        assert_eq!(result, serde_json::json!(10));
+
+        let exports_handler = function("exports.handler = (event) => event.n - 1;");
+        let result = execute(&exports_handler, payload.clone(), timeout, 1024).await.unwrap();
+        assert_eq!(result, serde_json::json!(0));
+
+        let throws = function("export default function handler() { throw new Error('boom'); }");
+        let error = execute(&throws, payload.clone(), timeout, 1024).await.unwrap_err();
+        assert!(error.to_string().contains("boom"));
+
+        let large = function("export default function handler() { return 'x'.repeat(2048); }");
+        assert!(execute(&large, payload, timeout, 1024).await.is_err());
+    }
+}
@@ -1,151 +1,3628 @@
 use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use base64::{prelude::BASE64_STANDARD, Engine};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
-    routing::{get, post},
-    Router,
+    body::Body,
+    extract::{rejection::JsonRejection, ConnectInfo, DefaultBodyLimit, FromRequest, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    routing::{delete, get, post, put},
+    Extension, Router,
 };
+use futures::{future, stream, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
 use tokio::net::TcpListener;
-use tracing::{info, warn, error};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+use tower_http::compression::{
+    predicate::{NotForContentType, Predicate, SizeAbove},
+    CompressionLayer,
+};
+use tracing::{debug, info, warn, error};
+use tracing::{field, info_span, Instrument, Span};
 use uuid::Uuid;
 
+mod audit;
+mod breaker;
+mod cache;
+mod code_url;
+mod config;
+mod cors;
+mod events;
+mod ip_access;
+mod metrics;
+mod output_policy;
 mod vm;
 mod function;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "local-runtime")]
+mod local_runtime;
 mod pool;
+mod ratelimit;
+mod runtime_info;
+mod runtimes;
+mod scheduler;
+mod signing;
+mod telemetry;
+mod transform;
 mod types;
+mod typescript;
+mod usage;
 
-use vm::VmManager;
-use function::FunctionStore;
-use pool::VmPool;
+use audit::{AuditEvent, AuditLog, AuditOutcome};
+use breaker::{CircuitBreakers, CircuitOpen};
+use cache::{CacheKey, ResultCache};
+use code_url::{CodeFetchError, CodeFetcher};
+use config::{Config, ExecutionMode, PoolConfig, Tunables};
+use events::{EventBus, PlatformEvent};
+use ip_access::TrustedProxies;
+use metrics::Metrics;
+use output_policy::OutputPolicy;
+use vm::{VmManager, WorkDirs};
+use function::{AlreadyExists, CodeBudget, FunctionStore, QuotaExceeded, ValidationError};
+use ratelimit::RateLimiter;
+use pool::{
+    AcquireTracker, BootBackoff, BootLimiter, DemandForecast, DemandHold, FailedVms, FairQueue, FairTurn, FlushedVms,
+    Generation, Generations, ReservedVms, SaturationAlert, SaturationTracker, UnhealthyVms, VmPool, WarmupGate,
+};
+use runtime_info::RuntimeInfo;
+use scheduler::ScheduleTracker;
+use signing::ResponseSigner;
+use transform::Transform;
 use types::*;
+use typescript::TranspileError;
+use usage::UsageStats;
+
+// Upper bound on payloads accepted by a single batch invoke
+const MAX_BATCH_SIZE: usize = 1000;
+// Batch items executing at once, so one batch can't drain the pool
+const BATCH_CONCURRENCY: usize = 8;
+// Dead VMs replaced per acquire before giving up
+const MAX_ACQUIRE_ATTEMPTS: usize = 3;
+// Consecutive failed boots, with no VM running, that mean VMs can't be
+// booted at all
+const BOOT_OUTAGE_FAILURES: u32 = 3;
+const VM_PING_TIMEOUT: Duration = Duration::from_millis(500);
+// Fleet health checks: VMs probed at once, and the answer time past which
+// a VM counts as degraded
+const HEALTHCHECK_CONCURRENCY: usize = 16;
+const VM_DEGRADED_LATENCY: Duration = Duration::from_millis(100);
+const SATURATION_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+// How often recent invocations are folded into the demand forecast
+const FORECAST_INTERVAL: Duration = Duration::from_secs(5);
+// Premium reserve top-up when no VM was taken, to replace ones that failed
+const RESERVE_REFILL_INTERVAL: Duration = Duration::from_secs(5);
+// How often to check whether idle pings were turned back on
+const IDLE_PING_DISABLED_RECHECK: Duration = Duration::from_secs(60);
+// How often the recycle bin is checked for functions past retention
+const RECYCLE_BIN_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+// Cron expressions have one-second resolution
+const SCHEDULER_TICK: Duration = Duration::from_secs(1);
+// The admin self-test's canary lives in its own namespace, out of the way
+// of real functions, and echoes back the nonce it's invoked with
+const SELFTEST_NAMESPACE: &str = "hyperdrive-selftest";
+const SELFTEST_CANARY: &str = "export default function handler(event) { return { echo: event.nonce }; }";
+// Responses smaller than this aren't worth the compression overhead
+const MIN_COMPRESSED_SIZE: u16 = 1024;
 
 #[derive(Clone)]
 pub struct AppState {
-    vm_manager: Arc<VmManager>,
+    config: Arc<Config>,
+    tunables: Arc<ArcSwap<Tunables>>, // reloaded on SIGHUP
+    generations: Arc<Generations<VmBackend>>, // rolled when the VM boot settings change
+    work_dirs: Arc<WorkDirs>, // stale ones reaped at startup and shutdown
     function_store: Arc<FunctionStore>,
-    vm_pool: Arc<VmPool>,
+    v8_client: reqwest::Client, // shared so V8 host connections are pooled
+    acquire_tracker: Arc<AcquireTracker>,
+    fair_queue: Arc<FairQueue>, // admits invocations round-robin across functions
+    saturation: Arc<SaturationTracker>,
+    demand: Arc<DemandForecast>, // sizes the warm pool with pool.predictive_warming
+    boot_backoff: Arc<BootBackoff>, // fed by every pool's boots
+    failed_vms: Arc<FailedVms>, // listed as `Failed` until they age out
+    boot_limiter: Arc<BootLimiter>, // shared by every generation's pool
+    schedules: Arc<ScheduleTracker>,
+    unhealthy_vms: Arc<UnhealthyVms>, // failed an idle ping, reaped on next acquire
+    flushed_vms: Arc<FlushedVms>,
+    reserved_vms: Arc<ReservedVms>, // held out of the pool for premium functions
+    warmup: Arc<WarmupGate>,
+    metrics: Arc<Metrics>,
+    audit_log: Arc<AuditLog>,
+    result_cache: Arc<ResultCache>,
+    breakers: Arc<CircuitBreakers>,
+    rate_limiter: Arc<RateLimiter>,
+    signer: Option<Arc<ResponseSigner>>, // set when signing.key is configured
+    output_policy: Arc<OutputPolicy>,
+    trusted_proxies: Arc<TrustedProxies>,
+    events: EventBus,
+    usage_stats: Arc<UsageStats>,
+    shutdown: CancellationToken, // cancelled once draining begins
+    code_fetcher: Arc<CodeFetcher>,
+    runtime_info: Arc<RuntimeInfo>,
+}
+
+// Boots and pools the VMs of one config generation
+struct VmBackend {
+    config: VmConfig,
+    manager: Arc<VmManager>,
+    pool: Arc<VmPool>,
+}
+
+// Test invocations run like live ones but are left out of the function's
+// invocation counts and usage stats, and flagged in the audit log. Replays
+// are treated the same and name the invocation they re-ran.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InvocationKind {
+    Live,
+    Test,
+    Replay(Uuid),
+}
+
+// Outcome of running a function on a pooled VM
+struct PoolExecution {
+    result: serde_json::Value,
+    cold_start: bool, // acquire had to boot a fresh VM
+    cached: bool,     // served from the result cache without a VM
+    usage: Option<ResourceUsage>, // as reported by the V8 host
+    vm_id: Option<Uuid>, // None when no VM was used
+    invocation_id: Option<Uuid>, // audit log entry, set by run_audited
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging
-    tracing_subscriber::fmt::init();
+    // Load configuration, then initialize logging and span export
+    let config = Arc::new(Config::load().context("Invalid configuration")?);
+    let tracer_provider = telemetry::init(&config.telemetry)?;
     info!("Starting Hyperdrive Rust");
+    if let Some(endpoint) = &config.telemetry.otlp_endpoint {
+        info!("Exporting invocation traces to {}", endpoint);
+    }
+    let bind_address = config.bind_address()?;
 
-    // Initialize components
-    let vm_manager = Arc::new(VmManager::new().await?);
-    let function_store = Arc::new(FunctionStore::new());
-    let vm_pool = Arc::new(VmPool::new(vm_manager.clone()).await?);
+    let state = build_state(config.clone()).await?;
+    let shutdown = state.shutdown.clone();
+    let generations = state.generations.clone();
+    let reserved_vms = state.reserved_vms.clone();
+    let work_dirs = state.work_dirs.clone();
 
-    let state = AppState {
-        vm_manager,
-        function_store,
-        vm_pool,
+    spawn_config_reloader(state.clone())?;
+    spawn_shutdown_signals(shutdown.clone())?;
+    if config.execution.mode == ExecutionMode::Local {
+        warn!("Running functions IN-PROCESS with no VM isolation; for local development only");
+        state.warmup.open();
+    } else {
+        spawn_pool_warmup(state.clone());
+        spawn_reserve_refill(state.clone());
+        spawn_idle_pinger(state.clone());
+        spawn_demand_forecaster(state.clone());
+    }
+    spawn_saturation_monitor(state.clone());
+    if config.cache.enabled() {
+        spawn_cache_sweeper(state.clone());
+    }
+    if config.functions.deleted_retention().is_some() {
+        spawn_recycle_bin_purger(state.clone());
+    }
+    spawn_scheduler(state.clone());
+
+    // gRPC shares the state, so it drains on the same shutdown signal
+    #[cfg(feature = "grpc")]
+    let grpc_server = match config.grpc_address()? {
+        Some(address) => {
+            let listener = TcpListener::bind(address).await?;
+            info!("gRPC listening on {}", address);
+            let state = state.clone();
+            Some(tokio::spawn(async move {
+                if let Err(e) = grpc::serve(state, listener).await {
+                    error!("{:#}", e);
+                }
+            }))
+        }
+        None => None,
     };
 
-    // Build router
+    // Build router. Un-namespaced function routes use the default namespace.
     let app = Router::new()
         .route("/health", get(health_check))
-        .route("/api/v1/functions", get(list_functions))
-        .route("/api/v1/functions", post(create_function))
-        .route("/api/v1/functions/:name/invoke", post(invoke_function))
+        .route("/ready", get(readiness))
+        .route("/info", get(platform_info))
+        .route("/version", get(version))
+        .route("/metrics", get(render_metrics))
+        .nest("/api/v1/functions", function_routes(&state))
+        .nest("/api/v1/namespaces/:namespace/functions", function_routes(&state))
+        .route(
+            "/api/v1/code/:hash/invoke",
+            post(invoke_by_hash)
+                .layer(DefaultBodyLimit::max(config.invoke.max_payload_bytes))
+                .layer(middleware::from_fn_with_state(state.clone(), rate_limit)),
+        )
+        .route("/api/v1/events", get(stream_events))
         .route("/api/v1/advanced/vms", get(list_vms))
+        .route("/api/v1/advanced/vms/healthcheck", post(healthcheck_vms))
+        .route("/api/v1/advanced/pool", get(pool_stats))
+        .route("/api/v1/advanced/pool/scale", post(scale_pool))
+        .route("/api/v1/advanced/pool/flush", post(flush_pool))
+        .route("/api/v1/admin/shutdown", post(admin_shutdown))
+        .route("/api/v1/admin/selftest", post(admin_selftest))
+        .layer(middleware::map_response_with_state(state.clone(), sign_response))
+        .layer(middleware::map_response(method_not_allowed))
+        .layer(compression_layer())
+        .layer(config.cors.layer()?)
         .with_state(state);
 
     // Start server
-    let listener = TcpListener::bind("0.0.0.0:8090").await?;
-    info!("Hyperdrive Rust listening on :8090");
-    
-    axum::serve(listener, app).await?;
+    let listener = TcpListener::bind(bind_address).await?;
+    info!("Hyperdrive Rust listening on {}", bind_address);
+
+    // On shutdown the listener closes and in-flight requests run to
+    // completion before the pool is torn down
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await?;
+    #[cfg(feature = "grpc")]
+    if let Some(server) = grpc_server {
+        if let Err(e) = server.await {
+            error!("gRPC server task failed: {}", e);
+        }
+    }
+
+    info!("In-flight requests drained, shutting down VM pool");
+    for vm in reserved_vms.excess(0) {
+        if let Some(generation) = vm.generation.and_then(|number| generations.get(number)) {
+            generation.backend.pool.release(vm).await;
+        }
+    }
+    for generation in generations.all() {
+        generation.backend.pool.shutdown().await;
+    }
+    if let Err(e) = work_dirs.reap_orphans() {
+        warn!("Failed to clean up VM work dirs: {:#}", e);
+    }
+    if let Some(provider) = tracer_provider {
+        // Flushing blocks on the exporter
+        let flushed = tokio::task::spawn_blocking(move || provider.shutdown()).await;
+        if let Ok(Err(e)) = flushed {
+            warn!("Failed to flush traces: {}", e);
+        }
+    }
+    info!("Hyperdrive Rust stopped");
+    Ok(())
+}
+
+// Initialize components. Work dirs still around are from VMs of a run that
+// didn't shut down cleanly.
+async fn build_state(config: Arc<Config>) -> Result<AppState> {
+    let work_dirs = Arc::new(WorkDirs::new(&config.vm.work_dir_base));
+    match work_dirs.reap_orphans() {
+        Ok(0) => {}
+        Ok(reaped) => info!("Removed {} stale VM work dirs from {}", reaped, config.vm.work_dir_base),
+        Err(e) => warn!("Failed to clean up stale VM work dirs: {:#}", e),
+    }
+    let vm_manager = Arc::new(VmManager::new(config.vm.clone()).await?);
+    let events = EventBus::new();
+    let function_store = Arc::new(
+        FunctionStore::with_config(&config.functions)
+            .with_max_payload_bytes(config.invoke.max_payload_bytes)
+            .with_events(events.clone()),
+    );
+    let failed_vms = Arc::new(FailedVms::new());
+    let boot_backoff = Arc::new(BootBackoff::new(failed_vms.clone()));
+    let boot_limiter = Arc::new(BootLimiter::new(config.pool.max_concurrent_boots));
+    let vm_pool = VmPool::new(vm_manager.clone(), config.pool.clone(), boot_backoff.clone(), boot_limiter.clone());
+    let metrics = Arc::new(Metrics::new()?);
+    let audit_log = Arc::new(AuditLog::from_config(&config.audit)?);
+    let runtime_info = Arc::new(RuntimeInfo::gather(&config.vm).await);
+    info!("Runtime: {:?}", runtime_info);
+
+    Ok(AppState {
+        config: config.clone(),
+        tunables: Arc::new(ArcSwap::from_pointee(config.tunables())),
+        generations: Arc::new(Generations::new(VmBackend {
+            config: config.vm.clone(),
+            manager: vm_manager,
+            pool: vm_pool,
+        })),
+        work_dirs: work_dirs.clone(),
+        function_store,
+        v8_client: config.vm.v8_host_client().context("Failed to build V8 host client")?,
+        acquire_tracker: Arc::new(AcquireTracker::new()),
+        fair_queue: Arc::new(FairQueue::new(config.pool.capacity(), config.pool.max_waiting)),
+        saturation: Arc::new(SaturationTracker::new()),
+        demand: Arc::new(DemandForecast::new()),
+        boot_backoff,
+        failed_vms,
+        boot_limiter,
+        schedules: Arc::new(ScheduleTracker::new()),
+        unhealthy_vms: Arc::new(UnhealthyVms::new()),
+        flushed_vms: Arc::new(FlushedVms::new()),
+        reserved_vms: Arc::new(ReservedVms::new()),
+        warmup: Arc::new(WarmupGate::new()),
+        metrics,
+        audit_log,
+        result_cache: Arc::new(ResultCache::new(&config.cache)),
+        breakers: Arc::new(CircuitBreakers::new(&config.breaker)),
+        rate_limiter: Arc::new(RateLimiter::new(&config.rate_limit)),
+        signer: ResponseSigner::from_config(&config.signing).map(Arc::new),
+        output_policy: Arc::new(OutputPolicy::new(&config.output_policy)?),
+        trusted_proxies: Arc::new(
+            TrustedProxies::new(&config.server.trusted_proxies).map_err(anyhow::Error::msg)?,
+        ),
+        events,
+        usage_stats: Arc::new(UsageStats::new()),
+        shutdown: CancellationToken::new(),
+        code_fetcher: Arc::new(CodeFetcher::new(&config.code_url)?),
+        runtime_info,
+    })
+}
+
+fn function_routes(state: &AppState) -> Router<AppState> {
+    let config = &state.config;
+    let rate_limited = || middleware::from_fn_with_state(state.clone(), rate_limit);
+    Router::new()
+        .route("/", get(list_functions))
+        .route("/", post(create_function))
+        .route("/export", get(export_functions))
+        .route("/scheduled", get(list_scheduled))
+        .route("/deleted", get(list_deleted_functions))
+        .route(
+            "/import",
+            post(import_functions).layer(DefaultBodyLimit::max(config.functions.max_import_bytes)),
+        )
+        .route("/:name", get(get_function))
+        .route("/:name", delete(delete_function))
+        .route("/:name/code", get(get_function_code))
+        .route("/:name/stats", get(function_stats))
+        .route("/:name/versions", get(list_function_versions))
+        .route("/:name/traffic", get(get_traffic_split))
+        .route("/:name/traffic", put(set_traffic_split))
+        .route(
+            "/:name/invoke",
+            post(invoke_function)
+                .layer(DefaultBodyLimit::max(config.invoke.max_payload_bytes))
+                .layer(rate_limited()),
+        )
+        .route(
+            "/:name/invoke/batch",
+            post(invoke_function_batch)
+                .layer(DefaultBodyLimit::max(config.invoke.max_batch_bytes))
+                .layer(middleware::from_fn_with_state(state.clone(), batch_rate_limit)),
+        )
+        .route(
+            "/:name/test",
+            post(test_function).layer(DefaultBodyLimit::max(config.invoke.max_payload_bytes)),
+        )
+        .route("/:name/replay/:invocation_id", post(replay_invocation))
+        .route("/:name/clone", post(clone_function))
+        .route("/:name/warmup", post(warmup_function))
+        .route("/:name/disable", post(disable_function))
+        .route("/:name/enable", post(enable_function))
+        .route("/:name/restore", post(restore_function))
+}
+
+// Count an invocation against its function's rate limit, or its code hash
+// for invocations by hash, and report the caller's standing in
+// X-RateLimit-* headers on every response, rejections included
+async fn rate_limit(
+    State(state): State<AppState>,
+    Path(params): Path<HashMap<String, String>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    charge_rate_limit(&state, &rate_limit_key(&params), 1, request, next).await
+}
+
+// A batch counts one call per payload, so batching can't be used to get
+// around the limit. Bodies the handler will reject, not a JSON array or
+// over MAX_BATCH_SIZE, count as one call.
+async fn batch_rate_limit(
+    State(state): State<AppState>,
+    Path(params): Path<HashMap<String, String>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.config.rate_limit.invocations == 0 {
+        return next.run(request).await;
+    }
+    let (parts, body) = request.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, state.config.invoke.max_batch_bytes).await else {
+        return ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "Batch body is too large").into_response();
+    };
+    let calls = match serde_json::from_slice::<Vec<serde::de::IgnoredAny>>(&body) {
+        Ok(payloads) if (1..=MAX_BATCH_SIZE).contains(&payloads.len()) => payloads.len(),
+        _ => 1,
+    };
+    let request = Request::from_parts(parts, Body::from(body));
+    charge_rate_limit(&state, &rate_limit_key(&params), calls as u32, request, next).await
+}
+
+fn rate_limit_key(params: &HashMap<String, String>) -> String {
+    match (params.get("hash"), params.get("name")) {
+        (Some(hash), _) => format!("code:{}", hash.to_ascii_lowercase()),
+        (None, name) => format!(
+            "{}/{}",
+            params.get("namespace").map_or(function::DEFAULT_NAMESPACE, String::as_str),
+            name.map_or("", String::as_str)
+        ),
+    }
+}
+
+async fn charge_rate_limit(state: &AppState, key: &str, calls: u32, request: Request, next: Next) -> Response {
+    let Some(status) = state.rate_limiter.charge(key, calls) else {
+        return next.run(request).await;
+    };
+
+    let mut response = if status.allowed {
+        next.run(request).await
+    } else {
+        warn!("Rate limit exceeded for {}", key);
+        ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("Rate limit of {} invocations exceeded for {}", status.limit, key),
+        )
+        .retry_after(status.reset_secs().max(1))
+        .into_response()
+    };
+    status.apply(response.headers_mut());
+    response
+}
+
+// Boot the warm pool before admitting invocations. Acquires wait on the gate
+// until this finishes; if VMs fail to boot the gate opens anyway and
+// invocations take their chances with the pool.
+fn spawn_pool_warmup(state: AppState) {
+    tokio::spawn(async move {
+        let target = state.tunables.load().pool.min_vms;
+        let started = std::time::Instant::now();
+        let booted = state.generations.current().backend.pool.fill(target).await;
+        state.warmup.open();
+        info!("Warm pool ready: {}/{} VMs in {:?}", booted, target, started.elapsed());
+    });
+}
+
+// Hold pool.premium_reserved_vms warm VMs out of the shared pool for
+// premium functions. Tops up once warm-up is done, whenever a premium
+// invocation takes one, and on a timer to replace any that failed; a
+// reload that lowers the target hands the excess back to the pool.
+fn spawn_reserve_refill(state: AppState) {
+    tokio::spawn(async move {
+        state.warmup.wait().await;
+        loop {
+            let target = state.tunables.load().pool.premium_reserved_vms;
+            for vm in state.reserved_vms.excess(target) {
+                return_to_pool(&state, vm, true).await;
+            }
+            while state.reserved_vms.count() < target {
+                let vm = tokio::select! {
+                    vm = checkout_vm(&state, None) => vm,
+                    _ = state.shutdown.cancelled() => return,
+                };
+                match vm {
+                    Ok(vm) => {
+                        if let Some(vm) = state.reserved_vms.offer(vm, target) {
+                            return_to_pool(&state, vm, true).await;
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Failed to reserve a VM for premium functions: {:#}", e);
+                        break;
+                    }
+                }
+            }
+
+            tokio::select! {
+                _ = state.reserved_vms.wait_taken() => {}
+                _ = tokio::time::sleep(RESERVE_REFILL_INTERVAL) => {}
+                _ = state.shutdown.cancelled() => return,
+            }
+        }
+    });
+}
+
+// Reload hot-tunable settings on SIGHUP. Restarting would throw away the
+// warm pool, so pool limits and timeouts are swapped in place and new VM
+// boot settings (kernel, rootfs, sizing) roll the pool to a new generation;
+// anything else that changed is reported and left as-is until the next
+// restart.
+fn spawn_config_reloader(state: AppState) -> Result<()> {
+    let mut hangup = signal(SignalKind::hangup()).context("Failed to install SIGHUP handler")?;
+
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading configuration");
+
+            let new_config = match Config::load() {
+                Ok(config) => config,
+                Err(e) => {
+                    error!("Config reload failed, keeping current settings: {:#}", e);
+                    continue;
+                }
+            };
+
+            for section in state.config.restart_required_changes(&new_config) {
+                warn!("Config section [{}] changed but requires a restart; ignored on reload", section);
+            }
+
+            let tunables = new_config.tunables();
+            let limits = warm_limits(&state, &tunables.pool);
+            state.generations.current().backend.pool.set_limits(limits).await;
+            state.fair_queue.set_limits(tunables.pool.capacity(), tunables.pool.max_waiting);
+            state.boot_limiter.set_limit(tunables.pool.max_concurrent_boots);
+            state.tunables.store(Arc::new(tunables));
+
+            // Work dirs and V8 host connections stay as they were started
+            let current = state.generations.current().backend.config.clone();
+            let vm_config = current.with_boot_settings(&new_config.vm);
+            if vm_config != current && state.config.execution.mode == ExecutionMode::Vm {
+                if let Err(e) = roll_generation(&state, vm_config).await {
+                    error!("Failed to roll VMs to the new vm settings, keeping the current ones: {:#}", e);
+                }
+            }
+            info!("Configuration reloaded");
+        }
+    });
+
+    Ok(())
+}
+
+// Samples pool saturation so operators hear about sustained pressure
+// before acquires start timing out
+fn spawn_saturation_monitor(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SATURATION_SAMPLE_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let vms = list_active_vms(&state).await.unwrap_or_default();
+            let busy = vms.iter().filter(|vm| vm.state == VmState::Busy).count();
+            let pool = state.tunables.load().pool.clone();
+            let window = pool.saturation_window();
+
+            let alert = state.saturation.record(
+                busy,
+                pool.max_vms,
+                std::time::Instant::now(),
+                window,
+                pool.saturation_threshold,
+            );
+            match alert {
+                Some(SaturationAlert::Raised { average }) => warn!(
+                    "Pool saturated: {:.0}% of {} VMs busy on average over {:?} (threshold {:.0}%)",
+                    average * 100.0,
+                    pool.max_vms,
+                    window,
+                    pool.saturation_threshold * 100.0
+                ),
+                Some(SaturationAlert::Cleared { average }) => {
+                    info!("Pool saturation cleared: {:.0}% busy over {:?}", average * 100.0, window)
+                }
+                None => {}
+            }
+            state.metrics.set_pool_saturation(state.saturation.stats(window).window_average);
+        }
+    });
+}
+
+// Fold recent invocations into the demand forecast and, with predictive
+// warming on, move the warm pool to the size it calls for
+fn spawn_demand_forecaster(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(FORECAST_INTERVAL);
+        let mut applied = None;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = state.shutdown.cancelled() => break,
+            }
+            let pool = state.tunables.load().pool.clone();
+            let now = std::time::Instant::now();
+            state.demand.tick(now, pool.predictive_half_life(), pool.predictive_margin, pool.vm_concurrency);
+            if !pool.predictive_warming {
+                applied = None;
+                continue;
+            }
+
+            let limits = warm_limits(&state, &pool);
+            if applied != Some(limits.min_vms) {
+                info!("Predicted warm pool target: {} VMs", limits.min_vms);
+                applied = Some(limits.min_vms);
+                state.generations.current().backend.pool.set_limits(limits).await;
+            }
+        }
+    });
+}
+
+// The pool limits in force: pool.min_vms is the warm target unless
+// predictive warming raises it to cover recent demand
+fn warm_limits(state: &AppState, pool: &PoolConfig) -> PoolConfig {
+    let min_vms = if pool.predictive_warming {
+        state.demand.target(pool.min_vms, pool.max_vms)
+    } else {
+        pool.min_vms
+    };
+    PoolConfig {
+        min_vms,
+        ..pool.clone()
+    }
+}
+
+// Drops expired cached results that are never looked up again, which
+// would otherwise sit in memory until the cache filled up
+fn spawn_cache_sweeper(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(state.config.cache.sweep_interval());
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = state.shutdown.cancelled() => break,
+            }
+            let swept = state.result_cache.sweep();
+            if swept > 0 {
+                debug!("Swept {} expired cached results", swept);
+            }
+        }
+    });
+}
+
+// Purges soft-deleted functions once functions.deleted_retention_secs has
+// passed since their deletion
+fn spawn_recycle_bin_purger(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RECYCLE_BIN_SWEEP_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = state.shutdown.cancelled() => break,
+            }
+            let purged = state.function_store.purge_deleted().await;
+            if purged > 0 {
+                debug!("Purged {} functions from the recycle bin", purged);
+            }
+        }
+    });
+}
+
+// Health-ping idle VMs now and then. A V8 host's keep-alive connection can
+// die quietly while its VM sits in the pool, leaving the next invocation to
+// reconnect or fail; pinging through the shared client keeps the connection
+// warm and finds dead VMs before traffic does. Those are marked so acquire
+// reaps them and the pool boots replacements.
+fn spawn_idle_pinger(state: AppState) {
+    tokio::spawn(async move {
+        loop {
+            // Re-read each round so a reload can change or disable it
+            let interval = state.tunables.load().pool.idle_ping_interval();
+            tokio::select! {
+                _ = tokio::time::sleep(interval.unwrap_or(IDLE_PING_DISABLED_RECHECK)) => {}
+                _ = state.shutdown.cancelled() => break,
+            }
+            if interval.is_none() {
+                continue;
+            }
+
+            let vms = list_active_vms(&state).await.unwrap_or_default();
+            let live = vms.iter().filter_map(|vm| vm.id.parse().ok()).collect();
+            state.unhealthy_vms.retain(&live);
+            state.flushed_vms.retain(&live);
+
+            let client = &state.v8_client;
+            let idle = vms.iter().filter(|vm| vm.state == VmState::Ready);
+            let pings = idle.map(|vm| async move { (vm, vm.ping(client, VM_PING_TIMEOUT).await) });
+            for (vm, alive) in future::join_all(pings).await {
+                if alive {
+                    continue;
+                }
+                warn!("Idle VM {} failed health ping; it will be replaced", vm.id);
+                if let Ok(id) = vm.id.parse() {
+                    state.unhealthy_vms.mark(id);
+                }
+            }
+        }
+    });
+}
+
+// Invoke scheduled functions as their cron expressions come due. Runs go
+// through the same path as API invocations, so they're audited, counted and
+// published like any other; they stop once the server starts draining.
+fn spawn_scheduler(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SCHEDULER_TICK);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = state.shutdown.cancelled() => break,
+            }
+
+            let functions = state.function_store.list_scheduled().await;
+            for function in state.schedules.due(&functions, chrono::Utc::now()) {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let payload = function.schedule_payload.clone().unwrap_or_else(|| serde_json::json!({}));
+                    info!("Running scheduled invocation of {}", function.qualified_name());
+                    let outcome =
+                        run_audited(&state, &function, payload, None, Priority::Normal, InvocationKind::Live).await;
+                    if let Err(e) = &outcome {
+                        warn!("Scheduled invocation of {} failed: {:#}", function.qualified_name(), e);
+                    }
+                    state.schedules.finish(&function.namespace, &function.name, outcome.is_ok());
+                });
+            }
+        }
+    });
+}
+
+// SIGTERM and Ctrl-C begin a graceful drain, same as the admin endpoint
+fn spawn_shutdown_signals(shutdown: CancellationToken) -> Result<()> {
+    let mut terminate = signal(SignalKind::terminate()).context("Failed to install SIGTERM handler")?;
+    let mut interrupt = signal(SignalKind::interrupt()).context("Failed to install SIGINT handler")?;
+
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = terminate.recv() => info!("Received SIGTERM, draining"),
+            _ = interrupt.recv() => info!("Received SIGINT, draining"),
+        }
+        shutdown.cancel();
+    });
+
     Ok(())
 }
 
+// axum answers a known path with an unsupported method with a bare 405.
+// Give it the same JSON error body as the rest of the API, naming the
+// methods from the `Allow` header so clients can see what to use instead.
+async fn method_not_allowed(response: Response) -> Response {
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return response;
+    }
+
+    let allow = response.headers().get(header::ALLOW).cloned();
+    let supported = allow
+        .as_ref()
+        .and_then(|allow| allow.to_str().ok())
+        .unwrap_or_default()
+        .replace(',', ", ");
+    let mut rewritten = ApiError::new(
+        StatusCode::METHOD_NOT_ALLOWED,
+        format!("Method not allowed; supported methods: {}", supported),
+    )
+    .into_response();
+    if let Some(allow) = allow {
+        rewritten.headers_mut().insert(header::ALLOW, allow);
+    }
+    rewritten
+}
+
+// gzip/brotli compression negotiated via Accept-Encoding. Event streams are
+// excluded so compression buffering never delays streamed messages.
+fn compression_layer() -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new().compress_when(
+        SizeAbove::new(MIN_COMPRESSED_SIZE)
+            .and(NotForContentType::GRPC)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::SSE),
+    )
+}
+
+// Readiness probe: 503 until the warm pool has booted, so load balancers
+// hold traffic during startup
+async fn readiness(State(state): State<AppState>) -> (StatusCode, Json<ReadinessResponse>) {
+    if state.shutdown.is_cancelled() {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ReadinessResponse { status: "draining".to_string() }),
+        )
+    } else if state.warmup.is_open() {
+        (StatusCode::OK, Json(ReadinessResponse { status: "ready".to_string() }))
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ReadinessResponse { status: "warming".to_string() }),
+        )
+    }
+}
+
+// Engine and image versions functions run against, for compatibility
+// debugging
+async fn platform_info(State(state): State<AppState>) -> Json<InfoResponse> {
+    Json(InfoResponse {
+        platform: "hyperdrive-rust".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        runtime: (*state.runtime_info).clone(),
+    })
+}
+
+// Set by build.rs
+async fn version() -> Json<VersionResponse> {
+    let build_timestamp = env!("HYPERDRIVE_BUILD_TIMESTAMP")
+        .parse()
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map_or_else(|| "unknown".to_string(), |at| at.to_rfc3339());
+
+    Json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_commit: env!("HYPERDRIVE_GIT_COMMIT").to_string(),
+        build_timestamp,
+        rustc_version: env!("HYPERDRIVE_RUSTC_VERSION").to_string(),
+    })
+}
+
 // Health check endpoint
-async fn health_check() -> Json<HealthResponse> {
-    Json(HealthResponse {
+async fn health_check(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    let outage = boot_outage(&state).await;
+    let (status, health) = match outage {
+        Some(_) => (StatusCode::SERVICE_UNAVAILABLE, "unhealthy"),
+        None => (StatusCode::OK, "healthy"),
+    };
+    let response = HealthResponse {
         platform: "hyperdrive-rust".to_string(),
-        status: "healthy".to_string(),
-        version: "0.1.0".to_string(),
+        status: health.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
         timestamp: chrono::Utc::now().to_rfc3339(),
         components: HealthComponents {
-            firecracker: true,
+            firecracker: outage.is_none(),
             dns: true,
             ssl: true,
             cdn: true,
             monitoring: true,
         },
+        diagnostic: outage.map(|reason| format!("no VMs could be booted: {}", reason)),
+    };
+    (status, Json(response))
+}
+
+// Why the pool can't serve anything, when every recent boot has failed and
+// no VM is left running. That's a broken image or host (missing kernel,
+// no KVM) rather than load, so it's reported as such instead of as
+// timeouts.
+async fn boot_outage(state: &AppState) -> Option<String> {
+    let reason = state.boot_backoff.outage(BOOT_OUTAGE_FAILURES)?;
+    let vms = list_active_vms(state).await.unwrap_or_default();
+    let serving = vms.iter().any(|vm| matches!(vm.state, VmState::Ready | VmState::Busy));
+    (!serving).then_some(reason)
+}
+
+// Prometheus metrics
+async fn render_metrics(State(state): State<AppState>) -> Result<String, StatusCode> {
+    state.metrics.set_cache_size(state.result_cache.count(), state.result_cache.bytes());
+    state.metrics.render().map_err(|e| {
+        error!("Failed to render metrics: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
     })
 }
 
 // List functions
-async fn list_functions(State(state): State<AppState>) -> Json<FunctionListResponse> {
-    let functions = state.function_store.list().await;
-    Json(FunctionListResponse { functions })
+async fn list_functions(
+    State(state): State<AppState>,
+    Path(path): Path<NamespacePath>,
+    Query(query): Query<ListFunctionsQuery>,
+) -> Result<Json<FunctionListResponse>, ApiError> {
+    let mut functions = match query.tag.as_deref() {
+        Some(tag) => {
+            let (key, value) = tag.split_once(':').ok_or_else(|| {
+                ApiError::new(StatusCode::BAD_REQUEST, "Tag filter must be in the form key:value")
+            })?;
+            state.function_store.list_tagged(&path.namespace, key, value).await
+        }
+        None => state.function_store.list(&path.namespace).await,
+    };
+
+    // The store iterates in hash order; sort so listings are stable between
+    // calls. Names are unique within a namespace, so they break ties.
+    functions.sort_by(|a, b| match query.sort {
+        FunctionSort::Name => a.name.cmp(&b.name),
+        FunctionSort::CreatedAt => a.created_at.cmp(&b.created_at).then_with(|| a.name.cmp(&b.name)),
+    });
+    if query.order == SortOrder::Desc {
+        functions.reverse();
+    }
+
+    Ok(Json(FunctionListResponse { functions }))
 }
 
-// Create function
+// Create function, answering 201 with the new function's Location
 async fn create_function(
     State(state): State<AppState>,
-    Json(request): Json<CreateFunctionRequest>,
-) -> Result<Json<CreateFunctionResponse>, StatusCode> {
-    match state.function_store.create(request).await {
-        Ok(function) => Ok(Json(CreateFunctionResponse { 
-            name: function.name,
-            created: true,
-        })),
+    Path(path): Path<NamespacePath>,
+    headers: HeaderMap,
+    request: Result<Json<CreateFunctionRequest>, JsonRejection>,
+) -> Result<(StatusCode, HeaderMap, Json<CreateFunctionResponse>), ApiError> {
+    let mut request = json_body(request)?;
+    authorize_priority(&state.config, &headers, request.max_priority)?;
+    if let Some(code_url) = request.code_url.take() {
+        if !request.code.is_empty() {
+            return Err(code_source_error());
+        }
+        request.code = state.code_fetcher.fetch(&code_url).await.map_err(|e| {
+            warn!("Failed to fetch code for {}: {}", request.name, e);
+            let status = match e {
+                CodeFetchError::Fetch(_) => StatusCode::BAD_GATEWAY,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            ApiError::new(status, e.to_string())
+        })?;
+    } else if request.code.is_empty() {
+        return Err(code_source_error());
+    }
+    ensure_signable(&state, request.sign_responses)?;
+
+    match state.function_store.create(&path.namespace, request).await {
+        Ok(function) => {
+            spawn_prime(&state, &function);
+            Ok(created_response(function))
+        }
         Err(e) => {
             error!("Failed to create function: {}", e);
-            Err(StatusCode::BAD_REQUEST)
+            let status = match e.downcast_ref::<QuotaExceeded>() {
+                Some(_) => StatusCode::FORBIDDEN,
+                None => StatusCode::BAD_REQUEST,
+            };
+            Err(ApiError::with_body(status, validation_error(&e)))
         }
     }
 }
 
-// Invoke function
-async fn invoke_function(
+// Copy a function's latest version under a new name, to experiment on a
+// variant without re-uploading its code
+async fn clone_function(
     State(state): State<AppState>,
-    Path(name): Path<String>,
-    Json(payload): Json<serde_json::Value>,
-) -> Result<Json<InvokeResponse>, StatusCode> {
-    info!("Invoking function: {}", name);
-
-    // Get function
-    let function = match state.function_store.get(&name).await {
-        Some(f) => f,
-        None => {
-            warn!("Function not found: {}", name);
-            return Err(StatusCode::NOT_FOUND);
+    Path(path): Path<FunctionPath>,
+    request: Result<Json<CloneFunctionRequest>, JsonRejection>,
+) -> Result<(StatusCode, HeaderMap, Json<CreateFunctionResponse>), ApiError> {
+    let target = json_body(request)?.name;
+    match state.function_store.clone_function(&path.namespace, &path.name, &target).await {
+        Ok(Some(function)) => {
+            spawn_prime(&state, &function);
+            Ok(created_response(function))
         }
-    };
-
-    // Get VM from pool
-    let vm = match state.vm_pool.acquire().await {
-        Ok(vm) => vm,
+        Ok(None) => Err(StatusCode::NOT_FOUND.into()),
         Err(e) => {
-            error!("Failed to acquire VM: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            warn!("Failed to clone {}/{} to {}: {}", path.namespace, path.name, target, e);
+            let status = if e.is::<AlreadyExists>() {
+                StatusCode::CONFLICT
+            } else if e.is::<QuotaExceeded>() {
+                StatusCode::FORBIDDEN
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            Err(ApiError::with_body(status, validation_error(&e)))
         }
+    }
+}
+
+// 201 for a newly stored function, pointing at it with Location
+fn created_response(function: Function) -> (StatusCode, HeaderMap, Json<CreateFunctionResponse>) {
+    // Lint and measure what the author wrote, not the transpiled output
+    let source = function.source.as_deref().unwrap_or(&function.code);
+    let warnings = function::lint(source);
+    let code = CodeBudget::measure(source);
+
+    let name = encode_path_segment(&function.name);
+    let location = match function.namespace.as_str() {
+        function::DEFAULT_NAMESPACE => format!("/api/v1/functions/{}", name),
+        namespace => format!("/api/v1/namespaces/{}/functions/{}", encode_path_segment(namespace), name),
     };
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::LOCATION,
+        HeaderValue::from_str(&location).expect("an encoded path is a valid header value"),
+    );
+
+    (
+        StatusCode::CREATED,
+        headers,
+        Json(CreateFunctionResponse {
+            namespace: function.namespace,
+            name: function.name,
+            created: true,
+            status: function.status.get(),
+            warnings,
+            code,
+        }),
+    )
+}
+
+// Validation limits names to alphanumerics, `-` and `_`, but alphanumerics
+// include non-ASCII letters, which are percent-encoded as UTF-8
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+// Scheduled functions in the namespace and when they next run
+async fn list_scheduled(
+    State(state): State<AppState>,
+    Path(path): Path<NamespacePath>,
+) -> Json<ScheduleListResponse> {
+    Json(ScheduleListResponse {
+        functions: state.schedules.list(&path.namespace),
+    })
+}
+
+// Export every function in the namespace as a single document
+async fn export_functions(
+    State(state): State<AppState>,
+    Path(path): Path<NamespacePath>,
+) -> Json<FunctionExport> {
+    let functions = state.function_store.export(&path.namespace).await;
+    info!("Exported {} functions from namespace {}", functions.len(), path.namespace);
+    Json(FunctionExport {
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        functions,
+    })
+}
+
+// Import an exported document, reporting the outcome per function
+async fn import_functions(
+    State(state): State<AppState>,
+    Path(path): Path<NamespacePath>,
+    Query(query): Query<ImportQuery>,
+    headers: HeaderMap,
+    document: Result<Json<FunctionExport>, JsonRejection>,
+) -> Result<Json<ImportResponse>, ApiError> {
+    let document = json_body(document)?;
+    let max_priority = document.functions.iter().map(|exported| exported.function.max_priority).max();
+    authorize_priority(&state.config, &headers, max_priority.unwrap_or_default())?;
+    ensure_signable(&state, document.functions.iter().any(|exported| exported.function.sign_responses))?;
+    info!(
+        "Importing {} functions into namespace {} (overwrite: {})",
+        document.functions.len(),
+        path.namespace,
+        query.overwrite
+    );
 
-    // Execute function
-    match vm.execute_function(&function, payload).await {
-        Ok(result) => {
-            // Return VM to pool
-            state.vm_pool.release(vm).await;
-            Ok(Json(InvokeResponse { result }))
+    let results = state
+        .function_store
+        .import(&path.namespace, document.functions, query.overwrite)
+        .await;
+    for result in &results {
+        if let ImportOutcome::Imported { version } = result.outcome {
+            if let Some(function) = state.function_store.get_version(&path.namespace, &result.name, version).await {
+                spawn_prime(&state, &function);
+            }
         }
+    }
+    Ok(Json(ImportResponse { results }))
+}
+
+// Every create path checks this, so no stored function asks for signatures
+// that can't be made
+fn ensure_signable(state: &AppState, sign_responses: bool) -> Result<(), ApiError> {
+    if sign_responses && state.signer.is_none() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "sign_responses requires signing.key to be configured",
+        ));
+    }
+    Ok(())
+}
+
+fn code_source_error() -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, "Provide exactly one of code or code_url")
+}
+
+// Error body for a rejected function, with the source position when the
+// TypeScript compiler reported one, or a machine-readable code for
+// validation failures
+fn validation_error(e: &anyhow::Error) -> ErrorResponse {
+    if let Some(transpile) = e.downcast_ref::<TranspileError>() {
+        return ErrorResponse {
+            error: transpile.message.clone(),
+            line: Some(transpile.line),
+            column: Some(transpile.column),
+            code: None,
+            details: Vec::new(),
+        };
+    }
+    match e.downcast_ref::<ValidationError>() {
+        Some(invalid) => ErrorResponse {
+            code: Some(invalid.kind),
+            ..ErrorResponse::new(invalid.message.clone())
+        },
+        None => ErrorResponse::new(e.to_string()),
+    }
+}
+
+// Get a function, latest version unless pinned. Honors If-None-Match so
+// pollers can skip unchanged definitions.
+async fn get_function(
+    State(state): State<AppState>,
+    Path(path): Path<FunctionPath>,
+    Query(query): Query<InvokeQuery>,
+    request_headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let store = &state.function_store;
+    let function = match query.version {
+        Some(version) => store.get_version(&path.namespace, &path.name, version).await,
+        None => store.get(&path.namespace, &path.name).await,
+    }
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Serialized once so the ETag is the hash of exactly the body sent
+    let body = serde_json::to_vec(&function).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let etag = body_etag(&body);
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::ETAG,
+        HeaderValue::from_str(&etag).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    );
+
+    if etag_matches(&request_headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok((headers, body).into_response())
+}
+
+// The JavaScript the V8 host runs, unwrapped, for inspecting and diffing
+// what is actually deployed
+async fn get_function_code(
+    State(state): State<AppState>,
+    Path(path): Path<FunctionPath>,
+    Query(query): Query<CodeQuery>,
+) -> Result<Response, StatusCode> {
+    let store = &state.function_store;
+    let function = match query.version {
+        Some(version) => store.get_version(&path.namespace, &path.name, version).await,
+        None => store.get(&path.namespace, &path.name).await,
+    }
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/javascript; charset=utf-8"),
+    );
+    headers.insert("x-function-version", HeaderValue::from(function.version));
+    if query.download {
+        // Names are alphanumeric plus `-`/`_`, but may be non-ASCII, which
+        // header values can't carry
+        let filename: String = function
+            .name
+            .chars()
+            .map(|c| if c.is_ascii() { c } else { '_' })
+            .collect();
+        let disposition = format!("attachment; filename=\"{}.js\"", filename);
+        headers.insert(
+            header::CONTENT_DISPOSITION,
+            HeaderValue::from_str(&disposition).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        );
+    }
+
+    Ok((headers, function.code).into_response())
+}
+
+// If-None-Match may list several tags, weak or strong, or be `*`
+fn etag_matches(request_headers: &HeaderMap, etag: &str) -> bool {
+    request_headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+// Per-function stats, including the circuit breaker
+async fn function_stats(
+    State(state): State<AppState>,
+    Path(FunctionPath { namespace, name }): Path<FunctionPath>,
+) -> Result<Json<FunctionStatsResponse>, StatusCode> {
+    let versions = state
+        .function_store
+        .list_versions(&namespace, &name)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let latest = versions.last().ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(FunctionStatsResponse {
+        latest_version: latest.version,
+        retained_versions: versions.len(),
+        circuit: state.breakers.status(&latest.qualified_name()),
+        usage: state.usage_stats.summary(&latest.qualified_name()),
+        namespace,
+        name,
+    }))
+}
+
+// List retained versions of a function
+async fn list_function_versions(
+    State(state): State<AppState>,
+    Path(FunctionPath { namespace, name }): Path<FunctionPath>,
+) -> Result<Json<FunctionVersionsResponse>, StatusCode> {
+    match state.function_store.list_versions(&namespace, &name).await {
+        Some(versions) => Ok(Json(FunctionVersionsResponse { namespace, name, versions })),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+// Get the weighted traffic split between versions
+async fn get_traffic_split(
+    State(state): State<AppState>,
+    Path(FunctionPath { namespace, name }): Path<FunctionPath>,
+) -> Result<Json<TrafficSplitResponse>, StatusCode> {
+    match state.function_store.get_traffic_split(&namespace, &name).await {
+        Some(weights) => Ok(Json(TrafficSplitResponse { namespace, name, weights })),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+// Replace the weighted traffic split between versions
+async fn set_traffic_split(
+    State(state): State<AppState>,
+    Path(FunctionPath { namespace, name }): Path<FunctionPath>,
+    request: Result<Json<TrafficSplitRequest>, JsonRejection>,
+) -> Result<Json<TrafficSplitResponse>, ApiError> {
+    let request = json_body(request)?;
+    if state.function_store.get(&namespace, &name).await.is_none() {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    match state
+        .function_store
+        .set_traffic_split(&namespace, &name, request.weights.clone())
+        .await
+    {
+        Ok(()) => Ok(Json(TrafficSplitResponse {
+            namespace,
+            name,
+            weights: request.weights,
+        })),
         Err(e) => {
-            error!("Function execution failed: {}", e);
-            // VM might be corrupted, don't return to pool
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            error!("Failed to set traffic split: {}", e);
+            Err(StatusCode::BAD_REQUEST.into())
         }
     }
 }
 
-// List active VMs
-async fn list_vms(State(state): State<AppState>) -> Json<VmListResponse> {
-    let vms = state.vm_manager.list_active_vms().await;
-    Json(VmListResponse { vms })
-}
\ No newline at end of file
+// Invoke function. JSON bodies are parsed and passed as the event; any
+// other content type is streamed through to the V8 host untouched.
+async fn invoke_function(
+    State(state): State<AppState>,
+    Path(path): Path<FunctionPath>,
+    Query(query): Query<InvokeQuery>,
+    request: Request,
+) -> Result<Response, ApiError> {
+    info!("Invoking function: {}/{}", path.namespace, path.name);
+    ensure_accepting(&state)?;
+    let deadline = invocation_deadline(&state, request.headers(), &query)?;
+
+    let function = resolve_function(&state, &path, &query).await?;
+    invoke_resolved(&state, function, &query, request, deadline, InvocationKind::Live).await
+}
+
+// Invoke a function while developing it. Runs exactly like invoke, IP
+// rules included, but isn't rate limited or counted in the function's
+// stats, is flagged as a test in the audit log, and always includes the
+// invocation meta.
+async fn test_function(
+    State(state): State<AppState>,
+    Path(path): Path<FunctionPath>,
+    Query(query): Query<InvokeQuery>,
+    request: Request,
+) -> Result<Response, ApiError> {
+    info!("Test invoking function: {}/{}", path.namespace, path.name);
+    ensure_accepting(&state)?;
+    let deadline = invocation_deadline(&state, request.headers(), &query)?;
+
+    let function = resolve_function(&state, &path, &query).await?;
+    let query = InvokeQuery { meta: true, ..query };
+    invoke_resolved(&state, function, &query, request, deadline, InvocationKind::Test).await
+}
+
+// Re-run a logged invocation with exactly the payload it was given, after
+// any input transform, against the version it ran on unless ?version= picks
+// another. Admin only, and only for functions that opted in to payload
+// capture; the replay is audited with `replay_of` naming the original.
+async fn replay_invocation(
+    State(state): State<AppState>,
+    Path(path): Path<ReplayPath>,
+    Query(query): Query<InvokeQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    authorize_admin(&state.config, &headers)?;
+    info!("Replaying invocation {} of {}/{}", path.invocation_id, path.namespace, path.name);
+    ensure_accepting(&state)?;
+    let deadline = invocation_deadline(&state, &headers, &query)?;
+
+    let current = state
+        .function_store
+        .resolve(&path.namespace, &path.name)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if !current.audit_payloads {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            format!("{} doesn't capture payloads; enable audit_payloads to replay it", current.qualified_name()),
+        ));
+    }
+
+    if !state.audit_log.readable() {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "Replays read payloads back from the audit log, which needs audit.sink = \"file\"",
+        ));
+    }
+
+    let audit_log = state.audit_log.clone();
+    let invocation_id = path.invocation_id;
+    let found = match tokio::task::spawn_blocking(move || audit_log.find(invocation_id)).await {
+        Ok(found) => found,
+        Err(e) => Err(anyhow::anyhow!(e)),
+    };
+    let recorded = found
+        .map_err(|e| {
+            error!("Failed to search the audit log for {}: {:#}", invocation_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .filter(|recorded| recorded.namespace == path.namespace && recorded.function == path.name)
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                format!("No invocation {} of {} in the audit log", invocation_id, current.qualified_name()),
+            )
+        })?;
+    let Some(payload) = recorded.payload else {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            format!("Invocation {} was recorded without its payload", invocation_id),
+        ));
+    };
+
+    let version = query.version.unwrap_or(recorded.version);
+    let function = state
+        .function_store
+        .get_version(&path.namespace, &path.name, version)
+        .await
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                format!("Version {} of {} is no longer stored", version, current.qualified_name()),
+            )
+        })?;
+    ensure_enabled(&function)?;
+    ensure_ready(&function)?;
+    let priority = invocation_priority(&function, &headers)?;
+
+    let started = std::time::Instant::now();
+    let kind = InvocationKind::Replay(invocation_id);
+    let execution = run_audited(&state, &function, payload, deadline, priority, kind)
+        .await
+        .map_err(execution_error)?;
+    execution_response(&state, &function, execution, started, true)
+}
+
+// Invoke whichever stored version has exactly this code, independent of the
+// name it's stored under, so deployments can pin to the code itself
+async fn invoke_by_hash(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+    Query(query): Query<InvokeQuery>,
+    request: Request,
+) -> Result<Response, ApiError> {
+    info!("Invoking code: {}", hash);
+    ensure_accepting(&state)?;
+    let deadline = invocation_deadline(&state, request.headers(), &query)?;
+
+    let function = state.function_store.find_by_hash(&hash).await.ok_or_else(|| {
+        warn!("No function with code hash {}", hash);
+        ApiError::new(StatusCode::NOT_FOUND, format!("No function has code hash {}", hash))
+    })?;
+    invoke_resolved(&state, function, &query, request, deadline, InvocationKind::Live).await
+}
+
+async fn invoke_resolved(
+    state: &AppState,
+    function: Function,
+    query: &InvokeQuery,
+    request: Request,
+    deadline: Option<Deadline>,
+    kind: InvocationKind,
+) -> Result<Response, ApiError> {
+    ensure_enabled(&function)?;
+    // Replays are admin-only and re-run an invocation that was let in
+    if !matches!(kind, InvocationKind::Replay(_)) {
+        let peer = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0);
+        ensure_caller_allowed(state, &function, peer, request.headers())?;
+    }
+    ensure_ready(&function)?;
+    let priority = invocation_priority(&function, request.headers())?;
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    if let Some(content_type) = content_type.filter(|ct| !is_json(ct)) {
+        let body = request.into_body();
+        return invoke_streaming(state, &function, &content_type, body, deadline, priority, kind).await;
+    }
+
+    let request = limit_payload(request, function.max_payload_bytes).await?;
+    let mut payload = json_body(Json::<serde_json::Value>::from_request(request, state).await)?;
+    check_depth(state, &payload)?;
+    check_integers(state, &mut payload)?;
+    let payload = apply_transform(function.input_transform.as_ref(), payload);
+    check_input(&function, &payload)?;
+
+    let started = std::time::Instant::now();
+    let execution = run_audited(state, &function, payload, deadline, priority, kind)
+        .await
+        .map_err(execution_error)?;
+    execution_response(state, &function, execution, started, query.meta)
+}
+
+// The response to a buffered invocation, signed if the function's
+// responses are. Invokes and replays share it so a replay answers exactly
+// as the original invocation did.
+fn execution_response(
+    state: &AppState,
+    function: &Function,
+    mut execution: PoolExecution,
+    started: std::time::Instant,
+    with_meta: bool,
+) -> Result<Response, ApiError> {
+    execution.result = transform_output(function, execution.result);
+    let mut headers = HeaderMap::new();
+    headers.insert("x-function-version", HeaderValue::from(function.version));
+    headers.insert("x-cold-start", HeaderValue::from_static(bool_header(execution.cold_start)));
+    if let Some(invocation_id) = execution.invocation_id {
+        headers.insert("x-invocation-id", invocation_id_header(invocation_id));
+    }
+    if function.idempotent {
+        let cache = if execution.cached { "HIT" } else { "MISS" };
+        headers.insert("x-cache", HeaderValue::from_static(cache));
+    }
+    if let Some(usage) = execution.usage {
+        headers.insert("x-peak-memory-bytes", HeaderValue::from(usage.peak_memory_bytes));
+        if let Ok(cpu_time) = HeaderValue::from_str(&usage.cpu_time_ms.to_string()) {
+            headers.insert("x-cpu-time-ms", cpu_time);
+        }
+    }
+
+    // `http_response` functions own their whole body, so meta is only ever
+    // added to the standard JSON envelope
+    let signed = sign_marker(state, function);
+    if function.http_response {
+        return function_http_response(execution.result, headers).map(|response| (signed, response).into_response());
+    }
+    let meta = with_meta.then(|| InvocationMeta {
+        duration_ms: started.elapsed().as_millis() as u64,
+        vm_id: execution.vm_id,
+        cold_start: execution.cold_start,
+        cached: execution.cached,
+        usage: execution.usage,
+    });
+    let response = InvokeResponse {
+        result: execution.result,
+        meta,
+    };
+    Ok((headers, signed, Json(response)).into_response())
+}
+
+// Names the invocation's audit log entry, e.g. to replay it
+fn invocation_id_header(invocation_id: Uuid) -> HeaderValue {
+    HeaderValue::from_str(&invocation_id.to_string()).expect("a UUID is a valid header value")
+}
+
+// Marks a response whose body gets an X-Signature on the way out
+#[derive(Clone, Copy)]
+struct SignResponse;
+
+// Whether the function's responses are signed, as a response part
+fn sign_marker(state: &AppState, function: &Function) -> Option<Extension<SignResponse>> {
+    let signer = state.signer.as_ref()?;
+    signer.covers(function.sign_responses).then_some(Extension(SignResponse))
+}
+
+// Sign the bodies of marked responses. Only buffered invoke responses are
+// marked, so reading the body back costs a copy at most; streaming is
+// refused for functions whose responses are signed.
+async fn sign_response(State(state): State<AppState>, response: Response) -> Response {
+    let Some(signer) = &state.signer else {
+        return response;
+    };
+    if response.extensions().get::<SignResponse>().is_none() {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => {
+            parts.headers.insert("x-signature", signer.sign(&bytes));
+            Response::from_parts(parts, Body::from(bytes))
+        }
+        Err(e) => {
+            error!("Failed to read response body for signing: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// Deeply nested payloads are cheap to send and expensive for the V8 host
+// to walk
+fn check_depth(state: &AppState, payload: &serde_json::Value) -> Result<(), ApiError> {
+    let max = state.config.invoke.max_json_depth;
+    if exceeds_json_depth(payload, max) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Payload nests deeper than the limit of {} levels", max),
+        ));
+    }
+    Ok(())
+}
+
+// Apply invoke.large_integers, before any transform sees the payload
+fn check_integers(state: &AppState, payload: &mut serde_json::Value) -> Result<(), ApiError> {
+    integers_error(state, payload).map_err(|error| ApiError::new(StatusCode::BAD_REQUEST, error))
+}
+
+// check_integers for one batch item, whose failure is reported in its slot
+fn integers_error(state: &AppState, payload: &mut serde_json::Value) -> Result<(), String> {
+    handle_large_integers(payload, state.config.invoke.large_integers).map_err(|path| {
+        format!(
+            "Integer at {} is beyond JavaScript's safe range of ±{}; send it as a string",
+            path, MAX_SAFE_INTEGER
+        )
+    })
+}
+
+// Reshape a payload or result with the function's transform, if it has
+// one. Streamed (non-JSON) bodies skip transforms, as they skip the schema.
+fn apply_transform(transform: Option<&Transform>, value: serde_json::Value) -> serde_json::Value {
+    match transform {
+        Some(transform) => transform.apply(&value),
+        None => value,
+    }
+}
+
+// The result as the caller sees it, reshaped by the output transform.
+// An `http_response` result describes the whole HTTP response rather than
+// a result, so the transform never applies to it.
+fn transform_output(function: &Function, result: serde_json::Value) -> serde_json::Value {
+    if function.http_response {
+        return result;
+    }
+    apply_transform(function.output_transform.as_ref(), result)
+}
+
+// Reject payloads that don't match the function's input schema, before
+// any VM is spent on them. Runs after the input transform, so the schema
+// describes what the function receives.
+fn check_input(function: &Function, payload: &serde_json::Value) -> Result<(), ApiError> {
+    let Some(schema) = &function.input_schema else {
+        return Ok(());
+    };
+    let details = schema.violations(payload);
+    if details.is_empty() {
+        return Ok(());
+    }
+    Err(ApiError::with_body(
+        StatusCode::BAD_REQUEST,
+        ErrorResponse {
+            details,
+            ..ErrorResponse::new("Payload does not match the function's input schema")
+        },
+    ))
+}
+
+// Enforce a function's own payload limit, which is tighter than the body
+// limit on the route. The body has to be buffered to check it, so it's
+// handed back as a new request.
+async fn limit_payload(request: Request, limit: Option<usize>) -> Result<Request, ApiError> {
+    let Some(limit) = limit else {
+        return Ok(request);
+    };
+    let too_large = || {
+        ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Payload exceeds this function's limit of {} bytes", limit),
+        )
+    };
+
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared.is_some_and(|len| len > limit) {
+        return Err(too_large());
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, limit).await.map_err(|_| too_large())?;
+    Ok(Request::from_parts(parts, Body::from(bytes)))
+}
+
+// The caller's budget from `X-Invocation-Deadline` (milliseconds) or
+// `?timeout_ms=`, whichever is shorter, clamped to
+// timeouts.max_invocation_timeout_secs. None leaves the configured
+// acquire and execution timeouts in charge.
+fn invocation_deadline(
+    state: &AppState,
+    headers: &HeaderMap,
+    query: &InvokeQuery,
+) -> Result<Option<Deadline>, ApiError> {
+    let header_ms = match headers.get("x-invocation-deadline") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .ok_or_else(|| {
+                    ApiError::new(StatusCode::BAD_REQUEST, "X-Invocation-Deadline must be a number of milliseconds")
+                })?,
+        ),
+        None => None,
+    };
+
+    let Some(budget_ms) = header_ms.into_iter().chain(query.timeout_ms).min() else {
+        return Ok(None);
+    };
+    if budget_ms == 0 {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Invocation timeout must be greater than zero"));
+    }
+
+    let max = state.tunables.load().timeouts.max_invocation_timeout();
+    Ok(Some(Deadline::new(Duration::from_millis(budget_ms).min(max))))
+}
+
+// The caller's `X-Priority` (high, normal or low; normal if absent),
+// capped at the function's max_priority so any caller can ask for less but
+// only functions deployed as interactive can be served ahead of others
+fn invocation_priority(function: &Function, headers: &HeaderMap) -> Result<Priority, ApiError> {
+    let Some(value) = headers.get("x-priority") else {
+        return Ok(Priority::Normal.min(function.max_priority));
+    };
+    let requested: Priority = value
+        .to_str()
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "X-Priority must be high, normal or low"))?;
+    if requested > function.max_priority {
+        debug!(
+            "Capped {:?} priority for {} at {:?}",
+            requested,
+            function.qualified_name(),
+            function.max_priority
+        );
+    }
+    Ok(requested.min(function.max_priority))
+}
+
+fn is_json(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    mime.eq_ignore_ascii_case("application/json") || mime.ends_with("+json")
+}
+
+fn execution_error(e: anyhow::Error) -> ApiError {
+    if let Some(open) = e.downcast_ref::<CircuitOpen>() {
+        warn!("Rejected invocation: {}", open);
+        return ApiError::new(StatusCode::SERVICE_UNAVAILABLE, open.to_string())
+            .retry_after(open.retry_after.as_secs().max(1));
+    }
+
+    if let Some(exceeded) = e.downcast_ref::<DeadlineExceeded>() {
+        warn!("Invocation failed: {}", exceeded);
+        return ApiError::new(StatusCode::GATEWAY_TIMEOUT, exceeded.to_string());
+    }
+
+    if let Some(flushed @ HyperdriveError::VmFlushed(_)) = e.downcast_ref::<HyperdriveError>() {
+        warn!("Invocation cancelled: {}", flushed);
+        return ApiError::new(StatusCode::SERVICE_UNAVAILABLE, flushed.to_string()).retry_after(1);
+    }
+
+    if let Some(HyperdriveError::PoolExhausted) = e.downcast_ref::<HyperdriveError>() {
+        warn!("Rejected invocation: pool exhausted");
+        return ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Pool exhausted; too many invocations waiting")
+            .retry_after(1);
+    }
+
+    if let Some(outage @ HyperdriveError::NoBootableVms(_)) = e.downcast_ref::<HyperdriveError>() {
+        error!("Rejected invocation: {}", outage);
+        return ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!(
+                "{}. The server can't start VMs; check vm.kernel_path, vm.rootfs_path and that /dev/kvm is \
+                 available, and see /api/v1/advanced/vms for failed boots",
+                outage
+            ),
+        );
+    }
+
+    // The function misbehaved rather than the platform
+    if let Some(too_large @ HyperdriveError::ResponseTooLarge(_)) = e.downcast_ref::<HyperdriveError>() {
+        warn!("Rejected function response: {}", too_large);
+        return ApiError::new(StatusCode::BAD_GATEWAY, too_large.to_string());
+    }
+
+    error!("Function execution failed: {:#}", e);
+    StatusCode::INTERNAL_SERVER_ERROR.into()
+}
+
+// Stream a raw request body to the function and its response back without
+// buffering either. The VM stays checked out until the response body has
+// been sent.
+async fn invoke_streaming(
+    state: &AppState,
+    function: &Function,
+    content_type: &str,
+    body: Body,
+    deadline: Option<Deadline>,
+    priority: Priority,
+    kind: InvocationKind,
+) -> Result<Response, ApiError> {
+    ensure_vm_execution(state)?;
+    ensure_streamable(state, function)?;
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let started = std::time::Instant::now();
+
+    let limit = function
+        .max_payload_bytes
+        .map_or(state.config.invoke.max_stream_bytes, |max| max.min(state.config.invoke.max_stream_bytes));
+    let mut received = 0;
+    let body = body.into_data_stream().map(move |chunk| -> Result<_, BoxError> {
+        let chunk = chunk?;
+        received += chunk.len();
+        if received > limit {
+            return Err(format!("Request body exceeds {} bytes", limit).into());
+        }
+        Ok(chunk)
+    });
+
+    let span = invocation_span(function);
+    let body = reqwest::Body::wrap_stream(body);
+    let outcome = stream_on_pool(state, function, content_type, body, deadline, priority, kind)
+        .instrument(span.clone())
+        .await;
+    span.record("outcome", if outcome.is_ok() { "success" } else { "error" });
+    let invocation_id = record_invocation(state, function, kind, timestamp, started, &outcome, None);
+    let (response, mut lease, cold_start) = outcome.map_err(execution_error)?;
+
+    let mut headers = HeaderMap::new();
+    if let Some(content_type) = response.headers().get(reqwest::header::CONTENT_TYPE) {
+        if let Ok(content_type) = HeaderValue::from_bytes(content_type.as_bytes()) {
+            headers.insert(header::CONTENT_TYPE, content_type);
+        }
+    }
+    headers.insert("x-function-version", HeaderValue::from(function.version));
+    headers.insert("x-cold-start", HeaderValue::from_static(bool_header(cold_start)));
+    headers.insert("x-invocation-id", invocation_id_header(invocation_id));
+
+    let body = response.bytes_stream().map(move |chunk| {
+        if chunk.is_err() {
+            lease.mark_failed();
+        }
+        chunk
+    });
+    Ok((headers, Body::from_stream(body)).into_response())
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+async fn stream_on_pool(
+    state: &AppState,
+    function: &Function,
+    content_type: &str,
+    body: reqwest::Body,
+    deadline: Option<Deadline>,
+    priority: Priority,
+    kind: InvocationKind,
+) -> Result<(reqwest::Response, VmLease, bool)> {
+    let qualified_name = function.qualified_name();
+    state.breakers.check(&qualified_name)?;
+    // Only live traffic says whether the function is healthy
+    let live = kind == InvocationKind::Live;
+
+    let affinity_key = function.affinity_key();
+    let (mut vm, cold_start, turn) = acquire_vm(state, function, deadline, priority)
+        .instrument(info_span!("acquire"))
+        .await?;
+    record_vm(&vm, cold_start);
+    let hold = live.then(|| state.demand.hold());
+
+    let timeout = execution_timeout(state, deadline);
+    let execute = async {
+        if vm.loaded_function.as_deref() != Some(affinity_key.as_str()) {
+            vm.prime_function(&state.v8_client, function, timeout).await?;
+        }
+        vm.execute_stream(&state.v8_client, function, content_type, body, timeout).await
+    };
+    // Covers the response headers; the body streams after this returns
+    let forced = state.flushed_vms.forced();
+    let result = tokio::select! {
+        result = execute.instrument(info_span!("execute")) => result,
+        _ = forced.cancelled() => return Err(kill_flushed_vm(state, vm).await.into()),
+    };
+
+    match result {
+        Ok(response) => {
+            if live {
+                state.breakers.record(&qualified_name, true);
+            }
+            Ok((response, VmLease::new(state.clone(), vm, turn, hold), cold_start))
+        }
+        Err(e) => {
+            let exceeded = deadline_exceeded(deadline);
+            if exceeded.is_none() && live {
+                state.breakers.record(&qualified_name, false);
+            }
+            discard_failed_vm(state, vm, &e).await;
+            Err(exceeded.map_or(e, Into::into))
+        }
+    }
+}
+
+// A failed VM might be corrupted, so it's replaced rather than reused. If
+// the call timed out the guest may still be running the handler, so it's
+// recorded as failed; the pool kills its process once no other invocation
+// is running on it instead of leaving it to burn CPU.
+async fn discard_failed_vm(state: &AppState, vm: VmInstance, error: &anyhow::Error) {
+    if is_timeout(error) {
+        warn!("VM {} timed out; tearing it down", vm.id);
+        fail_vm(state, vm, "Torn down after its invocation timed out").await;
+        return;
+    }
+    discard_vm(state, vm).await;
+}
+
+// A forced flush doesn't wait for the VM's invocation, so the guest may
+// still be running it. The VM is torn down once its other slots, flushed
+// along with it, are back.
+async fn kill_flushed_vm(state: &AppState, vm: VmInstance) -> HyperdriveError {
+    state.flushed_vms.take(vm.id);
+    warn!("VM {} flushed mid-invocation; tearing it down", vm.id);
+    let id = vm.id;
+    fail_vm(state, vm, "Torn down by a forced pool flush").await;
+    HyperdriveError::VmFlushed(id)
+}
+
+// Mark the VM `Failed` and hand it back to be destroyed. It stays listed
+// with the reason until pool.failed_vm_retention_secs passes.
+async fn fail_vm(state: &AppState, vm: VmInstance, reason: &str) {
+    state.failed_vms.record(vm.info(), reason, std::time::Instant::now());
+    publish_vm_state(state, &vm, VmState::Failed);
+    return_to_pool(state, vm, false).await;
+}
+
+async fn discard_vm(state: &AppState, vm: VmInstance) {
+    publish_vm_state(state, &vm, VmState::Stopping);
+    return_to_pool(state, vm, false).await;
+}
+
+// VM transitions as seen by the server: checked out, returned, or thrown away
+fn publish_vm_state(state: &AppState, vm: &VmInstance, vm_state: VmState) {
+    state.events.publish(PlatformEvent::VmStateChanged {
+        vm_id: vm.id,
+        state: vm_state,
+    });
+}
+
+fn is_timeout(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .any(|e| e.is_timeout())
+}
+
+// Holds a VM while a streamed response is in flight, returning it to the
+// pool when the stream is dropped, or discarding it if the stream broke
+struct VmLease {
+    state: AppState,
+    vm: Option<VmInstance>,
+    _turn: FairTurn, // the function's slot, given up with the VM
+    _hold: Option<DemandHold>, // live streams count toward demand until done
+    failed: bool,
+}
+
+impl VmLease {
+    fn new(state: AppState, vm: VmInstance, turn: FairTurn, hold: Option<DemandHold>) -> Self {
+        Self {
+            state,
+            vm: Some(vm),
+            _turn: turn,
+            _hold: hold,
+            failed: false,
+        }
+    }
+
+    fn mark_failed(&mut self) {
+        self.failed = true;
+    }
+}
+
+impl Drop for VmLease {
+    fn drop(&mut self) {
+        if let Some(vm) = self.vm.take() {
+            let state = self.state.clone();
+            let failed = self.failed;
+            tokio::spawn(async move {
+                if failed {
+                    discard_vm(&state, vm).await;
+                } else {
+                    release_vm(&state, vm).await;
+                }
+            });
+        }
+    }
+}
+
+// Build the response an `http_response` function described. A malformed
+// description is the function's fault, so it surfaces as a 502.
+fn function_http_response(result: serde_json::Value, mut headers: HeaderMap) -> Result<Response, ApiError> {
+    let invalid = |reason: String| {
+        ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            format!("Function returned an invalid HTTP response: {}", reason),
+        )
+    };
+
+    let described: FunctionHttpResponse = serde_json::from_value(result).map_err(|e| invalid(e.to_string()))?;
+    let status = StatusCode::from_u16(described.status).map_err(|e| invalid(e.to_string()))?;
+
+    let (body, default_content_type) = if described.base64 {
+        let bytes = BASE64_STANDARD
+            .decode(described.body.as_bytes())
+            .map_err(|e| invalid(e.to_string()))?;
+        (bytes, "application/octet-stream")
+    } else {
+        (described.body.into_bytes(), "text/plain; charset=utf-8")
+    };
+
+    let content_type = described.content_type.as_deref().unwrap_or(default_content_type);
+    let content_type = HeaderValue::from_str(content_type).map_err(|e| invalid(e.to_string()))?;
+    headers.insert(header::CONTENT_TYPE, content_type);
+
+    Ok((status, headers, body).into_response())
+}
+
+// Invoke function once per payload, reporting failures per item
+async fn invoke_function_batch(
+    State(state): State<AppState>,
+    Path(path): Path<FunctionPath>,
+    Query(query): Query<InvokeQuery>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    payloads: Result<Json<Vec<serde_json::Value>>, JsonRejection>,
+) -> Result<(HeaderMap, Option<Extension<SignResponse>>, Json<BatchInvokeResponse>), ApiError> {
+    ensure_accepting(&state)?;
+    let payloads = json_body(payloads)?;
+    info!(
+        "Batch invoking function: {}/{} ({} payloads)",
+        path.namespace,
+        path.name,
+        payloads.len()
+    );
+
+    if payloads.len() > MAX_BATCH_SIZE {
+        warn!("Batch of {} exceeds limit of {}", payloads.len(), MAX_BATCH_SIZE);
+        return Err(ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Batch cannot exceed {} payloads", MAX_BATCH_SIZE),
+        ));
+    }
+    // Too-deep payloads are refused whole, before any of them is walked
+    for payload in &payloads {
+        check_depth(&state, payload)?;
+    }
+
+    // The whole batch runs against a single version
+    let function = resolve_function(&state, &path, &query).await?;
+    ensure_enabled(&function)?;
+    ensure_caller_allowed(&state, &function, peer.map(|info| info.0), &headers)?;
+    ensure_ready(&function)?;
+    let priority = invocation_priority(&function, &headers)?;
+
+    // `buffered` keeps results in input order while bounding pool usage
+    let results = stream::iter(payloads)
+        .map(|mut payload| async {
+            if let Err(error) = integers_error(&state, &mut payload) {
+                return BatchItemResult::Error { error };
+            }
+            let payload = apply_transform(function.input_transform.as_ref(), payload);
+            let violations = match &function.input_schema {
+                Some(schema) => schema.violations(&payload),
+                None => Vec::new(),
+            };
+            if !violations.is_empty() {
+                return BatchItemResult::Error {
+                    error: format!("Payload does not match the function's input schema: {}", violations.join("; ")),
+                };
+            }
+            match run_audited(&state, &function, payload, None, priority, InvocationKind::Live).await {
+                Ok(execution) => BatchItemResult::Success {
+                    result: transform_output(&function, execution.result),
+                },
+                Err(e) => {
+                    warn!("Batch item for {}/{} failed: {:#}", path.namespace, path.name, e);
+                    BatchItemResult::Error { error: format!("{:#}", e) }
+                }
+            }
+        })
+        .buffered(BATCH_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-function-version", HeaderValue::from(function.version));
+    Ok((headers, sign_marker(&state, &function), Json(BatchInvokeResponse { results })))
+}
+
+// Acquire a VM and load the function's code into it ahead of traffic. The
+// VM goes back to the pool remembering what it has loaded.
+async fn warmup_function(
+    State(state): State<AppState>,
+    Path(path): Path<FunctionPath>,
+    Query(query): Query<InvokeQuery>,
+) -> Result<Json<WarmupResponse>, StatusCode> {
+    info!("Warming up function: {}/{}", path.namespace, path.name);
+    ensure_accepting(&state).map_err(|e| e.status)?;
+    ensure_vm_execution(&state).map_err(|e| e.status)?;
+
+    let function = resolve_function(&state, &path, &query).await?;
+    ensure_enabled(&function).map_err(|e| e.status)?;
+
+    let vm_id = prime_function(&state, &function).await.map_err(|e| {
+        error!("Failed to warm up {}: {:#}", function.affinity_key(), e);
+        match e.downcast_ref::<HyperdriveError>() {
+            Some(HyperdriveError::PoolExhausted) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    })?;
+
+    Ok(Json(WarmupResponse {
+        namespace: function.namespace,
+        name: function.name,
+        version: function.version,
+        vm_id,
+    }))
+}
+
+// Prime a new version into a VM in the background so it becomes `Ready`.
+// With no VMs there's nothing to prime, so it's ready straight away.
+fn spawn_prime(state: &AppState, function: &Function) {
+    if state.config.execution.mode == ExecutionMode::Local {
+        function.status.ready();
+        return;
+    }
+    let (state, function) = (state.clone(), function.clone());
+    tokio::spawn(async move {
+        if let Err(e) = prime_function(&state, &function).await {
+            warn!("Failed to prime {}: {:#}", function.affinity_key(), e);
+        }
+    });
+}
+
+// Load a version's code into a pooled VM ahead of traffic and hand the VM
+// back remembering it. The version is `Ready` afterwards, or `Error` if it
+// never was and priming failed.
+async fn prime_function(state: &AppState, function: &Function) -> Result<Uuid> {
+    let primed = async {
+        let (mut vm, _, _turn) = acquire_vm(state, function, None, Priority::Normal).await?;
+        let timeout = state.tunables.load().timeouts.execution_timeout();
+        if let Err(e) = vm.prime_function(&state.v8_client, function, timeout).await {
+            discard_failed_vm(state, vm, &e).await;
+            return Err(e);
+        }
+        let vm_id = vm.id;
+        release_vm(state, vm).await;
+        Ok(vm_id)
+    };
+    let outcome = primed.await;
+    match outcome {
+        Ok(_) => function.status.ready(),
+        Err(_) => function.status.fail(),
+    }
+    outcome
+}
+
+// Until a new function has been primed once, invoking it would only queue
+// the caller behind its prime. Later versions are served while they prime
+// so a deploy doesn't turn live traffic away.
+fn ensure_ready(function: &Function) -> Result<(), ApiError> {
+    if function.version == 1 && function.status.get() == FunctionStatus::Creating {
+        return Err(ApiError::new(
+            StatusCode::TOO_EARLY,
+            format!("Function {} v{} is still being created", function.qualified_name(), function.version),
+        )
+        .retry_after(1));
+    }
+    Ok(())
+}
+
+// New invocations are refused once draining begins; ones already running
+// are left to finish
+fn ensure_accepting(state: &AppState) -> Result<(), ApiError> {
+    if state.shutdown.is_cancelled() {
+        return Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down"));
+    }
+    Ok(())
+}
+
+// Enforce the function's IP rules against the caller's address. With rules
+// set, a caller whose address is unknown is turned away.
+fn ensure_caller_allowed(
+    state: &AppState,
+    function: &Function,
+    peer: Option<SocketAddr>,
+    headers: &HeaderMap,
+) -> Result<(), ApiError> {
+    if function.ip_rules.is_empty() {
+        return Ok(());
+    }
+    let client = peer.map(|peer| state.trusted_proxies.client_ip(peer.ip(), headers));
+    if client.is_some_and(|ip| function.ip_rules.permits(ip)) {
+        return Ok(());
+    }
+    warn!(
+        "Rejected invocation of {} from {}",
+        function.qualified_name(),
+        client.map_or("an unknown address".to_string(), |ip| ip.to_string())
+    );
+    Err(ApiError::new(
+        StatusCode::FORBIDDEN,
+        format!("Caller is not allowed to invoke {}", function.qualified_name()),
+    ))
+}
+
+fn ensure_enabled(function: &Function) -> Result<(), ApiError> {
+    if !function.enabled {
+        warn!("Rejected invocation of disabled function {}", function.qualified_name());
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("Function {} is disabled", function.qualified_name()),
+        ));
+    }
+    Ok(())
+}
+
+// Deletes go to the recycle bin unless `?hard=true`, which also removes a
+// function already in the bin
+async fn delete_function(
+    State(state): State<AppState>,
+    Path(path): Path<FunctionPath>,
+    Query(query): Query<DeleteFunctionQuery>,
+) -> Result<StatusCode, ApiError> {
+    let store = &state.function_store;
+    let deleted = if query.hard {
+        store.delete(&path.namespace, &path.name).await
+    } else {
+        store.soft_delete(&path.namespace, &path.name).await
+    }
+    .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))?;
+
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_deleted_functions(
+    State(state): State<AppState>,
+    Path(path): Path<NamespacePath>,
+) -> Json<DeletedFunctionListResponse> {
+    let mut functions = state.function_store.list_deleted(&path.namespace).await;
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+    Json(DeletedFunctionListResponse { functions })
+}
+
+async fn restore_function(
+    State(state): State<AppState>,
+    Path(path): Path<FunctionPath>,
+) -> Result<Json<Function>, ApiError> {
+    match state.function_store.restore(&path.namespace, &path.name).await {
+        Ok(Some(function)) => Ok(Json(function)),
+        Ok(None) => Err(StatusCode::NOT_FOUND.into()),
+        Err(e) => {
+            warn!("Failed to restore {}/{}: {}", path.namespace, path.name, e);
+            let status = if e.is::<AlreadyExists>() {
+                StatusCode::CONFLICT
+            } else if e.is::<QuotaExceeded>() {
+                StatusCode::FORBIDDEN
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            };
+            Err(ApiError::new(status, e.to_string()))
+        }
+    }
+}
+
+// Take a misbehaving function out of service without losing its definition
+async fn disable_function(
+    State(state): State<AppState>,
+    Path(path): Path<FunctionPath>,
+) -> Result<Json<FunctionEnabledResponse>, StatusCode> {
+    set_function_enabled(&state, &path, false).await
+}
+
+async fn enable_function(
+    State(state): State<AppState>,
+    Path(path): Path<FunctionPath>,
+) -> Result<Json<FunctionEnabledResponse>, StatusCode> {
+    set_function_enabled(&state, &path, true).await
+}
+
+async fn set_function_enabled(
+    state: &AppState,
+    path: &FunctionPath,
+    enabled: bool,
+) -> Result<Json<FunctionEnabledResponse>, StatusCode> {
+    let function = state
+        .function_store
+        .set_enabled(&path.namespace, &path.name, enabled)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(FunctionEnabledResponse {
+        namespace: function.namespace,
+        name: function.name,
+        enabled: function.enabled,
+    }))
+}
+
+// Streaming and warm-up need a V8 host, which local execution doesn't have
+fn ensure_vm_execution(state: &AppState) -> Result<(), ApiError> {
+    if state.config.execution.mode == ExecutionMode::Local {
+        return Err(ApiError::new(
+            StatusCode::NOT_IMPLEMENTED,
+            "Not supported with execution.mode = \"local\"",
+        ));
+    }
+    Ok(())
+}
+
+// Streamed responses pass through as raw bytes, so neither the operator's
+// output policy nor a response signature can be applied to them
+fn ensure_streamable(state: &AppState, function: &Function) -> Result<(), ApiError> {
+    if !state.output_policy.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Streaming invocations are disabled while output_policy is configured; send application/json",
+        ));
+    }
+    if sign_marker(state, function).is_some() {
+        return Err(ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Responses of this function are signed, which streaming can't do; send application/json",
+        ));
+    }
+    Ok(())
+}
+
+// Begin a graceful drain and exit once it completes. Returns before the
+// drain does, so orchestrators can poll /ready or wait for the process.
+async fn admin_shutdown(State(state): State<AppState>, headers: HeaderMap) -> Result<StatusCode, ApiError> {
+    authorize_admin(&state.config, &headers)?;
+
+    if !state.shutdown.is_cancelled() {
+        warn!("Shutdown requested via admin API, draining");
+        state.shutdown.cancel();
+    }
+    Ok(StatusCode::ACCEPTED)
+}
+
+// Deploy a throwaway canary function, invoke it with a fresh nonce and
+// check it echoes it back, then delete it: one call that proves the store,
+// pool, VMs and V8 host work end to end after a deploy or config change.
+// The canary doesn't count toward functions.max_functions or quotas.
+// Answers 200 when every step passed and 503 otherwise, with the report
+// either way.
+async fn admin_selftest(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<SelfTestResponse>), ApiError> {
+    authorize_admin(&state.config, &headers)?;
+    ensure_accepting(&state)?;
+
+    let started = std::time::Instant::now();
+    let nonce = Uuid::new_v4().simple().to_string();
+    let name = format!("canary-{}", &nonce[..12]);
+    info!("Running self-test with canary {}/{}", SELFTEST_NAMESPACE, name);
+    let mut steps = Vec::new();
+
+    let step_started = std::time::Instant::now();
+    let request = CreateFunctionRequest {
+        name: name.clone(),
+        code: SELFTEST_CANARY.to_string(),
+        runtime: "v8".to_string(),
+        ..Default::default()
+    };
+    let created = state.function_store.create_unmetered(SELFTEST_NAMESPACE, request).await;
+    steps.push(selftest_step("create", step_started, created.as_ref().err().map(|e| format!("{:#}", e))));
+
+    if let Ok(function) = created {
+        let step_started = std::time::Instant::now();
+        let payload = serde_json::json!({ "nonce": nonce });
+        let invoked = run_audited(&state, &function, payload, None, Priority::High, InvocationKind::Test).await;
+        let error = match invoked {
+            Ok(execution) if execution.result == serde_json::json!({ "echo": nonce }) => None,
+            Ok(execution) => Some(format!("Canary returned {} instead of echoing the nonce", execution.result)),
+            Err(e) => Some(format!("{:#}", e)),
+        };
+        steps.push(selftest_step("invoke", step_started, error));
+
+        let step_started = std::time::Instant::now();
+        let error = match state.function_store.delete(SELFTEST_NAMESPACE, &name).await {
+            Ok(true) => None,
+            Ok(false) => Some("Canary was already gone".to_string()),
+            Err(e) => Some(format!("{:#}", e)),
+        };
+        steps.push(selftest_step("cleanup", step_started, error));
+    }
+
+    let passed = steps.iter().all(|step| step.passed);
+    if passed {
+        info!("Self-test passed in {:?}", started.elapsed());
+    } else {
+        error!("Self-test failed: {:?}", steps.iter().find(|step| !step.passed));
+    }
+    let status = if passed { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    Ok((
+        status,
+        Json(SelfTestResponse {
+            passed,
+            duration_ms: started.elapsed().as_millis() as u64,
+            steps,
+        }),
+    ))
+}
+
+fn selftest_step(step: &str, started: std::time::Instant, error: Option<String>) -> SelfTestStep {
+    SelfTestStep {
+        step: step.to_string(),
+        passed: error.is_none(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        error,
+    }
+}
+
+// Admin endpoints take `Authorization: Bearer <admin.token>` and are
+// disabled entirely when no token is configured
+fn authorize_admin(config: &Config, headers: &HeaderMap) -> Result<(), ApiError> {
+    let Some(expected) = config.admin.token.as_deref() else {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "Admin API is disabled"));
+    };
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match provided {
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => {
+            warn!("Rejected admin request with missing or invalid token");
+            Err(ApiError::new(StatusCode::UNAUTHORIZED, "Invalid admin token"))
+        }
+    }
+}
+
+// A max_priority above normal lets a function's callers jump ahead of
+// everyone else's, so only the admin token may grant it
+fn authorize_priority(config: &Config, headers: &HeaderMap, max_priority: Priority) -> Result<(), ApiError> {
+    if max_priority <= Priority::Normal {
+        return Ok(());
+    }
+    authorize_admin(config, headers).map_err(|_| {
+        ApiError::new(
+            StatusCode::FORBIDDEN,
+            format!("max_priority {:?} requires the admin token", max_priority),
+        )
+    })
+}
+
+// Compares without short-circuiting so response timing doesn't reveal how
+// much of the token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// Unwrap a JSON request body, explaining why it was rejected in the usual
+// error shape rather than axum's plain-text 422. Oversized bodies keep
+// their 413; unparseable ones become a 400.
+fn json_body<T>(body: Result<Json<T>, JsonRejection>) -> Result<T, ApiError> {
+    match body {
+        Ok(Json(value)) => Ok(value),
+        Err(rejection) => {
+            let status = match rejection.status() {
+                StatusCode::PAYLOAD_TOO_LARGE => StatusCode::PAYLOAD_TOO_LARGE,
+                StatusCode::UNSUPPORTED_MEDIA_TYPE => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            warn!("Rejected request body: {}", rejection.body_text());
+            Err(ApiError::new(status, format!("Invalid JSON: {}", rejection.body_text())))
+        }
+    }
+}
+
+// Look up the function to invoke, routing unpinned calls through the
+// traffic split
+async fn resolve_function(
+    state: &AppState,
+    path: &FunctionPath,
+    query: &InvokeQuery,
+) -> Result<Function, StatusCode> {
+    let store = &state.function_store;
+    let function = match query.version {
+        Some(version) => store.get_version(&path.namespace, &path.name, version).await,
+        None => store.resolve(&path.namespace, &path.name).await,
+    };
+
+    function.ok_or_else(|| {
+        warn!(
+            "Function not found: {}/{} (version {:?})",
+            path.namespace, path.name, query.version
+        );
+        StatusCode::NOT_FOUND
+    })
+}
+
+// Execute the function on a pooled VM and record the invocation in the
+// audit log
+async fn run_audited(
+    state: &AppState,
+    function: &Function,
+    payload: serde_json::Value,
+    deadline: Option<Deadline>,
+    priority: Priority,
+    kind: InvocationKind,
+) -> Result<PoolExecution> {
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let started = std::time::Instant::now();
+    let audited_payload = function.audit_payloads.then(|| payload.clone());
+    if function.debug {
+        debug!("Invoking {} v{} with payload: {}", function.qualified_name(), function.version, payload);
+    }
+
+    let span = invocation_span(function);
+    let outcome = run_cached(state, function, payload, deadline, priority, kind)
+        .instrument(span.clone())
+        .await;
+    span.record(
+        "outcome",
+        match &outcome {
+            Ok(execution) if execution.cached => "cached",
+            Ok(_) => "success",
+            Err(_) => "error",
+        },
+    );
+    if function.debug {
+        match &outcome {
+            Ok(execution) => debug!(
+                "{} v{} returned: {}",
+                function.qualified_name(),
+                function.version,
+                execution.result
+            ),
+            Err(e) => debug!("{} v{} failed: {:#}", function.qualified_name(), function.version, e),
+        }
+    }
+    if let (Ok(execution), InvocationKind::Live) = (&outcome, kind) {
+        if let Some(usage) = execution.usage {
+            state.usage_stats.record(&function.qualified_name(), usage);
+        }
+    }
+    let invocation_id = record_invocation(state, function, kind, timestamp, started, &outcome, audited_payload);
+    outcome.map(|execution| PoolExecution {
+        invocation_id: Some(invocation_id),
+        ..execution
+    })
+}
+
+// Root span of an invocation, exported over OTLP when a collector is
+// configured. Acquire, execute and release are child spans; the VM and
+// outcome are filled in as they become known.
+fn invocation_span(function: &Function) -> Span {
+    info_span!(
+        "invocation",
+        function = %function.qualified_name(),
+        version = function.version,
+        vm_id = field::Empty,
+        cold_start = field::Empty,
+        outcome = field::Empty,
+    )
+}
+
+// Tag the current invocation span with the VM it got
+fn record_vm(vm: &VmInstance, cold_start: bool) {
+    let span = Span::current();
+    span.record("vm_id", field::display(vm.id));
+    span.record("cold_start", cold_start);
+}
+
+// Record a finished invocation in the function's counters, the audit log
+// and on the event bus. Returns its id in the audit log.
+fn record_invocation<T>(
+    state: &AppState,
+    function: &Function,
+    kind: InvocationKind,
+    timestamp: String,
+    started: std::time::Instant,
+    outcome: &Result<T>,
+    payload: Option<serde_json::Value>,
+) -> Uuid {
+    let invocation_id = Uuid::new_v4();
+    let duration_ms = started.elapsed().as_millis() as u64;
+    // Test runs, replays and the selftest stay out of live traffic figures
+    if kind == InvocationKind::Live {
+        function.counters.record(outcome.is_ok());
+        state.events.publish(PlatformEvent::Invocation {
+            namespace: function.namespace.clone(),
+            function: function.name.clone(),
+            version: function.version,
+            success: outcome.is_ok(),
+            duration_ms,
+        });
+    }
+
+    state.audit_log.record(AuditEvent {
+        invocation_id,
+        timestamp,
+        namespace: function.namespace.clone(),
+        function: function.name.clone(),
+        version: function.version,
+        caller: None, // no authentication yet
+        test: kind == InvocationKind::Test,
+        duration_ms,
+        outcome: match outcome {
+            Ok(_) => AuditOutcome::Success,
+            Err(e) => AuditOutcome::Error {
+                message: format!("{:#}", e),
+            },
+        },
+        payload,
+        replay_of: match kind {
+            InvocationKind::Replay(invocation_id) => Some(invocation_id),
+            _ => None,
+        },
+    });
+    invocation_id
+}
+
+// Acquire a VM from the pool, reporting whether one had to be booted. The
+// function first waits its turn in the fair queue, which caps checkouts at
+// pool.max_vms × pool.vm_concurrency; the pool boots at most pool.max_vms
+// VMs and prefers one that already has its code loaded.
+// The turn must be held until the VM is handed back.
+async fn acquire_vm(
+    state: &AppState,
+    function: &Function,
+    deadline: Option<Deadline>,
+    priority: Priority,
+) -> Result<(VmInstance, bool, FairTurn)> {
+    let requested_at = chrono::Utc::now();
+    let acquire_started = std::time::Instant::now();
+    let tunables = state.tunables.load();
+    let acquire_timeout = deadline.map_or(tunables.timeouts.acquire_timeout(), |d| d.remaining());
+    let affinity_key = tunables.pool.affinity.then(|| function.affinity_key());
+    let affinity_key = affinity_key.as_deref();
+    let waiting = state.acquire_tracker.wait();
+    let acquire = async {
+        let turn = state.fair_queue.turn(&function.qualified_name(), priority).await?;
+        let queued = acquire_started.elapsed();
+        state.warmup.wait().await;
+        acquire_healthy_vm(state, affinity_key, function.tier).await.map(|vm| (vm, turn, queued))
+    };
+    let acquired = match tokio::time::timeout(acquire_timeout, acquire).await {
+        Ok(acquired) => acquired,
+        Err(_) => match deadline {
+            Some(deadline) => return Err(deadline.exceeded().into()),
+            None => Err(anyhow::anyhow!("Timed out acquiring VM after {:?}", acquire_timeout)),
+        },
+    };
+    // Acquires still go to the pool during an outage so its boots keep
+    // being retried; only the failure is reported differently
+    let (vm, turn, queued) = match acquired {
+        Ok(acquired) => acquired,
+        Err(e) => {
+            return Err(match boot_outage(state).await {
+                Some(reason) => HyperdriveError::NoBootableVms(reason).into(),
+                None => e,
+            })
+        }
+    };
+    drop(waiting);
+    state.acquire_tracker.record(acquire_started.elapsed());
+
+    // A VM created after we asked for one was booted for this call
+    let cold_start = vm.created_at >= requested_at;
+    state.metrics.record_acquire(cold_start, acquire_started.elapsed());
+    state.metrics.record_acquire_phases(queued, acquire_started.elapsed() - queued);
+    if let Some(key) = affinity_key {
+        state.metrics.record_affinity(vm.loaded_function.as_deref() == Some(key));
+    }
+
+    publish_vm_state(state, &vm, VmState::Busy);
+    Ok((vm, cold_start, turn))
+}
+
+// Acquire a VM whose V8 host answers a ping. Dead VMs are handed back to
+// the pool to be destroyed and replaced, so it heals back to its target size.
+// Premium functions take a reserved VM while there is one.
+async fn acquire_healthy_vm(state: &AppState, affinity_key: Option<&str>, tier: FunctionTier) -> Result<VmInstance> {
+    for _ in 0..MAX_ACQUIRE_ATTEMPTS {
+        let reserved = match tier {
+            FunctionTier::Premium => state.reserved_vms.take(),
+            FunctionTier::Standard => None,
+        };
+        let vm = match reserved {
+            Some(vm) => vm,
+            None => checkout_vm(state, affinity_key).await.context("Failed to acquire VM")?,
+        };
+
+        if state.unhealthy_vms.take(vm.id) {
+            warn!("VM {} failed an idle health ping, replacing it", vm.id);
+            fail_vm(state, vm, "Failed an idle health ping").await;
+            continue;
+        }
+        if state.flushed_vms.take(vm.id) {
+            discard_vm(state, vm).await;
+            continue;
+        }
+        if vm.ping(&state.v8_client, VM_PING_TIMEOUT).await {
+            return Ok(vm);
+        }
+
+        warn!("VM {} failed health check on acquire, replacing it", vm.id);
+        fail_vm(state, vm, "Failed a health check on acquire").await;
+    }
+
+    Err(anyhow::anyhow!("No healthy VM after {} attempts", MAX_ACQUIRE_ATTEMPTS))
+}
+
+// Check a VM out of the current generation's pool. The checkout is counted
+// before waiting on the pool, so a roll can't retire the generation while
+// its VM is on the way out; it's handed back if the acquire fails or is
+// abandoned.
+async fn checkout_vm(state: &AppState, affinity_key: Option<&str>) -> Result<VmInstance> {
+    let generation = state.generations.current();
+    generation.check_out();
+    let pending = PendingCheckout(Some(generation.clone()));
+    let mut vm = match affinity_key {
+        Some(_) => generation.backend.pool.acquire_for(affinity_key).await?,
+        None => generation.backend.pool.acquire().await?,
+    };
+    vm.generation = Some(generation.number);
+    pending.complete();
+    Ok(vm)
+}
+
+// A generation checkout not yet backed by a VM
+struct PendingCheckout(Option<Arc<Generation<VmBackend>>>);
+
+impl PendingCheckout {
+    fn complete(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for PendingCheckout {
+    fn drop(&mut self) {
+        if let Some(generation) = self.0.take() {
+            generation.check_in();
+        }
+    }
+}
+
+// Hand a VM back to the pool of the generation it came from, to be reused
+// or destroyed. VMs of a draining generation are never reused, and the
+// generation is retired once its last VM is back.
+async fn return_to_pool(state: &AppState, vm: VmInstance, reuse: bool) {
+    let Some(generation) = vm.generation.and_then(|number| state.generations.get(number)) else {
+        error!("VM {} doesn't belong to a running generation ({:?}); tearing it down", vm.id, vm.generation);
+        vm::release_host_resources(&state.work_dirs, &vm).await;
+        return;
+    };
+    let draining = generation.number != state.generations.current().number;
+    if reuse && !draining {
+        generation.backend.pool.release(vm).await;
+    } else {
+        generation.backend.pool.discard(vm).await;
+    }
+    generation.check_in();
+    if draining {
+        retire_drained(state).await;
+    }
+}
+
+// Roll the pool over to VMs booted with `vm_config`. The new generation's
+// warm pool boots before any checkout moves to it, so invocations never
+// wait on the switch; if none of its VMs boot, the current generation is
+// kept. The old generation then drains as its busy VMs come back.
+async fn roll_generation(state: &AppState, vm_config: VmConfig) -> Result<()> {
+    // Each generation's manager hands out V8 host ports on its own
+    if let Some(running) = state
+        .generations
+        .all()
+        .into_iter()
+        .find(|generation| generation.backend.config.ports_overlap(&vm_config))
+    {
+        return Err(anyhow::anyhow!(
+            "vm.port_range {}-{} overlaps that of generation {}, which is still running; alternate between \
+             two port ranges when changing VM boot settings",
+            vm_config.port_range_start,
+            vm_config.port_range_end,
+            running.number
+        ));
+    }
+
+    let pool_config = warm_limits(state, &state.tunables.load().pool);
+    let manager = Arc::new(VmManager::new(vm_config.clone()).await?);
+    let pool = VmPool::new(
+        manager.clone(),
+        pool_config.clone(),
+        state.boot_backoff.clone(),
+        state.boot_limiter.clone(),
+    );
+    let booted = pool.fill(pool_config.min_vms).await;
+    if booted == 0 && pool_config.min_vms > 0 {
+        pool.shutdown().await;
+        return Err(anyhow::anyhow!("None of {} VMs booted with the new settings", pool_config.min_vms));
+    }
+
+    let previous = state.generations.roll(VmBackend {
+        config: vm_config,
+        manager,
+        pool,
+    });
+    info!(
+        "Rolled VMs to generation {} ({} warm); generation {} is draining",
+        previous.number + 1,
+        booted,
+        previous.number
+    );
+
+    // The draining pool shouldn't boot replacements, and reserved VMs are
+    // checked out, so they'd keep it from ever being retired
+    let draining = PoolConfig {
+        min_vms: 0,
+        ..pool_config
+    };
+    previous.backend.pool.set_limits(draining).await;
+    for vm in state.reserved_vms.excess(0) {
+        release_vm(state, vm).await;
+    }
+    retire_drained(state).await;
+    Ok(())
+}
+
+// Shut down draining generations with no VMs checked out, tearing down the
+// idle VMs their pools still hold
+async fn retire_drained(state: &AppState) {
+    for generation in state.generations.take_drained() {
+        let backend = &generation.backend;
+        let idle = backend.manager.list_active_vms();
+        backend.pool.shutdown().await;
+        info!("Retired VM generation {} and its {} idle VMs", generation.number, idle.len());
+    }
+}
+
+// Active VMs of every generation, each tagged with the one it belongs to
+async fn list_active_vms(state: &AppState) -> Option<Vec<VmInfo>> {
+    let mut listed: Option<Vec<VmInfo>> = None;
+    for generation in state.generations.all() {
+        let vms = generation.backend.manager.list_active_vms().into_iter().map(|vm| VmInfo {
+            generation: Some(generation.number),
+            ..vm
+        });
+        listed.get_or_insert_with(Vec::new).extend(vms);
+    }
+    listed
+}
+
+// Serve live invocations of idempotent functions from the result cache,
+// skipping VM acquisition entirely on a hit. Failures are never cached, and
+// test runs and replays always run the function.
+async fn run_cached(
+    state: &AppState,
+    function: &Function,
+    payload: serde_json::Value,
+    deadline: Option<Deadline>,
+    priority: Priority,
+    kind: InvocationKind,
+) -> Result<PoolExecution> {
+    if !function.idempotent || kind != InvocationKind::Live {
+        return run_with_policy(state, function, payload, deadline, priority, kind).await;
+    }
+
+    let key = CacheKey::new(function, &payload);
+    if let Some(result) = state.result_cache.get(&key) {
+        state.metrics.record_cache(true);
+        return Ok(PoolExecution {
+            result,
+            cold_start: false,
+            cached: true,
+            usage: None,
+            vm_id: None,
+            invocation_id: None,
+        });
+    }
+    state.metrics.record_cache(false);
+
+    let execution = run_with_policy(state, function, payload, deadline, priority, kind).await?;
+    state.result_cache.insert(key, execution.result.clone());
+    Ok(execution)
+}
+
+// Execute the function on a pooled VM, then apply the operator's output
+// policy, so cached results are stored already redacted
+async fn run_with_policy(
+    state: &AppState,
+    function: &Function,
+    payload: serde_json::Value,
+    deadline: Option<Deadline>,
+    priority: Priority,
+    kind: InvocationKind,
+) -> Result<PoolExecution> {
+    let mut execution = run_on_pool(state, function, payload, deadline, priority, kind).await?;
+    execution.result = state.output_policy.apply(execution.result)?;
+    Ok(execution)
+}
+
+async fn run_on_pool(
+    state: &AppState,
+    function: &Function,
+    payload: serde_json::Value,
+    deadline: Option<Deadline>,
+    priority: Priority,
+    kind: InvocationKind,
+) -> Result<PoolExecution> {
+    // Only execution failures of live traffic count against the breaker; a
+    // starved pool says nothing about the function
+    let qualified_name = function.qualified_name();
+    state.breakers.check(&qualified_name)?;
+    let live = kind == InvocationKind::Live;
+
+    #[cfg(feature = "local-runtime")]
+    if state.config.execution.mode == ExecutionMode::Local {
+        let timeout = execution_timeout(state, deadline);
+        let outcome =
+            local_runtime::execute(function, payload, timeout, state.config.invoke.max_response_bytes).await;
+        if let Some(exceeded) = outcome.is_err().then(|| deadline_exceeded(deadline)).flatten() {
+            return Err(exceeded.into());
+        }
+        if live {
+            state.breakers.record(&qualified_name, outcome.is_ok());
+        }
+        return outcome.map(|result| PoolExecution {
+            result,
+            cold_start: false,
+            cached: false,
+            usage: None,
+            vm_id: None,
+            invocation_id: None,
+        });
+    }
+
+    let (mut vm, cold_start, _turn) = acquire_vm(state, function, deadline, priority)
+        .instrument(info_span!("acquire"))
+        .await?;
+    record_vm(&vm, cold_start);
+    // Live runs count toward demand for as long as they hold the VM
+    let _hold = live.then(|| state.demand.hold());
+
+    let forced = state.flushed_vms.forced();
+    let execute = vm
+        .execute_function(
+            &state.v8_client,
+            function,
+            payload,
+            execution_timeout(state, deadline),
+            state.config.invoke.max_response_bytes,
+        )
+        .instrument(info_span!("execute"));
+    let outcome = tokio::select! {
+        outcome = execute => outcome,
+        _ = forced.cancelled() => return Err(kill_flushed_vm(state, vm).await.into()),
+    };
+
+    let (result, usage, timings) = match outcome {
+        Ok(output) => output,
+        Err(e) => {
+            let exceeded = deadline_exceeded(deadline);
+            if exceeded.is_none() && live {
+                state.breakers.record(&qualified_name, false);
+            }
+            discard_failed_vm(state, vm, &e).await;
+            return Err(exceeded.map_or(e, Into::into));
+        }
+    };
+    if live {
+        state.breakers.record(&qualified_name, true);
+    }
+    state.metrics.record_host_timings(&timings);
+    function.status.ready();
+
+    let vm_id = vm.id;
+    release_vm(state, vm).instrument(info_span!("release")).await;
+
+    Ok(PoolExecution {
+        result,
+        cold_start,
+        cached: false,
+        usage,
+        vm_id: Some(vm_id),
+        invocation_id: None,
+    })
+}
+
+// Whatever is left of the caller's deadline, else the configured timeout
+fn execution_timeout(state: &AppState, deadline: Option<Deadline>) -> Duration {
+    deadline.map_or(state.tunables.load().timeouts.execution_timeout(), |d| d.remaining())
+}
+
+// A call that failed with its deadline spent ran out of caller budget. That
+// says nothing about the function, so it doesn't count against the breaker.
+fn deadline_exceeded(deadline: Option<Deadline>) -> Option<DeadlineExceeded> {
+    deadline.filter(|d| d.remaining().is_zero()).map(|d| d.exceeded())
+}
+
+// Return a VM to the pool, or recycle it once it has served its quota of
+// invocations so state can't accumulate in a long-lived V8 context
+async fn release_vm(state: &AppState, vm: VmInstance) {
+    let max_invocations = state.tunables.load().pool.max_invocations_per_vm;
+    if vm.state == VmState::Failed {
+        fail_vm(state, vm, "Failed while checked out").await;
+    } else if state.flushed_vms.take(vm.id) {
+        info!("Discarding VM {} flushed while busy", vm.id);
+        discard_vm(state, vm).await;
+    } else if max_invocations > 0 && vm.invocation_count >= max_invocations {
+        info!("Recycling VM {} after {} invocations", vm.id, vm.invocation_count);
+        discard_vm(state, vm).await;
+    } else if vm.generation != Some(state.generations.current().number) {
+        info!("Retiring VM {} from draining generation {:?}", vm.id, vm.generation);
+        discard_vm(state, vm).await;
+    } else {
+        publish_vm_state(state, &vm, VmState::Ready);
+        return_to_pool(state, vm, true).await;
+    }
+}
+
+fn bool_header(value: bool) -> &'static str {
+    if value {
+        "true"
+    } else {
+        "false"
+    }
+}
+
+// Live platform events as Server-Sent Events, each named after its type.
+// Subscribers that fall too far behind skip the events they missed.
+async fn stream_events(
+    State(state): State<AppState>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError> {
+    let types: Option<Vec<String>> = query.types.map(|types| {
+        types
+            .split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect()
+    });
+    if let Some(unknown) = types
+        .iter()
+        .flatten()
+        .find(|t| !PlatformEvent::KINDS.contains(&t.as_str()))
+    {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Unknown event type: {} (expected one of {})", unknown, PlatformEvent::KINDS.join(", ")),
+        ));
+    }
+
+    let events = stream::unfold(state.events.subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((event, receiver)),
+                Err(RecvError::Lagged(missed)) => warn!("Event subscriber lagged; skipped {} events", missed),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+    .filter(move |event| {
+        let wanted = types.as_ref().is_none_or(|types| types.iter().any(|t| t == event.kind()));
+        future::ready(wanted)
+    })
+    .map(|event| Event::default().event(event.kind()).json_data(&event))
+    // Open streams would otherwise hold a graceful shutdown forever
+    .take_until(state.shutdown.clone().cancelled_owned());
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+// List active VMs
+async fn list_vms(
+    State(state): State<AppState>,
+    Query(query): Query<VmListQuery>,
+) -> Result<Json<VmListResponse>, ApiError> {
+    let wanted: Option<VmState> = query
+        .state
+        .as_deref()
+        .map(str::parse)
+        .transpose()
+        .map_err(|e: anyhow::Error| ApiError::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let mut vms = list_active_vms(&state).await;
+    // Failed VMs are gone from the pool, so they're listed separately
+    let retention = state.tunables.load().pool.failed_vm_retention();
+    let failed = state.failed_vms.list(std::time::Instant::now(), retention);
+    if !failed.is_empty() {
+        vms.get_or_insert_with(Vec::new).extend(failed);
+    }
+    if let (Some(wanted), Some(vms)) = (wanted, vms.as_mut()) {
+        vms.retain(|vm| vm.state == wanted);
+    }
+    Ok(Json(VmListResponse { vms }))
+}
+
+// Probe every VM's V8 host and report which are healthy, degraded or
+// unreachable. With `?reap=true` unreachable VMs are marked like ones that
+// fail an idle ping, and replaced when next checked out; VMs still booting
+// are left alone. Reaping is admin only.
+async fn healthcheck_vms(
+    State(state): State<AppState>,
+    Query(query): Query<HealthcheckQuery>,
+    headers: HeaderMap,
+) -> Result<Json<HealthcheckResponse>, ApiError> {
+    if query.reap {
+        authorize_admin(&state.config, &headers)?;
+    }
+    ensure_vm_execution(&state)?;
+
+    let vms = list_active_vms(&state).await.unwrap_or_default();
+    let probes = stream::iter(vms)
+        .map(|vm| {
+            let client = state.v8_client.clone();
+            async move {
+                let probe = vm.probe(&client, VM_PING_TIMEOUT, VM_DEGRADED_LATENCY).await;
+                (vm, probe)
+            }
+        })
+        .buffered(HEALTHCHECK_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut response = HealthcheckResponse {
+        healthy: 0,
+        degraded: 0,
+        unreachable: 0,
+        reaped: 0,
+        vms: Vec::with_capacity(probes.len()),
+    };
+    for (vm, probe) in probes {
+        match probe.health {
+            VmHealth::Healthy => response.healthy += 1,
+            VmHealth::Degraded => response.degraded += 1,
+            VmHealth::Unreachable => response.unreachable += 1,
+        }
+        let reaped = query.reap && probe.health == VmHealth::Unreachable && vm.state != VmState::Starting;
+        if reaped {
+            match vm.id.parse() {
+                Ok(id) => {
+                    warn!("VM {} is unreachable; it will be replaced", vm.id);
+                    state.unhealthy_vms.mark(id);
+                    response.reaped += 1;
+                }
+                Err(_) => warn!("Cannot reap VM with unparseable id {}", vm.id),
+            }
+        }
+        response.vms.push(VmHealthReport {
+            vm_id: vm.id,
+            state: vm.state,
+            probe,
+            reaped,
+        });
+    }
+
+    info!(
+        "Health check of {} VMs: {} healthy, {} degraded, {} unreachable, {} reaped",
+        response.vms.len(),
+        response.healthy,
+        response.degraded,
+        response.unreachable,
+        response.reaped
+    );
+    Ok(Json(response))
+}
+
+// Set the warm pool size until the next config reload. The pool boots or
+// drains VMs toward it in the background; it can't exceed pool.max_vms.
+// With predictive warming on this sets the floor under the forecast.
+// Admin only.
+async fn scale_pool(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    request: Result<Json<ScalePoolRequest>, JsonRejection>,
+) -> Result<Json<ScalePoolResponse>, ApiError> {
+    authorize_admin(&state.config, &headers)?;
+    ensure_vm_execution(&state)?;
+    let target = json_body(request)?.target;
+
+    let mut tunables = Tunables::clone(&state.tunables.load());
+    let max_vms = tunables.pool.max_vms;
+    if target > max_vms {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Target {} exceeds pool.max_vms ({})", target, max_vms),
+        ));
+    }
+
+    let previous = std::mem::replace(&mut tunables.pool.min_vms, target);
+    state.generations.current().backend.pool.set_limits(warm_limits(&state, &tunables.pool)).await;
+    state.tunables.store(Arc::new(tunables));
+
+    info!("Scaled warm pool target from {} to {} VMs", previous, target);
+    Ok(Json(ScalePoolResponse {
+        warm_target: target,
+        max_vms,
+    }))
+}
+
+// Tear down every VM in the pool, e.g. after shipping a new rootfs or V8
+// image. Idle VMs go now; busy ones finish their invocations first unless
+// `?force=true`, which kills them. The pool then boots back up to its warm
+// target in the background. Admin only.
+async fn flush_pool(
+    State(state): State<AppState>,
+    Query(query): Query<FlushPoolQuery>,
+    headers: HeaderMap,
+) -> Result<Json<FlushPoolResponse>, ApiError> {
+    authorize_admin(&state.config, &headers)?;
+    ensure_vm_execution(&state)?;
+
+    let vms = list_active_vms(&state).await.unwrap_or_default();
+    let ids: Vec<Uuid> = vms.iter().filter_map(|vm| vm.id.parse().ok()).collect();
+    state.flushed_vms.flush(ids.iter().copied(), query.force);
+
+    // Idle VMs of draining generations go when those are retired
+    let idle = state.generations.current().backend.pool.drain_idle().await;
+    for vm in &idle {
+        state.flushed_vms.take(vm.id);
+        publish_vm_state(&state, vm, VmState::Stopping);
+    }
+
+    let draining = if query.force { 0 } else { vms.len().saturating_sub(idle.len()) };
+    info!(
+        "Flushed {} VMs from the pool ({} draining{})",
+        ids.len(),
+        draining,
+        if query.force { ", forced" } else { "" }
+    );
+    Ok(Json(FlushPoolResponse {
+        flushed: ids.len(),
+        draining,
+    }))
+}
+
+// Aggregated pool state
+async fn pool_stats(State(state): State<AppState>) -> Json<PoolStatsResponse> {
+    let vms = list_active_vms(&state).await.unwrap_or_default();
+
+    let mut vms_by_state = HashMap::new();
+    for vm in &vms {
+        *vms_by_state.entry(vm.state.clone()).or_insert(0) += 1;
+    }
+
+    let tunables = state.tunables.load();
+    Json(PoolStatsResponse {
+        total_vms: vms.len(),
+        vms_by_state,
+        waiters: state.acquire_tracker.waiters(),
+        checked_out: state.fair_queue.in_use(),
+        booting: state.boot_limiter.booting(),
+        warm_target: warm_limits(&state, &tunables.pool).min_vms,
+        reserved: state.reserved_vms.count(),
+        max_vms: tunables.pool.max_vms,
+        acquire_wait: state.acquire_tracker.percentiles(),
+        queued_by_function: state.fair_queue.depths(),
+        saturation: state.saturation.stats(tunables.pool.saturation_window()),
+        generation: state.generations.status(),
+        forecast: state.demand.stats(tunables.pool.min_vms, tunables.pool.max_vms),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // State for a server whose VMs can never boot, as on a host missing its
+    // kernel image
+    async fn failing_state(base: &std::path::Path) -> AppState {
+        state_with(failing_config(base)).await
+    }
+
+    fn failing_config(base: &std::path::Path) -> Config {
+        let mut config = Config::default();
+        config.vm.kernel_path = base.join("missing-vmlinux").to_string_lossy().into_owned();
+        config.vm.work_dir_base = base.join("vms").to_string_lossy().into_owned();
+        config.vm.port_range_start = 9300;
+        config.vm.port_range_end = 9310;
+        config.pool.min_vms = 0;
+        config.pool.boot_backoff_initial_ms = 1;
+        config
+    }
+
+    // A failing_state whose admin API takes `Bearer secret`
+    async fn admin_state(base: &std::path::Path) -> AppState {
+        let mut config = failing_config(base);
+        config.admin.token = Some("secret".to_string());
+        state_with(config).await
+    }
+
+    async fn state_with(config: Config) -> AppState {
+        let state = build_state(Arc::new(config)).await.unwrap();
+        state.warmup.open();
+        state
+    }
+
+    async fn create_function(state: &AppState, name: &str) -> Function {
+        create_function_with(state, CreateFunctionRequest {
+            name: name.to_string(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    async fn create_function_with(state: &AppState, request: CreateFunctionRequest) -> Function {
+        let request = CreateFunctionRequest {
+            code: "export default () => 1".to_string(),
+            runtime: "v8".to_string(),
+            ..request
+        };
+        state.function_store.create(function::DEFAULT_NAMESPACE, request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_boot_outage_reported() {
+        let base = tempfile::tempdir().unwrap();
+        let state = failing_state(base.path()).await;
+        let function = create_function(&state, "hello").await;
+
+        // Failures before the outage threshold are reported as they are
+        for _ in 1..BOOT_OUTAGE_FAILURES {
+            let Err(error) = acquire_vm(&state, &function, None, Priority::Normal).await else {
+                panic!("a VM booted without a kernel");
+            };
+            assert!(!matches!(error.downcast_ref(), Some(HyperdriveError::NoBootableVms(_))), "{:#}", error);
+        }
+
+        let Err(error) = acquire_vm(&state, &function, None, Priority::Normal).await else {
+            panic!("a VM booted without a kernel");
+        };
+        let response = execution_error(error);
+        assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+        let message = response.body.unwrap().error;
+        assert!(message.contains("missing-vmlinux not found"), "{}", message);
+        assert!(message.contains("vm.kernel_path"), "{}", message);
+        assert!(!state.failed_vms.list(std::time::Instant::now(), Duration::from_secs(60)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_checkout_checked_back_in() {
+        let base = tempfile::tempdir().unwrap();
+        let state = failing_state(base.path()).await;
+        let generation = state.generations.current();
+        let limits = PoolConfig {
+            min_vms: 0,
+            boot_backoff_initial_ms: 60_000,
+            ..state.config.pool.clone()
+        };
+        generation.backend.pool.set_limits(limits).await;
+
+        checkout_vm(&state, None).await.unwrap_err();
+        assert_eq!(generation.checked_out(), 0);
+
+        // Abandoned while waiting out the boot backoff
+        let abandoned = tokio::time::timeout(Duration::from_millis(1), checkout_vm(&state, None)).await;
+        assert!(abandoned.is_err());
+        assert_eq!(generation.checked_out(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_ip_rules_cover_test_invocations() {
+        let base = tempfile::tempdir().unwrap();
+        let state = failing_state(base.path()).await;
+        let request = CreateFunctionRequest {
+            name: "internal".to_string(),
+            allowed_ips: vec!["10.0.0.0/8".to_string()],
+            ..Default::default()
+        };
+        let function = create_function_with(&state, request).await;
+
+        let mut request = Request::new(Body::from("{}"));
+        request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([192, 0, 2, 1], 40000))));
+        let query = InvokeQuery::default();
+        let Err(rejected) = invoke_resolved(&state, function, &query, request, None, InvocationKind::Test).await
+        else {
+            panic!("test invocation bypassed the function's IP rules");
+        };
+        assert_eq!(rejected.status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_only_live_invocations_use_the_cache() {
+        let base = tempfile::tempdir().unwrap();
+        let state = failing_state(base.path()).await;
+        let request = CreateFunctionRequest {
+            name: "pure".to_string(),
+            idempotent: true,
+            ..Default::default()
+        };
+        let function = create_function_with(&state, request).await;
+        let payload = serde_json::json!({"n": 1});
+        state.result_cache.insert(CacheKey::new(&function, &payload), serde_json::json!("cached"));
+
+        let live = run_cached(&state, &function, payload.clone(), None, Priority::Normal, InvocationKind::Live);
+        let live = live.await.unwrap();
+        assert!(live.cached);
+        assert_eq!(live.result, serde_json::json!("cached"));
+
+        // Test runs and replays go to a VM, which here can't boot
+        for kind in [InvocationKind::Test, InvocationKind::Replay(Uuid::new_v4())] {
+            let run = run_cached(&state, &function, payload.clone(), None, Priority::Normal, kind).await;
+            assert!(run.is_err(), "{:?} was served from the cache", kind);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_needs_admin_token() {
+        let base = tempfile::tempdir().unwrap();
+        let state = admin_state(base.path()).await;
+        let create = |name: &str, max_priority, token: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(token) = token {
+                headers.insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+            }
+            let request = CreateFunctionRequest {
+                name: name.to_string(),
+                code: "export default () => 1".to_string(),
+                runtime: "v8".to_string(),
+                max_priority,
+                ..Default::default()
+            };
+            let path = NamespacePath {
+                namespace: function::DEFAULT_NAMESPACE.to_string(),
+            };
+            super::create_function(State(state.clone()), Path(path), headers, Ok(Json(request)))
+        };
+
+        assert!(create("background", Priority::Low, None).await.is_ok());
+        assert!(create("normal", Priority::Normal, None).await.is_ok());
+        for token in [None, Some("wrong")] {
+            let Err(error) = create("interactive", Priority::High, token).await else {
+                panic!("created a high priority function without the admin token");
+            };
+            assert_eq!(error.status, StatusCode::FORBIDDEN);
+        }
+        assert!(create("interactive", Priority::High, Some("secret")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_batch_charged_per_payload() {
+        use tower::Service;
+
+        let base = tempfile::tempdir().unwrap();
+        let mut config = failing_config(base.path());
+        config.rate_limit.invocations = 3;
+        let state = state_with(config).await;
+        let mut app = function_routes(&state).with_state(state);
+        let batch = |payloads: &str| {
+            Request::post("/missing/invoke/batch")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(payloads.to_string()))
+                .unwrap()
+        };
+
+        let response = app.call(batch("[1, 2]")).await.unwrap();
+        assert_eq!(response.headers()["x-ratelimit-remaining"], "1");
+        let response = app.call(batch("[1, 2]")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let response = app.call(batch("[1]")).await.unwrap();
+        assert_eq!(response.headers()["x-ratelimit-remaining"], "0");
+    }
+
+    #[test]
+    fn test_http_response_skips_output_transform() {
+        let transform = Transform::compile(serde_json::json!({ "total": "$.sum" })).unwrap();
+        let function = Function {
+            output_transform: Some(transform),
+            ..Function::fixture("shaped", "export default () => 1")
+        };
+        let result = serde_json::json!({ "sum": 3 });
+        assert_eq!(transform_output(&function, result), serde_json::json!({ "total": 3 }));
+
+        let envelope = serde_json::json!({ "status": 201, "body": "created" });
+        let function = Function {
+            http_response: true,
+            ..function
+        };
+        assert_eq!(transform_output(&function, envelope.clone()), envelope);
+    }
+
+    #[tokio::test]
+    async fn test_signed_responses_not_streamed() {
+        let base = tempfile::tempdir().unwrap();
+        let mut config = failing_config(base.path());
+        config.signing.key = Some("secret".to_string());
+        let state = state_with(config).await;
+        let plain = create_function(&state, "plain").await;
+        let signed = create_function_with(&state, CreateFunctionRequest {
+            name: "signed".to_string(),
+            sign_responses: true,
+            ..Default::default()
+        })
+        .await;
+
+        assert!(ensure_streamable(&state, &plain).is_ok());
+        let Err(refused) = ensure_streamable(&state, &signed) else {
+            panic!("a signed function's response was streamed");
+        };
+        assert_eq!(refused.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn test_import_needs_signing_key() {
+        let base = tempfile::tempdir().unwrap();
+        let state = failing_state(base.path()).await;
+        let exported = ExportedFunction {
+            function: CreateFunctionRequest {
+                name: "signed".to_string(),
+                code: "export default () => 1".to_string(),
+                runtime: "v8".to_string(),
+                sign_responses: true,
+                ..Default::default()
+            },
+            invocation_count: 0,
+            error_count: 0,
+        };
+        let document = FunctionExport {
+            exported_at: chrono::Utc::now().to_rfc3339(),
+            functions: vec![exported],
+        };
+        let path = NamespacePath {
+            namespace: function::DEFAULT_NAMESPACE.to_string(),
+        };
+        let query = ImportQuery::default();
+        let document = Ok(Json(document));
+        let imported = import_functions(State(state.clone()), Path(path), Query(query), HeaderMap::new(), document);
+        let Err(rejected) = imported.await else {
+            panic!("imported a signed function without a signing key");
+        };
+        assert_eq!(rejected.status, StatusCode::BAD_REQUEST);
+        assert!(state.function_store.get(function::DEFAULT_NAMESPACE, "signed").await.is_none());
+    }
+
+    #[test]
+    fn test_location_encodes_non_ascii_names() {
+        let function = Function {
+            namespace: "équipe".to_string(),
+            ..Function::fixture("café", "export default () => 1")
+        };
+        let (_, headers, _) = created_response(function);
+        assert_eq!(headers[header::LOCATION], "/api/v1/namespaces/%C3%A9quipe/functions/caf%C3%A9");
+
+        let (_, headers, _) = created_response(Function::fixture("plain-name_1", "export default () => 1"));
+        assert_eq!(headers[header::LOCATION], "/api/v1/functions/plain-name_1");
+    }
+
+    #[tokio::test]
+    async fn test_replay_responds_as_invoke() {
+        let base = tempfile::tempdir().unwrap();
+        let mut config = failing_config(base.path());
+        config.signing.key = Some("secret".to_string());
+        let state = state_with(config).await;
+        let execution = |result| PoolExecution {
+            result,
+            cold_start: false,
+            cached: false,
+            usage: None,
+            vm_id: None,
+            invocation_id: Some(Uuid::new_v4()),
+        };
+        let started = std::time::Instant::now();
+
+        // Replays answer with every header invoke does, signature included
+        let signed = Function {
+            sign_responses: true,
+            ..Function::fixture("signed", "export default () => 1")
+        };
+        let response = execution_response(&state, &signed, execution(serde_json::json!(1)), started, true).unwrap();
+        let response = sign_response(State(state.clone()), response).await;
+        assert!(response.headers().contains_key("x-signature"));
+        assert!(response.headers().contains_key("x-invocation-id"));
+
+        // and an http_response function's own response, not the envelope
+        let described = Function {
+            http_response: true,
+            ..Function::fixture("described", "export default () => 1")
+        };
+        let result = serde_json::json!({ "status": 201, "body": "created" });
+        let response = execution_response(&state, &described, execution(result), started, true).unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(!response.headers().contains_key("x-signature"));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"created");
+    }
+
+    #[tokio::test]
+    async fn test_flush_needs_admin_token() {
+        let base = tempfile::tempdir().unwrap();
+        let state = admin_state(base.path()).await;
+        let query = FlushPoolQuery { force: true };
+        let Err(rejected) = flush_pool(State(state), Query(query), HeaderMap::new()).await else {
+            panic!("flushed the pool without the admin token");
+        };
+        assert_eq!(rejected.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_reap_needs_admin_token() {
+        let base = tempfile::tempdir().unwrap();
+        let state = admin_state(base.path()).await;
+        let probe = healthcheck_vms(State(state.clone()), Query(HealthcheckQuery { reap: false }), HeaderMap::new());
+        assert!(probe.await.is_ok());
+
+        let reap = healthcheck_vms(State(state), Query(HealthcheckQuery { reap: true }), HeaderMap::new());
+        let Err(rejected) = reap.await else {
+            panic!("reaped VMs without the admin token");
+        };
+        assert_eq!(rejected.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_scale_needs_admin_token() {
+        let base = tempfile::tempdir().unwrap();
+        let state = admin_state(base.path()).await;
+        let request = Ok(Json(ScalePoolRequest { target: 5 }));
+        let Err(rejected) = scale_pool(State(state.clone()), HeaderMap::new(), request).await else {
+            panic!("scaled the pool without the admin token");
+        };
+        assert_eq!(rejected.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(state.tunables.load().pool.min_vms, 0);
+    }
+
+    #[tokio::test]
+    async fn test_batch_rejects_large_integers_per_item() {
+        let base = tempfile::tempdir().unwrap();
+        let mut config = failing_config(base.path());
+        config.invoke.large_integers = LargeIntegers::Reject;
+        let state = state_with(config).await;
+        create_function(&state, "ids").await.status.ready();
+
+        let path = FunctionPath {
+            namespace: function::DEFAULT_NAMESPACE.to_string(),
+            name: "ids".to_string(),
+        };
+        let payloads = vec![serde_json::json!({ "id": 1 }), serde_json::json!({ "id": 9007199254740993u64 })];
+        let batch = invoke_function_batch(
+            State(state),
+            Path(path),
+            Query(InvokeQuery::default()),
+            None,
+            HeaderMap::new(),
+            Ok(Json(payloads)),
+        );
+        let Ok((_, _, Json(response))) = batch.await else {
+            panic!("one large integer failed the whole batch");
+        };
+        let BatchItemResult::Error { error } = &response.results[1] else {
+            panic!("the large integer was accepted");
+        };
+        assert!(error.contains("$.id"), "{}", error);
+        // The VM can't boot, so the other item fails for its own reason
+        let BatchItemResult::Error { error } = &response.results[0] else {
+            panic!("a VM booted without a kernel");
+        };
+        assert!(!error.contains("safe range"), "{}", error);
+    }
+}
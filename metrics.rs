@@ -0,0 +1,148 @@
+use anyhow::Result;
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::time::Duration;
+
+use crate::types::HostTimings;
+
+pub struct Metrics {
+    registry: Registry,
+    vm_acquire_seconds: HistogramVec,
+    vm_affinity_total: IntCounterVec,
+    result_cache_total: IntCounterVec,
+    result_cache_entries: IntGauge,
+    result_cache_bytes: IntGauge,
+    pool_saturation: Gauge,
+    invocation_phase_seconds: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new();
+
+        // Labeled by whether acquire had to boot a VM (cold) or reused one (warm)
+        let vm_acquire_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "hyperdrive_vm_acquire_seconds",
+                "Time spent acquiring a VM from the pool",
+            )
+            .buckets(vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]),
+            &["start"],
+        )?;
+        registry.register(Box::new(vm_acquire_seconds.clone()))?;
+
+        // Hit rate = hit / (hit + miss)
+        let vm_affinity_total = IntCounterVec::new(
+            Opts::new(
+                "hyperdrive_vm_affinity_total",
+                "Affinity-aware acquires by whether the VM already had the function loaded",
+            ),
+            &["result"],
+        )?;
+        registry.register(Box::new(vm_affinity_total.clone()))?;
+
+        // Only idempotent functions consult the cache
+        let result_cache_total = IntCounterVec::new(
+            Opts::new(
+                "hyperdrive_result_cache_total",
+                "Result cache lookups by whether a cached result was served",
+            ),
+            &["result"],
+        )?;
+        registry.register(Box::new(result_cache_total.clone()))?;
+
+        // Sampled on scrape
+        let result_cache_entries =
+            IntGauge::new("hyperdrive_result_cache_entries", "Results currently held in the result cache")?;
+        registry.register(Box::new(result_cache_entries.clone()))?;
+        let result_cache_bytes = IntGauge::new(
+            "hyperdrive_result_cache_bytes",
+            "Serialized size of the results currently held in the result cache",
+        )?;
+        registry.register(Box::new(result_cache_bytes.clone()))?;
+
+        // Sliding-window average, so scrapes don't miss short spikes between them
+        let pool_saturation = Gauge::new(
+            "hyperdrive_pool_saturation",
+            "Busy VMs as a fraction of pool capacity, averaged over the saturation window",
+        )?;
+        registry.register(Box::new(pool_saturation.clone()))?;
+
+        // queue: waiting for the function's turn; acquire: getting a healthy
+        // VM; load and execute: the V8 host's side (see HostTimings);
+        // response_read: receiving and parsing the result
+        let invocation_phase_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "hyperdrive_invocation_phase_seconds",
+                "Time spent in each phase of a pooled invocation",
+            )
+            .buckets(vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]),
+            &["phase"],
+        )?;
+        registry.register(Box::new(invocation_phase_seconds.clone()))?;
+
+        Ok(Self {
+            registry,
+            vm_acquire_seconds,
+            vm_affinity_total,
+            result_cache_total,
+            result_cache_entries,
+            result_cache_bytes,
+            pool_saturation,
+            invocation_phase_seconds,
+        })
+    }
+
+    pub fn record_acquire(&self, cold_start: bool, elapsed: Duration) {
+        let start = if cold_start { "cold" } else { "warm" };
+        self.vm_acquire_seconds
+            .with_label_values(&[start])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn record_affinity(&self, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.vm_affinity_total.with_label_values(&[result]).inc();
+    }
+
+    pub fn record_cache(&self, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.result_cache_total.with_label_values(&[result]).inc();
+    }
+
+    pub fn set_cache_size(&self, entries: usize, bytes: usize) {
+        self.result_cache_entries.set(entries as i64);
+        self.result_cache_bytes.set(bytes as i64);
+    }
+
+    pub fn record_acquire_phases(&self, queue: Duration, acquire: Duration) {
+        self.observe_phase("queue", queue);
+        self.observe_phase("acquire", acquire);
+    }
+
+    pub fn record_host_timings(&self, timings: &HostTimings) {
+        if let Some(load) = timings.load {
+            self.observe_phase("load", load);
+        }
+        self.observe_phase("execute", timings.execute);
+        self.observe_phase("response_read", timings.response_read);
+    }
+
+    fn observe_phase(&self, phase: &str, elapsed: Duration) {
+        self.invocation_phase_seconds
+            .with_label_values(&[phase])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn set_pool_saturation(&self, ratio: f64) {
+        self.pool_saturation.set(ratio);
+    }
+
+    // Prometheus text exposition format
+    pub fn render(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
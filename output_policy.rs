@@ -0,0 +1,145 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::transform::{parse_path, Segment};
+use crate::types::HyperdriveError;
+
+// Replaces redacted values, so clients can tell a field was withheld
+const REDACTED: &str = "[REDACTED]";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct OutputPolicyConfig {
+    // Result paths to mask, in transform syntax: "$.user.ssn", "$.cards[0]"
+    pub redact: Vec<String>,
+    // Largest result returned after redaction; unset leaves only
+    // invoke.max_response_bytes
+    pub max_result_bytes: Option<usize>,
+    pub strip_nulls: bool, // drop object fields whose value is null
+}
+
+// Operator rules applied to every function's result as it comes back from
+// the VM, before it's cached or reshaped by the function's own output
+// transform. Redactions run first, then null stripping, then the size check.
+pub struct OutputPolicy {
+    redact: Vec<Vec<Segment>>,
+    max_result_bytes: Option<usize>,
+    strip_nulls: bool,
+}
+
+impl OutputPolicy {
+    pub fn new(config: &OutputPolicyConfig) -> Result<Self> {
+        let redact = config
+            .redact
+            .iter()
+            .map(|path| match parse_path(path) {
+                Ok(segments) if segments.is_empty() => Err(anyhow::anyhow!("cannot redact the whole result")),
+                Ok(segments) => Ok(segments),
+                Err(e) => Err(anyhow::anyhow!(e)),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            redact,
+            max_result_bytes: config.max_result_bytes,
+            strip_nulls: config.strip_nulls,
+        })
+    }
+
+    // No rules configured, so results pass through untouched
+    pub fn is_empty(&self) -> bool {
+        self.redact.is_empty() && self.max_result_bytes.is_none() && !self.strip_nulls
+    }
+
+    pub fn apply(&self, mut result: Value) -> Result<Value> {
+        for path in &self.redact {
+            redact(&mut result, path);
+        }
+        if self.strip_nulls {
+            strip_nulls(&mut result);
+        }
+        if let Some(max) = self.max_result_bytes {
+            if serde_json::to_vec(&result)?.len() > max {
+                return Err(HyperdriveError::ResponseTooLarge(max).into());
+            }
+        }
+        Ok(result)
+    }
+}
+
+// Mask the value at `path` if it's there; missing paths are left alone
+fn redact(value: &mut Value, path: &[Segment]) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let parent = parents.iter().try_fold(value, |value, segment| match segment {
+        Segment::Field(name) => value.get_mut(name),
+        Segment::Index(index) => value.get_mut(index),
+    });
+    let target = parent.and_then(|parent| match last {
+        Segment::Field(name) => parent.get_mut(name),
+        Segment::Index(index) => parent.get_mut(index),
+    });
+    if let Some(target) = target {
+        *target = Value::String(REDACTED.to_string());
+    }
+}
+
+fn strip_nulls(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            fields.retain(|_, field| !field.is_null());
+            fields.values_mut().for_each(strip_nulls);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_output_policy() {
+        let policy = OutputPolicy::new(&OutputPolicyConfig {
+            redact: vec!["$.user.ssn".to_string(), "$.cards[1]".to_string(), "$.missing.path".to_string()],
+            max_result_bytes: Some(100),
+            strip_nulls: true,
+        })
+        .unwrap();
+
+        let result = policy
+            .apply(json!({
+                "user": {"name": "Ada", "ssn": "123-45-6789", "email": null},
+                "cards": ["1111", "2222"],
+                "items": [{"note": null}],
+            }))
+            .unwrap();
+        assert_eq!(
+            result,
+            json!({
+                "user": {"name": "Ada", "ssn": "[REDACTED]"},
+                "cards": ["1111", "[REDACTED]"],
+                "items": [{}],
+            })
+        );
+
+        let error = policy.apply(json!({"blob": "x".repeat(200)})).unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(HyperdriveError::ResponseTooLarge(100))));
+
+        let passthrough = OutputPolicy::new(&OutputPolicyConfig::default()).unwrap();
+        assert!(passthrough.is_empty());
+        assert!(!policy.is_empty());
+        assert_eq!(passthrough.apply(json!({"a": null})).unwrap(), json!({"a": null}));
+
+        for path in ["$", "user.ssn"] {
+            let config = OutputPolicyConfig {
+                redact: vec![path.to_string()],
+                ..Default::default()
+            };
+            assert!(OutputPolicy::new(&config).is_err(), "{}", path);
+        }
+    }
+}
@@ -0,0 +1,1848 @@
+use anyhow::Result;
+use futures::future;
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, watch, Notify, Semaphore, SemaphorePermit};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::config::PoolConfig;
+use crate::vm::VmManager;
+use crate::types::{
+    AcquireWaitPercentiles, DemandForecastStats, GenerationStatus, HyperdriveError, Priority, SaturationStats, VmInfo,
+    VmInstance, VmState,
+};
+
+// Acquire waits kept for percentile reporting
+const ACQUIRE_WINDOW: usize = 1024;
+// How often a pool tops itself up to its warm target
+const MAINTAIN_INTERVAL: Duration = Duration::from_secs(1);
+// Idle VMs above the warm target are kept this long in case load returns
+const SURPLUS_IDLE_GRACE: Duration = Duration::from_secs(60);
+
+// Pooled VMs with free invocation slots, least recently released first.
+// Each VM serves up to `slots` invocations at once, so a V8 host that can
+// pipeline I/O-bound calls isn't held by one of them; `Busy` means every
+// slot is taken. With one slot (the default) a VM is checked out
+// exclusively. VMs stay tracked by id while any slot is out, and one
+// retired mid-invocation is only handed back for teardown once its last
+// slot comes back, so the other slots' invocations run to completion.
+pub struct IdleVms {
+    vms: VecDeque<PooledVm>,
+    slots: usize,
+}
+
+struct PooledVm {
+    vm: VmInstance,
+    active: usize, // invocations checked out against this VM
+    retired: bool, // torn down once `active` drops to zero
+}
+
+impl PooledVm {
+    fn has_free_slot(&self, slots: usize) -> bool {
+        !self.retired && self.active < slots
+    }
+}
+
+impl IdleVms {
+    pub fn with_slots(slots: usize) -> Self {
+        Self {
+            vms: VecDeque::new(),
+            slots: slots.max(1),
+        }
+    }
+
+    // Takes effect as slots are next claimed; slots already out stay out
+    pub fn set_slots(&mut self, slots: usize) {
+        self.slots = slots.max(1);
+    }
+
+    // VMs that can take another invocation
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.vms.iter().filter(|pooled| pooled.has_free_slot(self.slots)).count()
+    }
+
+    #[cfg(test)]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Every VM still running, checked out or not
+    pub fn total(&self) -> usize {
+        self.vms.len()
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<&VmInstance> {
+        self.vms.iter().find(|pooled| pooled.vm.id == id).map(|pooled| &pooled.vm)
+    }
+
+    // Add a new VM with every slot free
+    pub fn push(&mut self, vm: VmInstance) {
+        self.vms.push_back(PooledVm {
+            vm,
+            active: 0,
+            retired: false,
+        });
+    }
+
+    // Add a freshly booted VM with one slot already claimed
+    pub fn add_taken(&mut self, vm: VmInstance) -> VmInstance {
+        self.push(vm);
+        let pooled = self.vms.back_mut().expect("just pushed");
+        Self::claim(pooled, self.slots)
+    }
+
+    // Give back a slot handed out by `take`, folding in the invocations run
+    // on it. With `retire` the VM takes no more invocations. Returns the VM
+    // once it's retired and its last slot is back, for the caller to tear
+    // down; a slot of a VM no longer tracked is dropped.
+    pub fn give_back(&mut self, vm: VmInstance, retire: bool) -> Option<VmInstance> {
+        let i = self.vms.iter().position(|pooled| pooled.vm.id == vm.id)?;
+        let mut pooled = self.vms.remove(i).expect("position is in bounds");
+        pooled.active = pooled.active.saturating_sub(1);
+        pooled.retired |= retire;
+        pooled.vm.loaded_function = vm.loaded_function;
+        pooled.vm.last_used = pooled.vm.last_used.max(vm.last_used);
+        // Every slot starts from the count at its checkout, so only what ran
+        // on this one is added
+        pooled.vm.invocation_count += vm.invocation_count.saturating_sub(vm.checkout_invocations);
+
+        if pooled.retired {
+            if pooled.active == 0 {
+                return Some(pooled.vm);
+            }
+            pooled.vm.state = VmState::Stopping;
+            self.vms.insert(i, pooled);
+        } else {
+            pooled.vm.state = if pooled.has_free_slot(self.slots) { VmState::Ready } else { VmState::Busy };
+            self.vms.push_back(pooled);
+        }
+        None
+    }
+
+    // Take a VM out of service, e.g. because it failed or is being
+    // recycled. Returns it for teardown if no slot is out; otherwise it's
+    // returned by `give_back` of the last one.
+    pub fn retire(&mut self, id: Uuid) -> Option<VmInstance> {
+        let i = self.vms.iter().position(|pooled| pooled.vm.id == id)?;
+        if self.vms[i].active > 0 {
+            self.vms[i].retired = true;
+            self.vms[i].vm.state = VmState::Stopping;
+            return None;
+        }
+        self.vms.remove(i).map(|pooled| pooled.vm)
+    }
+
+    // Retire every VM, returning those with no slot out
+    pub fn drain_idle(&mut self) -> Vec<VmInstance> {
+        let ids: Vec<Uuid> = self.vms.iter().map(|pooled| pooled.vm.id).collect();
+        ids.into_iter().filter_map(|id| self.retire(id)).collect()
+    }
+
+    // Stop tracking idle VMs above `keep`, least recently used first, that
+    // haven't been used since `idle_since`
+    pub fn take_surplus(&mut self, keep: usize, idle_since: chrono::DateTime<chrono::Utc>) -> Vec<VmInstance> {
+        let mut surplus = Vec::new();
+        while self.vms.len() > keep {
+            let Some(i) = self
+                .vms
+                .iter()
+                .position(|pooled| pooled.active == 0 && pooled.vm.last_used < idle_since)
+            else {
+                break;
+            };
+            surplus.extend(self.vms.remove(i).map(|pooled| pooled.vm));
+        }
+        surplus
+    }
+
+    // Claim a slot, preferring the most recently used VM that already has
+    // `affinity_key` loaded so the V8 host can skip reloading the code. Falls
+    // back to the least recently used VM. The flag reports an affinity hit.
+    pub fn take(&mut self, affinity_key: Option<&str>) -> Option<(VmInstance, bool)> {
+        let slots = self.slots;
+        let affinity = affinity_key.and_then(|key| {
+            self.vms
+                .iter()
+                .rposition(|pooled| pooled.has_free_slot(slots) && pooled.vm.loaded_function.as_deref() == Some(key))
+        });
+        let (i, hit) = match affinity {
+            Some(i) => (i, true),
+            None => (self.vms.iter().position(|pooled| pooled.has_free_slot(slots))?, false),
+        };
+        Some((Self::claim(&mut self.vms[i], slots), hit))
+    }
+
+    fn claim(pooled: &mut PooledVm, slots: usize) -> VmInstance {
+        pooled.active += 1;
+        pooled.vm.state = if pooled.has_free_slot(slots) { VmState::Ready } else { VmState::Busy };
+        let mut vm = pooled.vm.clone();
+        vm.checkout_invocations = vm.invocation_count;
+        vm
+    }
+}
+
+// The VMs of one config generation. Hands them out to invocations, boots
+// more on demand up to pool.max_vms (counting VMs still booting), and
+// keeps pool.min_vms warm in the background. Every boot first waits out
+// the boot backoff and reports how it went, so a host that can't start
+// VMs isn't hammered with doomed boots, and takes a boot limiter permit
+// shared by every generation's pool. Each VM serves up to
+// pool.vm_concurrency invocations at once.
+pub struct VmPool {
+    manager: Arc<VmManager>,
+    backoff: Arc<BootBackoff>,
+    limiter: Arc<BootLimiter>,
+    state: Mutex<PoolState>,
+    changed: Notify, // a VM was booted, handed back or torn down
+    closed: CancellationToken,
+}
+
+struct PoolState {
+    vms: IdleVms,
+    booting: usize, // boots under way, counted toward pool.max_vms
+    limits: PoolConfig,
+}
+
+impl PoolState {
+    fn room(&self) -> usize {
+        self.limits.max_vms.saturating_sub(self.vms.total() + self.booting)
+    }
+}
+
+// One boot counted toward pool.max_vms until it's dropped, however the boot
+// ended (including the acquire being abandoned mid-boot)
+struct BootSlot<'a> {
+    pool: &'a VmPool,
+}
+
+impl<'a> BootSlot<'a> {
+    fn claim(pool: &'a VmPool, state: &mut PoolState) -> Self {
+        state.booting += 1;
+        Self { pool }
+    }
+}
+
+impl Drop for BootSlot<'_> {
+    fn drop(&mut self) {
+        self.pool.state.lock().booting -= 1;
+        // A waiter may now have room to boot
+        self.pool.changed.notify_waiters();
+    }
+}
+
+impl VmPool {
+    pub fn new(
+        manager: Arc<VmManager>,
+        limits: PoolConfig,
+        backoff: Arc<BootBackoff>,
+        limiter: Arc<BootLimiter>,
+    ) -> Arc<Self> {
+        let pool = Arc::new(Self {
+            manager,
+            backoff,
+            limiter,
+            state: Mutex::new(PoolState {
+                vms: IdleVms::with_slots(limits.vm_concurrency),
+                booting: 0,
+                limits,
+            }),
+            changed: Notify::new(),
+            closed: CancellationToken::new(),
+        });
+        tokio::spawn(maintain(Arc::downgrade(&pool)));
+        pool
+    }
+
+    pub async fn acquire(&self) -> Result<VmInstance> {
+        self.acquire_for(None).await
+    }
+
+    // Check out a VM, preferring one with `affinity_key` loaded. With none
+    // free, boots one while the pool is under pool.max_vms and otherwise
+    // waits for one to be handed back.
+    pub async fn acquire_for(&self, affinity_key: Option<&str>) -> Result<VmInstance> {
+        loop {
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let slot = {
+                let mut state = self.state.lock();
+                if self.closed.is_cancelled() {
+                    return Err(anyhow::anyhow!("VM pool is shut down"));
+                }
+                if let Some((vm, _)) = state.vms.take(affinity_key) {
+                    self.manager.update(&vm);
+                    return Ok(vm);
+                }
+                (state.room() > 0).then(|| BootSlot::claim(self, &mut state))
+            };
+
+            if let Some(_slot) = slot {
+                let vm = self.boot().await?;
+                let taken = self.state.lock().vms.add_taken(vm);
+                self.manager.update(&taken);
+                return Ok(taken);
+            }
+            tokio::select! {
+                _ = changed => {}
+                _ = self.closed.cancelled() => {}
+            }
+        }
+    }
+
+    // Hand a VM's slot back for reuse
+    pub async fn release(&self, vm: VmInstance) {
+        let retire = self.closed.is_cancelled();
+        self.give_back(vm, retire).await;
+    }
+
+    // Hand back a VM's slot and take the VM out of service. It's torn down
+    // once every other invocation on it has finished too.
+    pub async fn discard(&self, vm: VmInstance) {
+        self.give_back(vm, true).await;
+    }
+
+    async fn give_back(&self, vm: VmInstance, retire: bool) {
+        let id = vm.id;
+        let retired = {
+            let mut state = self.state.lock();
+            let retired = state.vms.give_back(vm, retire);
+            if let Some(vm) = state.vms.get(id) {
+                self.manager.update(vm);
+            }
+            retired
+        };
+        if let Some(vm) = retired {
+            self.manager.destroy(&vm).await;
+        }
+        self.changed.notify_waiters();
+    }
+
+    // Boot VMs until the pool holds `target` (within pool.max_vms), e.g. to
+    // warm it before admitting traffic. Returns how many VMs it then holds.
+    pub async fn fill(&self, target: usize) -> usize {
+        let slots: Vec<_> = {
+            let mut state = self.state.lock();
+            let missing = target.saturating_sub(state.vms.total() + state.booting).min(state.room());
+            (0..missing).map(|_| BootSlot::claim(self, &mut state)).collect()
+        };
+        let boots = slots.into_iter().map(|_slot| async move {
+            let vm = self.boot().await?;
+            self.add_idle(vm).await;
+            anyhow::Ok(())
+        });
+
+        for boot in future::join_all(boots).await {
+            if let Err(e) = boot {
+                warn!("Failed to boot VM for the warm pool: {:#}", e);
+            }
+        }
+        self.state.lock().vms.total()
+    }
+
+    async fn add_idle(&self, vm: VmInstance) {
+        if self.closed.is_cancelled() {
+            return self.manager.destroy(&vm).await;
+        }
+        self.manager.update(&vm);
+        self.state.lock().vms.push(vm);
+        self.changed.notify_waiters();
+    }
+
+    // Boot a VM once any backoff from earlier failures has passed
+    async fn boot(&self) -> Result<VmInstance> {
+        while let Some(wait) = self.backoff.cooldown(Instant::now()) {
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = self.closed.cancelled() => return Err(anyhow::anyhow!("VM pool is shut down")),
+            }
+        }
+
+        let _boot = self.limiter.start().await;
+        match self.manager.boot().await {
+            Ok(vm) => {
+                self.backoff.record_success();
+                Ok(vm)
+            }
+            Err(failure) => {
+                let limits = self.state.lock().limits.clone();
+                self.backoff.record_failure(
+                    &failure.vm_id.to_string(),
+                    &format!("{:#}", failure.error),
+                    Instant::now(),
+                    limits.boot_backoff_initial(),
+                    limits.boot_backoff_max(),
+                );
+                Err(failure.into())
+            }
+        }
+    }
+
+    // Swap in new limits; the pool boots or lets VMs go toward the new warm
+    // target in the background
+    pub async fn set_limits(&self, limits: PoolConfig) {
+        let mut state = self.state.lock();
+        state.vms.set_slots(limits.vm_concurrency);
+        state.limits = limits;
+        drop(state);
+        self.changed.notify_waiters();
+    }
+
+    // Take every VM out of service, e.g. to flush the pool. Idle VMs are
+    // torn down now and returned; VMs still checked out are torn down once
+    // their last slot is handed back. Unless shut down, the pool then boots
+    // back up to its warm target in the background.
+    pub async fn drain_idle(&self) -> Vec<VmInstance> {
+        let idle = self.state.lock().vms.drain_idle();
+        for vm in &idle {
+            self.manager.destroy(vm).await;
+        }
+        self.changed.notify_waiters();
+        idle
+    }
+
+    // Tear down the idle VMs and stop booting
+    pub async fn shutdown(&self) {
+        self.closed.cancel();
+        self.drain_idle().await;
+    }
+}
+
+// Keeps a pool at its warm target: boots VMs while it's short of
+// pool.min_vms and lets idle VMs above it go once they've sat unused for
+// SURPLUS_IDLE_GRACE. Stops once the pool is shut down or dropped.
+async fn maintain(pool: Weak<VmPool>) {
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + MAINTAIN_INTERVAL, MAINTAIN_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(pool) = pool.upgrade() else {
+            return;
+        };
+        if pool.closed.is_cancelled() {
+            return;
+        }
+
+        let (min_vms, before) = {
+            let state = pool.state.lock();
+            (state.limits.min_vms, state.vms.total())
+        };
+        let after = pool.fill(min_vms).await;
+        if after > before {
+            info!("Booted {} VMs to keep {} warm", after - before, min_vms);
+        }
+
+        let grace = chrono::Duration::from_std(SURPLUS_IDLE_GRACE).unwrap_or(chrono::Duration::MAX);
+        let surplus = pool.state.lock().vms.take_surplus(min_vms, chrono::Utc::now() - grace);
+        for vm in &surplus {
+            pool.manager.destroy(vm).await;
+        }
+    }
+}
+
+// Tracks callers waiting on the pool and how long recent acquires took
+pub struct AcquireTracker {
+    waiters: AtomicUsize,
+    recent: Mutex<VecDeque<Duration>>,
+}
+
+// Counts a caller as waiting until dropped
+pub struct WaitGuard<'a> {
+    waiters: &'a AtomicUsize,
+}
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        self.waiters.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl AcquireTracker {
+    pub fn new() -> Self {
+        Self {
+            waiters: AtomicUsize::new(0),
+            recent: Mutex::new(VecDeque::with_capacity(ACQUIRE_WINDOW)),
+        }
+    }
+
+    pub fn wait(&self) -> WaitGuard<'_> {
+        self.waiters.fetch_add(1, Ordering::Relaxed);
+        WaitGuard {
+            waiters: &self.waiters,
+        }
+    }
+
+    pub fn waiters(&self) -> usize {
+        self.waiters.load(Ordering::Relaxed)
+    }
+
+    pub fn record(&self, elapsed: Duration) {
+        let mut recent = self.recent.lock();
+        if recent.len() == ACQUIRE_WINDOW {
+            recent.pop_front();
+        }
+        recent.push_back(elapsed);
+    }
+
+    // Nearest-rank percentiles over the recent window
+    pub fn percentiles(&self) -> AcquireWaitPercentiles {
+        let mut waits: Vec<Duration> = self.recent.lock().iter().copied().collect();
+        waits.sort_unstable();
+
+        let at = |p: f64| -> f64 {
+            if waits.is_empty() {
+                return 0.0;
+            }
+            let rank = ((p / 100.0) * waits.len() as f64).ceil() as usize;
+            waits[rank.clamp(1, waits.len()) - 1].as_secs_f64() * 1000.0
+        };
+
+        AcquireWaitPercentiles {
+            samples: waits.len(),
+            p50_ms: at(50.0),
+            p90_ms: at(90.0),
+            p99_ms: at(99.0),
+        }
+    }
+}
+
+// Holds acquires back until the pool's first VMs have booted, so a burst of
+// traffic at startup queues behind warm-up instead of every call racing to
+// boot its own VM
+pub struct WarmupGate {
+    ready: watch::Sender<bool>,
+}
+
+impl WarmupGate {
+    pub fn new() -> Self {
+        Self {
+            ready: watch::Sender::new(false),
+        }
+    }
+
+    pub fn open(&self) {
+        self.ready.send_replace(true);
+    }
+
+    pub fn is_open(&self) -> bool {
+        *self.ready.borrow()
+    }
+
+    pub async fn wait(&self) {
+        let mut ready = self.ready.subscribe();
+        // The sender lives as long as self, so this can't fail
+        let _ = ready.wait_for(|ready| *ready).await;
+    }
+}
+
+// Change in sustained saturation worth telling operators about
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SaturationAlert {
+    Raised { average: f64 },
+    Cleared { average: f64 },
+}
+
+// Busy VMs as a fraction of pool capacity, sampled periodically and averaged
+// over a sliding window. Saturation only counts as sustained once the
+// window has filled, so a burst right after startup doesn't alert.
+pub struct SaturationTracker {
+    state: Mutex<SaturationState>,
+}
+
+struct SaturationState {
+    samples: VecDeque<(Instant, f64)>,
+    first_sample: Option<Instant>,
+    busy_vms: usize,
+    alerting: bool,
+}
+
+impl SaturationTracker {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(SaturationState {
+                samples: VecDeque::new(),
+                first_sample: None,
+                busy_vms: 0,
+                alerting: false,
+            }),
+        }
+    }
+
+    pub fn record(
+        &self,
+        busy_vms: usize,
+        capacity: usize,
+        now: Instant,
+        window: Duration,
+        threshold: f64,
+    ) -> Option<SaturationAlert> {
+        let ratio = if capacity == 0 { 0.0 } else { busy_vms as f64 / capacity as f64 };
+
+        let mut state = self.state.lock();
+        state.busy_vms = busy_vms;
+        state.samples.push_back((now, ratio));
+        while state.samples.front().is_some_and(|(at, _)| now.duration_since(*at) > window) {
+            state.samples.pop_front();
+        }
+        let first_sample = *state.first_sample.get_or_insert(now);
+
+        let average = average(&state.samples);
+        let sustained = now.duration_since(first_sample) >= window && average >= threshold;
+        match (sustained, state.alerting) {
+            (true, false) => {
+                state.alerting = true;
+                Some(SaturationAlert::Raised { average })
+            }
+            (false, true) if average < threshold => {
+                state.alerting = false;
+                Some(SaturationAlert::Cleared { average })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn stats(&self, window: Duration) -> SaturationStats {
+        let state = self.state.lock();
+        SaturationStats {
+            busy_vms: state.busy_vms,
+            current: state.samples.back().map_or(0.0, |(_, ratio)| *ratio),
+            window_average: average(&state.samples),
+            window_secs: window.as_secs(),
+            sustained: state.alerting,
+        }
+    }
+}
+
+// Recent demand on the pool, for sizing the warm pool ahead of traffic.
+// Finished invocations are tallied, and each tick folds the tally into
+// exponentially weighted averages of the invocation rate and of busy VMs
+// (VM-seconds used per second). A spike raises the forecast within a tick
+// or two; once traffic drops it decays by half every half-life.
+pub struct DemandForecast {
+    state: Mutex<ForecastState>,
+}
+
+#[derive(Default)]
+struct ForecastState {
+    invocations: u64, // since the last tick
+    busy: Duration,
+    last_tick: Option<Instant>,
+    rate: f64,
+    busy_vms: f64,
+    forecast: Option<usize>, // VMs called for, before min_vms and max_vms
+}
+
+impl DemandForecast {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(ForecastState::default()),
+        }
+    }
+
+    pub fn record(&self, busy: Duration) {
+        let mut state = self.state.lock();
+        state.invocations += 1;
+        state.busy += busy;
+    }
+
+    // Tracks a VM checkout; its hold time is recorded when the hold drops
+    pub fn hold(self: &Arc<Self>) -> DemandHold {
+        DemandHold {
+            forecast: self.clone(),
+            since: Instant::now(),
+        }
+    }
+
+    pub fn tick(&self, now: Instant, half_life: Duration, margin: f64, vm_concurrency: usize) {
+        let mut state = self.state.lock();
+        let Some(last_tick) = state.last_tick.replace(now) else {
+            return;
+        };
+        let elapsed = now.duration_since(last_tick).as_secs_f64();
+        if elapsed <= 0.0 {
+            return;
+        }
+
+        let weight = 1.0 - 0.5f64.powf(elapsed / half_life.as_secs_f64());
+        let rate = state.invocations as f64 / elapsed;
+        let busy_vms = state.busy.as_secs_f64() / elapsed;
+        state.rate += weight * (rate - state.rate);
+        state.busy_vms += weight * (busy_vms - state.busy_vms);
+        state.invocations = 0;
+        state.busy = Duration::ZERO;
+        state.forecast = Some((state.busy_vms * margin / vm_concurrency.max(1) as f64).ceil() as usize);
+    }
+
+    // The warm pool size the forecast calls for; min_vms until it has one
+    pub fn target(&self, min_vms: usize, max_vms: usize) -> usize {
+        let forecast = self.state.lock().forecast.unwrap_or(0);
+        forecast.clamp(min_vms, max_vms.max(min_vms))
+    }
+
+    pub fn stats(&self, min_vms: usize, max_vms: usize) -> DemandForecastStats {
+        let state = self.state.lock();
+        DemandForecastStats {
+            invocation_rate: state.rate,
+            busy_vms: state.busy_vms,
+            predicted_target: state.forecast.unwrap_or(0).clamp(min_vms, max_vms.max(min_vms)),
+        }
+    }
+}
+
+// A VM held for one invocation, from acquire until it's handed back
+pub struct DemandHold {
+    forecast: Arc<DemandForecast>,
+    since: Instant,
+}
+
+impl Drop for DemandHold {
+    fn drop(&mut self) {
+        self.forecast.record(self.since.elapsed());
+    }
+}
+
+// Spaces out VM boots after failures so a host under resource pressure
+// isn't hammered with doomed boots. The wait doubles with each consecutive
+// failure up to the cap and resets on the first successful boot. Each
+// failed boot is kept in `failed` so it can be listed.
+pub struct BootBackoff {
+    state: Mutex<BootBackoffState>,
+    failed: Arc<FailedVms>,
+}
+
+#[derive(Default)]
+struct BootBackoffState {
+    consecutive_failures: u32,
+    retry_at: Option<Instant>,
+    last_reason: Option<String>,
+}
+
+impl BootBackoff {
+    pub fn new(failed: Arc<FailedVms>) -> Self {
+        Self {
+            state: Mutex::new(BootBackoffState::default()),
+            failed,
+        }
+    }
+
+    // Time left before the next boot may be attempted
+    pub fn cooldown(&self, now: Instant) -> Option<Duration> {
+        let state = self.state.lock();
+        state
+            .retry_at
+            .map(|at| at.saturating_duration_since(now))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    // Returns how long to wait before trying again
+    pub fn record_failure(
+        &self,
+        vm_id: &str,
+        reason: &str,
+        now: Instant,
+        initial: Duration,
+        max: Duration,
+    ) -> Duration {
+        let mut state = self.state.lock();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        let exponent = (state.consecutive_failures - 1).min(31);
+        let delay = initial.saturating_mul(1 << exponent).min(max);
+
+        state.retry_at = Some(now + delay);
+        state.last_reason = Some(reason.to_string());
+        let failed_at = chrono::Utc::now().to_rfc3339();
+        let vm = VmInfo {
+            id: vm_id.to_string(),
+            state: VmState::Failed,
+            ip_address: None,
+            port: None,
+            invocation_count: 0,
+            created_at: failed_at.clone(),
+            last_used: failed_at,
+            failure_reason: None,
+            generation: None,
+        };
+        let reason_with_count = format!("{} ({} consecutive failures)", reason, state.consecutive_failures);
+        self.failed.record(vm, &reason_with_count, now);
+        warn!(
+            "VM {} failed to boot ({} consecutive failures), retrying in {:?}: {}",
+            vm_id, state.consecutive_failures, delay, reason
+        );
+        delay
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock() = BootBackoffState::default();
+    }
+
+    // The latest failure reason once at least `threshold` boots in a row
+    // have failed
+    pub fn outage(&self, threshold: u32) -> Option<String> {
+        let state = self.state.lock();
+        (state.consecutive_failures >= threshold)
+            .then(|| state.last_reason.clone())
+            .flatten()
+    }
+}
+
+// Caps VMs booting at once, so a warm-pool fill or a scaling burst boots in
+// batches instead of thrashing the host with Firecracker processes
+pub struct BootLimiter {
+    permits: Arc<Semaphore>,
+    limit: Mutex<usize>,
+    booting: AtomicUsize,
+}
+
+// A boot in progress; the next one may start once it's dropped
+pub struct BootGuard<'a> {
+    _permit: SemaphorePermit<'a>,
+    booting: &'a AtomicUsize,
+}
+
+impl Drop for BootGuard<'_> {
+    fn drop(&mut self) {
+        self.booting.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl BootLimiter {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(limit)),
+            limit: Mutex::new(limit),
+            booting: AtomicUsize::new(0),
+        }
+    }
+
+    // Wait for a boot slot; hold the guard for the whole boot
+    pub async fn start(&self) -> BootGuard<'_> {
+        let permit = self.permits.acquire().await.expect("boot semaphore is never closed");
+        self.booting.fetch_add(1, Ordering::Relaxed);
+        BootGuard {
+            _permit: permit,
+            booting: &self.booting,
+        }
+    }
+
+    // Resize on config reload. Shrinking takes effect as running boots
+    // finish and their slots are retired.
+    pub fn set_limit(&self, limit: usize) {
+        let mut current = self.limit.lock();
+        if limit > *current {
+            self.permits.add_permits(limit - *current);
+        } else if limit < *current {
+            let permits = self.permits.clone();
+            let excess = (*current - limit) as u32;
+            tokio::spawn(async move {
+                if let Ok(retired) = permits.acquire_many_owned(excess).await {
+                    retired.forget();
+                }
+            });
+        }
+        *current = limit;
+    }
+
+    pub fn booting(&self) -> usize {
+        self.booting.load(Ordering::Relaxed)
+    }
+}
+
+// Most failed VMs kept, so a crash loop can't grow the list without bound
+const MAX_FAILED_VMS: usize = 100;
+
+// VMs that failed to boot, failed a health check or were killed, listed as
+// `Failed` for a retention window after the pool has let go of them so
+// operators can see what went wrong. Expired entries are dropped as the
+// list is read or added to.
+pub struct FailedVms {
+    entries: Mutex<VecDeque<(Instant, VmInfo)>>, // oldest first
+}
+
+impl FailedVms {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self, mut vm: VmInfo, reason: &str, now: Instant) {
+        vm.state = VmState::Failed;
+        vm.failure_reason = Some(reason.to_string());
+        let mut entries = self.entries.lock();
+        entries.push_back((now, vm));
+        while entries.len() > MAX_FAILED_VMS {
+            entries.pop_front();
+        }
+    }
+
+    // Failures within the retention window, oldest first
+    pub fn list(&self, now: Instant, retention: Duration) -> Vec<VmInfo> {
+        let mut entries = self.entries.lock();
+        while entries
+            .front()
+            .is_some_and(|(failed_at, _)| now.saturating_duration_since(*failed_at) >= retention)
+        {
+            entries.pop_front();
+        }
+        entries.iter().map(|(_, vm)| vm.clone()).collect()
+    }
+}
+
+// Idle VMs that failed a background health ping. The pool hands out idle
+// VMs itself, so they're reaped when next acquired rather than pulled out
+// from under it.
+pub struct UnhealthyVms {
+    ids: Mutex<HashSet<Uuid>>,
+}
+
+impl UnhealthyVms {
+    pub fn new() -> Self {
+        Self {
+            ids: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn mark(&self, id: Uuid) {
+        self.ids.lock().insert(id);
+    }
+
+    // Whether the VM was marked, clearing the mark
+    pub fn take(&self, id: Uuid) -> bool {
+        self.ids.lock().remove(&id)
+    }
+
+    // Forget VMs that no longer exist
+    pub fn retain(&self, live: &HashSet<Uuid>) {
+        self.ids.lock().retain(|id| live.contains(id));
+    }
+}
+
+// VMs retired by a pool flush. The flush tears down the idle ones itself;
+// busy ones are discarded when they come back, or sooner if the flush was
+// forced, in which case the invocations holding them are cancelled.
+pub struct FlushedVms {
+    ids: Mutex<HashSet<Uuid>>,
+    forced: Mutex<CancellationToken>, // cancelled by the next forced flush
+}
+
+impl FlushedVms {
+    pub fn new() -> Self {
+        Self {
+            ids: Mutex::new(HashSet::new()),
+            forced: Mutex::new(CancellationToken::new()),
+        }
+    }
+
+    pub fn flush(&self, ids: impl IntoIterator<Item = Uuid>, force: bool) {
+        self.ids.lock().extend(ids);
+        if force {
+            std::mem::replace(&mut *self.forced.lock(), CancellationToken::new()).cancel();
+        }
+    }
+
+    // Whether the VM was flushed, clearing the mark
+    pub fn take(&self, id: Uuid) -> bool {
+        self.ids.lock().remove(&id)
+    }
+
+    // Taken by an invocation before it runs; fires if a forced flush
+    // happens while it's running
+    pub fn forced(&self) -> CancellationToken {
+        self.forced.lock().clone()
+    }
+
+    // Forget VMs that no longer exist
+    pub fn retain(&self, live: &HashSet<Uuid>) {
+        self.ids.lock().retain(|id| live.contains(id));
+    }
+}
+
+// Warm VMs held back from the shared pool for premium-tier functions, so
+// they never wait on a boot however busy standard traffic keeps the pool.
+// Checked out of the pool while reserved; the refill task keeps the
+// reserve at pool.premium_reserved_vms and wakes whenever one is taken.
+pub struct ReservedVms {
+    vms: Mutex<VecDeque<VmInstance>>,
+    taken: Notify,
+}
+
+impl ReservedVms {
+    pub fn new() -> Self {
+        Self {
+            vms: Mutex::new(VecDeque::new()),
+            taken: Notify::new(),
+        }
+    }
+
+    pub fn take(&self) -> Option<VmInstance> {
+        let vm = self.vms.lock().pop_front();
+        if vm.is_some() {
+            self.taken.notify_one();
+        }
+        vm
+    }
+
+    // Keep the VM if the reserve is below `target`; otherwise it's handed
+    // back for the shared pool
+    pub fn offer(&self, vm: VmInstance, target: usize) -> Option<VmInstance> {
+        let mut vms = self.vms.lock();
+        if vms.len() >= target {
+            return Some(vm);
+        }
+        vms.push_back(vm);
+        None
+    }
+
+    // VMs past `target`, e.g. after a reload lowered it
+    pub fn excess(&self, target: usize) -> Vec<VmInstance> {
+        let mut vms = self.vms.lock();
+        let keep = vms.len().min(target);
+        vms.drain(keep..).collect()
+    }
+
+    pub fn count(&self) -> usize {
+        self.vms.lock().len()
+    }
+
+    // Resolves once a VM has been taken since the last call
+    pub async fn wait_taken(&self) {
+        self.taken.notified().await
+    }
+}
+
+// The VM configurations the pool has been booted from, so an image upgrade
+// (new kernel or rootfs) rolls through without downtime. VMs are checked
+// out from the newest generation only; older ones drain, their VMs thrown
+// away as they come back instead of being reused, and are retired once
+// none are still checked out. `T` is whatever boots a generation's VMs.
+pub struct Generations<T> {
+    state: Mutex<GenerationState<T>>,
+}
+
+struct GenerationState<T> {
+    current: Arc<Generation<T>>,
+    draining: Vec<Arc<Generation<T>>>, // oldest first
+}
+
+pub struct Generation<T> {
+    pub number: u64,
+    pub backend: T,
+    checked_out: AtomicUsize,
+}
+
+impl<T> Generation<T> {
+    fn new(number: u64, backend: T) -> Self {
+        Self {
+            number,
+            backend,
+            checked_out: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn check_out(&self) {
+        self.checked_out.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn check_in(&self) {
+        self.checked_out.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub fn checked_out(&self) -> usize {
+        self.checked_out.load(Ordering::SeqCst)
+    }
+}
+
+impl<T> Generations<T> {
+    pub fn new(backend: T) -> Self {
+        Self {
+            state: Mutex::new(GenerationState {
+                current: Arc::new(Generation::new(1, backend)),
+                draining: Vec::new(),
+            }),
+        }
+    }
+
+    // The generation new VMs come from
+    pub fn current(&self) -> Arc<Generation<T>> {
+        self.state.lock().current.clone()
+    }
+
+    pub fn get(&self, number: u64) -> Option<Arc<Generation<T>>> {
+        let state = self.state.lock();
+        std::iter::once(&state.current)
+            .chain(&state.draining)
+            .find(|generation| generation.number == number)
+            .cloned()
+    }
+
+    // Every generation still running VMs, newest first
+    pub fn all(&self) -> Vec<Arc<Generation<T>>> {
+        let state = self.state.lock();
+        std::iter::once(&state.current).chain(state.draining.iter().rev()).cloned().collect()
+    }
+
+    // Make `backend` the current generation, returning the one it replaced,
+    // which starts draining
+    pub fn roll(&self, backend: T) -> Arc<Generation<T>> {
+        let mut state = self.state.lock();
+        let next = Arc::new(Generation::new(state.current.number + 1, backend));
+        let previous = std::mem::replace(&mut state.current, next);
+        state.draining.push(previous.clone());
+        previous
+    }
+
+    // Draining generations with every VM back, removed so they can be shut
+    // down. Each is returned once.
+    pub fn take_drained(&self) -> Vec<Arc<Generation<T>>> {
+        let mut state = self.state.lock();
+        let (drained, draining) = state.draining.drain(..).partition(|generation| generation.checked_out() == 0);
+        state.draining = draining;
+        drained
+    }
+
+    pub fn status(&self) -> GenerationStatus {
+        let state = self.state.lock();
+        GenerationStatus {
+            current: state.draining.first().unwrap_or(&state.current).number,
+            target: state.current.number,
+        }
+    }
+}
+
+// Admits invocations to the pool one function at a time. Each function
+// waits in its own FIFO queue and free slots go round-robin across the
+// functions with callers waiting, so one function's burst can't starve the
+// rest behind it in the pool's own queue. Higher priority callers are
+// served first, round-robin among themselves; lower ones wait until no one
+// above them is queued. Capacity is the hard ceiling on invocations holding
+// a VM slot at once (pool.max_vms × pool.vm_concurrency; the pool itself
+// caps VMs at pool.max_vms); past `max_waiting` queued callers, new ones
+// are turned away instead of piling up.
+pub struct FairQueue {
+    state: Arc<Mutex<FairState>>,
+}
+
+struct FairState {
+    capacity: usize,
+    max_waiting: usize,
+    in_use: usize,
+    levels: [FairLevel; 3], // indexed by priority, lowest first
+}
+
+// Callers waiting at one priority
+#[derive(Default)]
+struct FairLevel {
+    waiting: HashMap<String, VecDeque<oneshot::Sender<FairTurn>>>,
+    rotation: VecDeque<String>, // functions with waiters, next to be served first
+}
+
+// A slot in the pool, handed to the next waiting function when dropped
+pub struct FairTurn {
+    state: Arc<Mutex<FairState>>,
+    held: bool, // false for a turn that never reached its waiter
+}
+
+impl FairQueue {
+    pub fn new(capacity: usize, max_waiting: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FairState {
+                capacity,
+                max_waiting,
+                in_use: 0,
+                levels: Default::default(),
+            })),
+        }
+    }
+
+    // Wait for this function's turn. Cancelling the wait gives up the place
+    // in line; a turn granted as the wait was cancelled is passed on.
+    pub async fn turn(&self, key: &str, priority: Priority) -> Result<FairTurn, HyperdriveError> {
+        let granted = {
+            let mut state = self.state.lock();
+            let queued = state.levels.iter().any(|level| !level.rotation.is_empty());
+            if !queued && state.in_use < state.capacity {
+                state.in_use += 1;
+                return Ok(FairTurn {
+                    state: self.state.clone(),
+                    held: true,
+                });
+            }
+            if state.waiters() >= state.max_waiting {
+                return Err(HyperdriveError::PoolExhausted);
+            }
+            let (tx, rx) = oneshot::channel();
+            let level = &mut state.levels[priority as usize];
+            let queue = level.waiting.entry(key.to_string()).or_default();
+            let first = queue.is_empty();
+            queue.push_back(tx);
+            if first {
+                level.rotation.push_back(key.to_string());
+            }
+            rx
+        };
+        Ok(granted.await.expect("fair queue outlives its waiters"))
+    }
+
+    // Resize on config reload. Shrinking takes effect as turns finish.
+    pub fn set_limits(&self, capacity: usize, max_waiting: usize) {
+        let mut state = self.state.lock();
+        state.capacity = capacity;
+        state.max_waiting = max_waiting;
+        grant(&mut state, &self.state);
+    }
+
+    pub fn in_use(&self) -> usize {
+        self.state.lock().in_use
+    }
+
+    // Callers waiting per function at any priority, leaving out ones that
+    // gave up
+    pub fn depths(&self) -> HashMap<String, usize> {
+        let state = self.state.lock();
+        let mut depths = HashMap::new();
+        for (key, queue) in state.levels.iter().flat_map(|level| &level.waiting) {
+            let depth = queue.iter().filter(|tx| !tx.is_closed()).count();
+            if depth > 0 {
+                *depths.entry(key.clone()).or_default() += depth;
+            }
+        }
+        depths
+    }
+}
+
+impl FairState {
+    // Callers still waiting, leaving out ones that gave up
+    fn waiters(&self) -> usize {
+        self.levels
+            .iter()
+            .flat_map(|level| level.waiting.values().flatten())
+            .filter(|tx| !tx.is_closed())
+            .count()
+    }
+}
+
+impl Drop for FairTurn {
+    fn drop(&mut self) {
+        if !self.held {
+            return;
+        }
+        let mut state = self.state.lock();
+        state.in_use -= 1;
+        grant(&mut state, &self.state);
+    }
+}
+
+// Hand free slots to waiters, highest priority first, taking one from each
+// function in turn
+fn grant(state: &mut FairState, shared: &Arc<Mutex<FairState>>) {
+    while state.in_use < state.capacity {
+        let Some(level) = state.levels.iter_mut().rev().find(|level| !level.rotation.is_empty()) else {
+            break;
+        };
+        let key = level.rotation.pop_front().expect("level has waiters");
+        let Some(queue) = level.waiting.get_mut(&key) else {
+            continue;
+        };
+        let next = queue.pop_front();
+        if queue.is_empty() {
+            level.waiting.remove(&key);
+        } else {
+            level.rotation.push_back(key);
+        }
+        let Some(tx) = next else {
+            continue;
+        };
+
+        state.in_use += 1;
+        let turn = FairTurn {
+            state: shared.clone(),
+            held: true,
+        };
+        if let Err(mut turn) = tx.send(turn) {
+            // The waiter gave up. Dropping the turn normally would re-lock
+            // the state, so its slot is given back here instead.
+            turn.held = false;
+            state.in_use -= 1;
+        }
+    }
+}
+
+fn average(samples: &VecDeque<(Instant, f64)>) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    samples.iter().map(|(_, ratio)| ratio).sum::<f64>() / samples.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A pool whose VMs can never boot, as on a host missing its kernel image
+    async fn failing_pool(
+        base: &std::path::Path,
+        limits: PoolConfig,
+        failed: Arc<FailedVms>,
+    ) -> (Arc<VmPool>, Arc<BootBackoff>) {
+        let manager = VmManager::new(crate::types::VmConfig {
+            kernel_path: base.join("missing-vmlinux").to_string_lossy().into_owned(),
+            work_dir_base: base.to_string_lossy().into_owned(),
+            port_range_start: 9200,
+            port_range_end: 9210,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let backoff = Arc::new(BootBackoff::new(failed));
+        let limiter = Arc::new(BootLimiter::new(limits.max_concurrent_boots));
+        (VmPool::new(Arc::new(manager), limits, backoff.clone(), limiter), backoff)
+    }
+
+    fn vm_with(loaded: Option<&str>) -> VmInstance {
+        let mut vm = VmInstance::new("/tmp".to_string());
+        vm.loaded_function = loaded.map(|s| s.to_string());
+        vm
+    }
+
+    #[test]
+    fn test_affinity_preferred() {
+        let mut idle = IdleVms::with_slots(1);
+        idle.push(vm_with(Some("a@1")));
+        idle.push(vm_with(Some("b@1")));
+        idle.push(vm_with(None));
+
+        let (vm, hit) = idle.take(Some("b@1")).unwrap();
+        assert!(hit);
+        assert_eq!(vm.loaded_function.as_deref(), Some("b@1"));
+
+        // No affinitized VM left: fall back to the oldest idle one
+        let (vm, hit) = idle.take(Some("b@1")).unwrap();
+        assert!(!hit);
+        assert_eq!(vm.loaded_function.as_deref(), Some("a@1"));
+
+        let (_, hit) = idle.take(None).unwrap();
+        assert!(!hit);
+        assert!(idle.take(Some("a@1")).is_none());
+    }
+
+    #[test]
+    fn test_concurrent_slots() {
+        let mut pool = IdleVms::with_slots(2);
+        pool.push(vm_with(Some("a@1")));
+
+        let (first, hit) = pool.take(Some("a@1")).unwrap();
+        assert!(hit);
+        assert_eq!(pool.len(), 1);
+
+        // The same VM serves a second invocation, then it's full
+        let (mut second, _) = pool.take(Some("a@1")).unwrap();
+        assert_eq!(second.id, first.id);
+        assert_eq!(second.state, VmState::Busy);
+        assert!(pool.is_empty());
+        assert!(pool.take(None).is_none());
+
+        second.invocation_count += 1;
+        assert!(pool.give_back(second, false).is_none());
+        let (mut third, _) = pool.take(None).unwrap();
+        assert_eq!(third.id, first.id);
+        assert_eq!(third.invocation_count, 1);
+
+        // Retiring the VM doesn't pull it from under its other slots; it's
+        // due for teardown once the last one is back, with every slot's
+        // invocations counted, and never comes back into service
+        assert!(pool.retire(first.id).is_none());
+        assert!(pool.take(None).is_none());
+        assert_eq!(pool.total(), 1);
+        let mut first = first;
+        first.invocation_count += 1;
+        assert!(pool.give_back(first, false).is_none());
+        assert!(pool.take(None).is_none());
+        third.invocation_count += 1;
+        let retired = pool.give_back(third.clone(), false).unwrap();
+        assert_eq!(retired.invocation_count, 3);
+        assert_eq!(pool.total(), 0);
+
+        // A slot of a VM that's gone doesn't bring it back
+        assert!(pool.give_back(third, false).is_none());
+        assert_eq!(pool.total(), 0);
+    }
+
+    #[test]
+    fn test_acquire_tracker() {
+        let tracker = AcquireTracker::new();
+        assert_eq!(tracker.percentiles().samples, 0);
+
+        for ms in 1..=100 {
+            tracker.record(Duration::from_millis(ms));
+        }
+        let percentiles = tracker.percentiles();
+        assert_eq!(percentiles.samples, 100);
+        assert_eq!(percentiles.p50_ms, 50.0);
+        assert_eq!(percentiles.p90_ms, 90.0);
+        assert_eq!(percentiles.p99_ms, 99.0);
+
+        {
+            let _first = tracker.wait();
+            let _second = tracker.wait();
+            assert_eq!(tracker.waiters(), 2);
+        }
+        assert_eq!(tracker.waiters(), 0);
+    }
+
+    #[test]
+    fn test_demand_forecast() {
+        let forecast = DemandForecast::new();
+        let half_life = Duration::from_secs(10);
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        // Nothing to go on until a full tick has passed
+        forecast.tick(at(0), half_life, 1.25, 1);
+        assert_eq!(forecast.target(2, 10), 2);
+
+        // 20 invocations of 2s over 10s keep 4 VMs busy; one half-life in,
+        // the average is halfway there
+        for _ in 0..20 {
+            forecast.record(Duration::from_secs(2));
+        }
+        forecast.tick(at(10), half_life, 1.25, 1);
+        let stats = forecast.stats(0, 10);
+        assert_eq!((stats.invocation_rate, stats.busy_vms), (1.0, 2.0));
+        assert_eq!(stats.predicted_target, 3);
+
+        // Sustained load converges on it, within max_vms
+        for secs in (20..=100).step_by(10) {
+            for _ in 0..20 {
+                forecast.record(Duration::from_secs(2));
+            }
+            forecast.tick(at(secs), half_life, 1.25, 1);
+        }
+        assert_eq!(forecast.target(0, 10), 5);
+        assert_eq!(forecast.target(0, 4), 4);
+        assert_eq!(forecast.stats(0, 10).predicted_target, 5);
+
+        // Then decays once traffic stops, back down to min_vms
+        for secs in (110..=300).step_by(10) {
+            forecast.tick(at(secs), half_life, 1.25, 1);
+        }
+        assert_eq!(forecast.target(2, 10), 2);
+    }
+
+    #[test]
+    fn test_demand_hold_records_on_drop() {
+        let forecast = Arc::new(DemandForecast::new());
+        let start = Instant::now();
+        forecast.tick(start, Duration::from_secs(10), 1.0, 1);
+
+        let hold = forecast.hold();
+        std::thread::sleep(Duration::from_millis(20));
+        forecast.tick(start + Duration::from_secs(1), Duration::from_secs(10), 1.0, 1);
+        assert_eq!(forecast.stats(0, 10).invocation_rate, 0.0);
+
+        drop(hold);
+        forecast.tick(start + Duration::from_secs(2), Duration::from_secs(10), 1.0, 1);
+        let stats = forecast.stats(0, 10);
+        assert!(stats.invocation_rate > 0.0);
+        assert!(stats.busy_vms > 0.0);
+    }
+
+    #[test]
+    fn test_saturation_alerts_when_sustained() {
+        let tracker = SaturationTracker::new();
+        let window = Duration::from_secs(30);
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        // Fully busy, but the window hasn't filled yet
+        for secs in 0..30 {
+            assert_eq!(tracker.record(10, 10, at(secs), window, 0.9), None);
+        }
+        assert_eq!(
+            tracker.record(10, 10, at(30), window, 0.9),
+            Some(SaturationAlert::Raised { average: 1.0 })
+        );
+        assert_eq!(tracker.record(10, 10, at(31), window, 0.9), None);
+        assert!(tracker.stats(window).sustained);
+
+        // Idle samples pull the average back under the threshold
+        let mut cleared = None;
+        for secs in 32..62 {
+            if let Some(alert) = tracker.record(2, 10, at(secs), window, 0.9) {
+                cleared = Some(alert);
+                break;
+            }
+        }
+        assert!(matches!(cleared, Some(SaturationAlert::Cleared { .. })));
+
+        let stats = tracker.stats(window);
+        assert_eq!(stats.busy_vms, 2);
+        assert_eq!(stats.current, 0.2);
+        assert!(!stats.sustained);
+    }
+
+    #[test]
+    fn test_unhealthy_vms() {
+        let unhealthy = UnhealthyVms::new();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        unhealthy.mark(a);
+        unhealthy.mark(b);
+
+        unhealthy.retain(&HashSet::from([a]));
+        assert!(!unhealthy.take(b));
+        assert!(unhealthy.take(a));
+        assert!(!unhealthy.take(a));
+    }
+
+    #[test]
+    fn test_flushed_vms() {
+        let flushed = FlushedVms::new();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let running = flushed.forced();
+        flushed.flush([a], false);
+        assert!(!running.is_cancelled());
+        assert!(flushed.take(a));
+        assert!(!flushed.take(a));
+
+        // Only invocations already running are cancelled by a forced flush
+        flushed.flush([b], true);
+        assert!(running.is_cancelled());
+        assert!(!flushed.forced().is_cancelled());
+        assert!(flushed.take(b));
+    }
+
+    #[test]
+    fn test_reserved_vms() {
+        let reserved = ReservedVms::new();
+        let vm = || VmInstance::new("/tmp".to_string());
+
+        assert!(reserved.offer(vm(), 2).is_none());
+        assert!(reserved.offer(vm(), 2).is_none());
+        assert!(reserved.offer(vm(), 2).is_some());
+        assert_eq!(reserved.count(), 2);
+
+        assert_eq!(reserved.excess(1).len(), 1);
+        assert!(reserved.take().is_some());
+        assert!(reserved.take().is_none());
+        assert_eq!(reserved.count(), 0);
+    }
+
+    #[test]
+    fn test_generations() {
+        let generations = Generations::new("v1");
+        let first = generations.current();
+        first.check_out();
+        assert_eq!(generations.status(), GenerationStatus { current: 1, target: 1 });
+
+        // The old generation drains until its checked-out VM comes back
+        assert_eq!(generations.roll("v2").number, 1);
+        assert_eq!(generations.current().backend, "v2");
+        assert_eq!(generations.status(), GenerationStatus { current: 1, target: 2 });
+        let numbers = |generations: &Generations<&str>| {
+            generations.all().iter().map(|generation| generation.number).collect::<Vec<_>>()
+        };
+        assert_eq!(numbers(&generations), [2, 1]);
+        assert!(generations.take_drained().is_empty());
+
+        generations.get(1).unwrap().check_in();
+        assert_eq!(generations.take_drained().len(), 1);
+        assert!(generations.get(1).is_none());
+        assert_eq!(generations.status(), GenerationStatus { current: 2, target: 2 });
+        assert_eq!(numbers(&generations), [2]);
+    }
+
+    #[test]
+    fn test_boot_backoff() {
+        let failed_vms = Arc::new(FailedVms::new());
+        let backoff = BootBackoff::new(failed_vms.clone());
+        let start = Instant::now();
+        let (initial, max) = (Duration::from_millis(500), Duration::from_secs(3));
+        let retention = Duration::from_secs(60);
+        assert_eq!(backoff.cooldown(start), None);
+        assert!(failed_vms.list(start, retention).is_empty());
+        assert_eq!(backoff.outage(1), None);
+
+        let delays: Vec<Duration> = (0..5)
+            .map(|_| backoff.record_failure("vm-1", "out of memory", start, initial, max))
+            .collect();
+        assert_eq!(
+            delays,
+            [500, 1000, 2000, 3000, 3000].map(Duration::from_millis).to_vec()
+        );
+        assert_eq!(backoff.cooldown(start), Some(max));
+        assert_eq!(backoff.cooldown(start + max), None);
+        assert_eq!(backoff.outage(5).as_deref(), Some("out of memory"));
+        assert_eq!(backoff.outage(6), None);
+
+        // Each failed boot is listed rather than silently dropped
+        let failed = failed_vms.list(start, retention);
+        assert_eq!(failed.len(), 5);
+        assert_eq!(failed[4].state, VmState::Failed);
+        assert_eq!(failed[4].id, "vm-1");
+        assert!(failed[4].failure_reason.as_deref().unwrap().contains("out of memory"));
+        assert!(failed[4].failure_reason.as_deref().unwrap().contains("5 consecutive failures"));
+
+        // A successful boot resets the backoff, but failures stay listed
+        // until they age out
+        backoff.record_success();
+        assert_eq!(backoff.outage(1), None);
+        assert_eq!(failed_vms.list(start, retention).len(), 5);
+        assert!(failed_vms.list(start + retention, retention).is_empty());
+        assert_eq!(
+            backoff.record_failure("vm-2", "boot timed out", start, initial, max),
+            initial
+        );
+    }
+
+    #[tokio::test]
+    async fn test_warmup_gate() {
+        let gate = std::sync::Arc::new(WarmupGate::new());
+        assert!(!gate.is_open());
+
+        let waiter = tokio::spawn({
+            let gate = gate.clone();
+            async move { gate.wait().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        gate.open();
+        waiter.await.unwrap();
+        assert!(gate.is_open());
+
+        // Already open: returns immediately
+        gate.wait().await;
+    }
+
+    #[tokio::test]
+    async fn test_fair_queue() {
+        let queue = std::sync::Arc::new(FairQueue::new(1, 16));
+        let running = queue.turn("busy", Priority::Normal).await.unwrap();
+
+        // "busy" queues three callers before "quiet" queues one
+        let (tx, mut served) = tokio::sync::mpsc::unbounded_channel();
+        for key in ["busy", "busy", "busy", "quiet"] {
+            let queue = queue.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let turn = queue.turn(key, Priority::Normal).await.unwrap();
+                tx.send(key).unwrap();
+                drop(turn);
+            });
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(queue.depths().get("busy"), Some(&3));
+        assert_eq!(queue.depths().get("quiet"), Some(&1));
+
+        // "quiet" is served second instead of waiting behind all of "busy"
+        drop(running);
+        let mut order = Vec::new();
+        for _ in 0..4 {
+            order.push(served.recv().await.unwrap());
+        }
+        assert_eq!(order, ["busy", "quiet", "busy", "busy"]);
+        assert!(queue.depths().is_empty());
+
+        // A caller that gives up doesn't hold on to its turn
+        let held = queue.turn("a", Priority::Normal).await.unwrap();
+        let abandoned = tokio::time::timeout(Duration::from_millis(10), queue.turn("b", Priority::Normal)).await;
+        assert!(abandoned.is_err());
+        drop(held);
+        let next = queue.turn("c", Priority::Normal);
+        let _next = tokio::time::timeout(Duration::from_secs(1), next).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fair_queue_priority() {
+        let queue = std::sync::Arc::new(FairQueue::new(1, 16));
+        let running = queue.turn("batch", Priority::Normal).await.unwrap();
+
+        // Queued in the reverse of the order they should be served
+        let (tx, mut served) = tokio::sync::mpsc::unbounded_channel();
+        let callers = [
+            ("batch", Priority::Low),
+            ("batch", Priority::Low),
+            ("api", Priority::Normal),
+            ("ui", Priority::High),
+            ("api", Priority::High),
+        ];
+        for (key, priority) in callers {
+            let queue = queue.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let turn = queue.turn(key, priority).await.unwrap();
+                tx.send((key, priority)).unwrap();
+                drop(turn);
+            });
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(queue.depths().get("api"), Some(&2));
+
+        drop(running);
+        let mut order = Vec::new();
+        for _ in 0..callers.len() {
+            order.push(served.recv().await.unwrap());
+        }
+        assert_eq!(
+            order,
+            [
+                ("ui", Priority::High),
+                ("api", Priority::High),
+                ("api", Priority::Normal),
+                ("batch", Priority::Low),
+                ("batch", Priority::Low),
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_boot_limiter() {
+        let limiter = Arc::new(BootLimiter::new(3));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let boot = |limiter: Arc<BootLimiter>, peak: Arc<AtomicUsize>| async move {
+            let _boot = limiter.start().await;
+            peak.fetch_max(limiter.booting(), Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        };
+        let boots = (0..20).map(|_| tokio::spawn(boot(limiter.clone(), peak.clone())));
+        futures::future::join_all(boots).await;
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+        assert_eq!(limiter.booting(), 0);
+
+        // Shrinking retires slots as they come free
+        limiter.set_limit(1);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        peak.store(0, Ordering::SeqCst);
+        let boots = (0..5).map(|_| tokio::spawn(boot(limiter.clone(), peak.clone())));
+        futures::future::join_all(boots).await;
+        assert!(peak.load(Ordering::SeqCst) <= 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_fair_queue_capacity() {
+        let (capacity, max_waiting) = (4, 20);
+        let queue = Arc::new(FairQueue::new(capacity, max_waiting));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        // Hold every slot, then flood it with far more callers than fit
+        let held = (0..capacity).map(|_| queue.turn("f", Priority::Normal));
+        let held: Vec<FairTurn> = futures::future::join_all(held)
+            .await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        let callers: Vec<_> = (0..200)
+            .map(|i| {
+                let queue = queue.clone();
+                let peak = peak.clone();
+                tokio::spawn(async move {
+                    let turn = queue.turn(&format!("f{}", i % 7), Priority::Normal).await?;
+                    peak.fetch_max(queue.in_use(), Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    drop(turn);
+                    Ok::<_, HyperdriveError>(())
+                })
+            })
+            .collect();
+        // With every slot held, only the turned-away callers can finish
+        while callers.iter().filter(|caller| caller.is_finished()).count() < 200 - max_waiting {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(queue.depths().values().sum::<usize>(), max_waiting);
+        drop(held);
+
+        let mut exhausted = 0;
+        for caller in callers {
+            if let Err(HyperdriveError::PoolExhausted) = caller.await.unwrap() {
+                exhausted += 1;
+            }
+        }
+        // Only the callers that fit in the queue ran, never more than
+        // `capacity` at once; the rest were turned away straight away
+        assert_eq!(exhausted, 200 - max_waiting);
+        assert!(peak.load(Ordering::SeqCst) <= capacity);
+        assert_eq!(queue.in_use(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_pool_boots_back_off() {
+        let base = tempfile::tempdir().unwrap();
+        let limits = PoolConfig {
+            min_vms: 0,
+            boot_backoff_initial_ms: 50,
+            ..Default::default()
+        };
+        let (pool, backoff) = failing_pool(base.path(), limits, Arc::new(FailedVms::new())).await;
+
+        let error = pool.acquire().await.unwrap_err();
+        assert!(error.to_string().contains("failed to boot"), "{:#}", error);
+        assert!(backoff.cooldown(Instant::now()).is_some());
+
+        // The next boot waits out the backoff before trying again
+        let started = Instant::now();
+        pool.acquire().await.unwrap_err();
+        assert!(started.elapsed() >= Duration::from_millis(40));
+        assert!(backoff.outage(2).unwrap().contains("missing-vmlinux not found"));
+        pool.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_failed_boots_listed() {
+        let base = tempfile::tempdir().unwrap();
+        let failed = Arc::new(FailedVms::new());
+        let limits = PoolConfig {
+            min_vms: 0,
+            ..Default::default()
+        };
+        let (pool, _) = failing_pool(base.path(), limits, failed.clone()).await;
+
+        let error = pool.acquire().await.unwrap_err();
+        let failure = error.downcast_ref::<crate::vm::BootFailure>().unwrap();
+        let listed = failed.list(Instant::now(), Duration::from_secs(60));
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, failure.vm_id.to_string());
+        assert_eq!(listed[0].state, VmState::Failed);
+        assert!(listed[0].failure_reason.as_ref().unwrap().contains("missing-vmlinux not found"));
+        pool.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_booting_counts_toward_max_vms() {
+        let base = tempfile::tempdir().unwrap();
+        let limits = PoolConfig {
+            min_vms: 0,
+            max_vms: 2,
+            boot_backoff_initial_ms: 60_000,
+            ..Default::default()
+        };
+        let (pool, _) = failing_pool(base.path(), limits, Arc::new(FailedVms::new())).await;
+        pool.acquire().await.unwrap_err();
+
+        // Every boot now waits out the backoff, holding its place toward
+        // max_vms while it does
+        let acquires: Vec<_> = (0..5)
+            .map(|_| {
+                let pool = pool.clone();
+                tokio::spawn(async move { pool.acquire().await })
+            })
+            .collect();
+        while pool.state.lock().booting < 2 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(pool.fill(10).await, 0);
+        assert_eq!(pool.state.lock().booting, 2);
+
+        pool.shutdown().await;
+        for acquire in acquires {
+            acquire.await.unwrap().unwrap_err();
+        }
+        assert_eq!(pool.state.lock().booting, 0);
+    }
+}
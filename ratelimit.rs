@@ -0,0 +1,169 @@
+use axum::http::{HeaderMap, HeaderValue};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub invocations: u32, // allowed per function per window; 0 disables
+    pub window_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            invocations: 0,
+            window_secs: 60,
+        }
+    }
+}
+
+// Windows tracked before expired ones are swept out
+const MAX_TRACKED_WINDOWS: usize = 4096;
+
+// Where a caller stands after an invocation was counted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitStatus {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset: Duration, // until the window starts over
+}
+
+impl RateLimitStatus {
+    // Whole seconds until the window starts over, rounded up
+    pub fn reset_secs(&self) -> u64 {
+        self.reset.as_secs() + u64::from(self.reset.subsec_nanos() > 0)
+    }
+
+    // X-RateLimit-Limit, -Remaining and -Reset (in seconds)
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        headers.insert("x-ratelimit-limit", HeaderValue::from(self.limit));
+        headers.insert("x-ratelimit-remaining", HeaderValue::from(self.remaining));
+        headers.insert("x-ratelimit-reset", HeaderValue::from(self.reset_secs()));
+    }
+}
+
+struct Window {
+    started: Instant,
+    count: u32,
+}
+
+// Fixed-window invocation limits per function. Each function gets
+// `invocations` calls per window; the window starts with its first call.
+pub struct RateLimiter {
+    windows: Mutex<HashMap<String, Window>>,
+    limit: u32,
+    window: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            windows: Mutex::new(HashMap::new()),
+            limit: config.invocations,
+            window: Duration::from_secs(config.window_secs),
+        }
+    }
+
+    // Count calls against the key's window, e.g. one per payload of a
+    // batch. They're allowed or refused together. None when limiting is off.
+    pub fn charge(&self, key: &str, calls: u32) -> Option<RateLimitStatus> {
+        self.charge_at(key, calls, Instant::now())
+    }
+
+    #[cfg(test)]
+    fn check_at(&self, key: &str, now: Instant) -> Option<RateLimitStatus> {
+        self.charge_at(key, 1, now)
+    }
+
+    fn charge_at(&self, key: &str, calls: u32, now: Instant) -> Option<RateLimitStatus> {
+        if self.limit == 0 {
+            return None;
+        }
+
+        let mut windows = self.windows.lock();
+        if windows.len() >= MAX_TRACKED_WINDOWS {
+            windows.retain(|_, w| now.saturating_duration_since(w.started) < self.window);
+        }
+        let window = windows.entry(key.to_string()).or_insert(Window { started: now, count: 0 });
+        if now.saturating_duration_since(window.started) >= self.window {
+            *window = Window { started: now, count: 0 };
+        }
+
+        let allowed = window.count.saturating_add(calls) <= self.limit;
+        if allowed {
+            window.count += calls;
+        }
+        Some(RateLimitStatus {
+            allowed,
+            limit: self.limit,
+            remaining: self.limit - window.count,
+            reset: self.window.saturating_sub(now.saturating_duration_since(window.started)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_window() {
+        let limiter = RateLimiter::new(&RateLimitConfig {
+            invocations: 2,
+            window_secs: 10,
+        });
+        let start = Instant::now();
+
+        let first = limiter.check_at("default/a", start).unwrap();
+        assert!(first.allowed);
+        assert_eq!((first.limit, first.remaining), (2, 1));
+        assert_eq!(first.reset, Duration::from_secs(10));
+
+        assert!(limiter.check_at("default/a", start + Duration::from_secs(1)).unwrap().allowed);
+        let denied = limiter.check_at("default/a", start + Duration::from_secs(4)).unwrap();
+        assert!(!denied.allowed);
+        assert_eq!(denied.remaining, 0);
+        assert_eq!(denied.reset, Duration::from_secs(6));
+
+        // Functions are limited separately, and the window starts over
+        assert!(limiter.check_at("default/b", start).unwrap().allowed);
+        let next = limiter.check_at("default/a", start + Duration::from_secs(10)).unwrap();
+        assert!(next.allowed);
+        assert_eq!(next.remaining, 1);
+
+        let mut headers = HeaderMap::new();
+        denied.apply(&mut headers);
+        assert_eq!(headers["x-ratelimit-remaining"], "0");
+        assert_eq!(headers["x-ratelimit-reset"], "6");
+    }
+
+    #[test]
+    fn test_charge_several() {
+        let limiter = RateLimiter::new(&RateLimitConfig {
+            invocations: 5,
+            window_secs: 10,
+        });
+        let start = Instant::now();
+
+        let batch = limiter.charge_at("default/a", 3, start).unwrap();
+        assert!(batch.allowed);
+        assert_eq!(batch.remaining, 2);
+
+        // A batch that doesn't fit is refused whole, leaving room for less
+        let refused = limiter.charge_at("default/a", 3, start).unwrap();
+        assert!(!refused.allowed);
+        assert_eq!(refused.remaining, 2);
+        assert_eq!(limiter.charge_at("default/a", 2, start).unwrap().remaining, 0);
+        assert!(!limiter.check_at("default/a", start).unwrap().allowed);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let limiter = RateLimiter::new(&RateLimitConfig::default());
+        assert!(limiter.charge("default/a", 1).is_none());
+    }
+}
@@ -0,0 +1,127 @@
+use serde::Serialize;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::warn;
+
+use crate::types::VmConfig;
+
+const VERSION_COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+// Bytes read from each end of the rootfs to fingerprint it
+const ROOTFS_FINGERPRINT_BYTES: u64 = 1024 * 1024;
+
+// What functions actually run against, gathered once at startup so /info
+// stays cheap. Anything that can't be determined is reported as null.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuntimeInfo {
+    pub v8_host_version: Option<String>,
+    pub firecracker_version: Option<String>,
+    pub kernel_version: Option<String>,
+    pub rootfs: Option<String>, // file name and content fingerprint
+}
+
+impl RuntimeInfo {
+    pub async fn gather(config: &VmConfig) -> Self {
+        let kernel_path = config.kernel_path.clone();
+        let rootfs_path = config.rootfs_path.clone();
+        let images = tokio::task::spawn_blocking(move || (kernel_version(&kernel_path), rootfs_id(&rootfs_path)));
+        let (kernel_version, rootfs) = images.await.unwrap_or((None, None));
+
+        Self {
+            v8_host_version: command_version(&config.v8_host_path).await,
+            firecracker_version: command_version("firecracker").await,
+            kernel_version,
+            rootfs,
+        }
+    }
+}
+
+// First line of `<program> --version`
+async fn command_version(program: &str) -> Option<String> {
+    let output = Command::new(program).arg("--version").kill_on_drop(true).output();
+    match tokio::time::timeout(VERSION_COMMAND_TIMEOUT, output).await {
+        Ok(Ok(output)) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty()),
+        Ok(Ok(output)) => {
+            warn!("{} --version exited with {}", program, output.status);
+            None
+        }
+        Ok(Err(e)) => {
+            warn!("Failed to run {} --version: {}", program, e);
+            None
+        }
+        Err(_) => {
+            warn!("{} --version timed out", program);
+            None
+        }
+    }
+}
+
+// Uncompressed kernels embed a "Linux version x.y.z ..." banner
+fn kernel_version(path: &str) -> Option<String> {
+    match std::fs::read(path) {
+        Ok(image) => parse_kernel_banner(&image),
+        Err(e) => {
+            warn!("Failed to read kernel image {}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn parse_kernel_banner(image: &[u8]) -> Option<String> {
+    const BANNER: &[u8] = b"Linux version ";
+    let start = image.windows(BANNER.len()).position(|w| w == BANNER)? + BANNER.len();
+    let version: Vec<u8> = image[start..]
+        .iter()
+        .take_while(|b| b.is_ascii_graphic())
+        .copied()
+        .collect();
+    (!version.is_empty()).then(|| String::from_utf8_lossy(&version).into_owned())
+}
+
+// Hashing a multi-gigabyte image at startup is too slow, so the
+// fingerprint covers its size and the blocks at either end
+fn rootfs_id(path: &str) -> Option<String> {
+    let fingerprint = || -> std::io::Result<u64> {
+        let mut file = std::fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut hasher = DefaultHasher::new();
+        size.hash(&mut hasher);
+
+        let mut block = Vec::new();
+        (&mut file).take(ROOTFS_FINGERPRINT_BYTES).read_to_end(&mut block)?;
+        file.seek(SeekFrom::Start(size.saturating_sub(ROOTFS_FINGERPRINT_BYTES)))?;
+        file.take(ROOTFS_FINGERPRINT_BYTES).read_to_end(&mut block)?;
+        block.hash(&mut hasher);
+
+        Ok(hasher.finish())
+    };
+
+    match fingerprint() {
+        Ok(hash) => {
+            let name = Path::new(path).file_name()?.to_string_lossy();
+            Some(format!("{}@{:016x}", name, hash))
+        }
+        Err(e) => {
+            warn!("Failed to fingerprint rootfs {}: {}", path, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kernel_banner() {
+        let image = b"\x7fELF\x00\x00Linux version 5.10.186 (builder@host) #1 SMP\x00";
+        assert_eq!(parse_kernel_banner(image).as_deref(), Some("5.10.186"));
+        assert_eq!(parse_kernel_banner(b"\x7fELF no banner here"), None);
+    }
+}
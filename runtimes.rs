@@ -0,0 +1,245 @@
+use anyhow::Result;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use crate::config::FunctionsConfig;
+use crate::function::{invalid, longest_line, ValidationErrorKind};
+use crate::typescript;
+
+// A language functions can be written in. Each runtime checks submitted
+// code against its own rules and returns the JavaScript the V8 host will
+// execute.
+pub trait Runtime: Send + Sync {
+    fn validate(&self, code: &str) -> Result<String>;
+}
+
+// The runtimes accepted on create, keyed by the `runtime` field
+pub struct Runtimes {
+    runtimes: BTreeMap<&'static str, Box<dyn Runtime>>,
+    max_line_bytes: Option<usize>, // checked on the source as submitted
+}
+
+impl Runtimes {
+    pub fn with_config(config: &FunctionsConfig) -> Self {
+        let javascript = JavaScript::with_config(config);
+        let mut runtimes: BTreeMap<&'static str, Box<dyn Runtime>> = BTreeMap::new();
+        runtimes.insert("ts", Box::new(TypeScript { javascript: javascript.clone() }));
+        runtimes.insert("v8", Box::new(javascript));
+        Self {
+            runtimes,
+            max_line_bytes: config.max_line_bytes,
+        }
+    }
+
+    pub fn validate(&self, runtime: &str, code: &str) -> Result<String> {
+        let Some(runtime) = self.runtimes.get(runtime) else {
+            let names: Vec<String> = self.runtimes.keys().map(|name| format!("'{}'", name)).collect();
+            return Err(invalid(
+                ValidationErrorKind::UnsupportedRuntime,
+                format!("Only {} runtimes are currently supported", names.join(" and ")),
+            ));
+        };
+        if let Some(max) = self.max_line_bytes {
+            if let Some((line, bytes)) = longest_line(code).filter(|&(_, bytes)| bytes > max) {
+                return Err(invalid(
+                    ValidationErrorKind::LineTooLong,
+                    format!(
+                        "Line {} is {} bytes, over the limit of {}; deploy unminified code",
+                        line, bytes, max
+                    ),
+                ));
+            }
+        }
+        runtime.validate(code)
+    }
+}
+
+// Plain JavaScript, run as submitted
+#[derive(Clone)]
+pub struct JavaScript {
+    forbidden_modules: Vec<String>,
+    forbidden_patterns: Vec<String>,
+    require_default_export: bool,
+}
+
+impl JavaScript {
+    pub fn with_config(config: &FunctionsConfig) -> Self {
+        Self {
+            forbidden_modules: config.forbidden_modules.clone(),
+            forbidden_patterns: config.forbidden_patterns.clone(),
+            require_default_export: config.require_default_export,
+        }
+    }
+}
+
+impl Runtime for JavaScript {
+    fn validate(&self, code: &str) -> Result<String> {
+        if self.require_default_export && !exports_handler(code) {
+            return Err(invalid(
+                ValidationErrorKind::MissingDefaultExport,
+                "Function must export a handler: a default export, module.exports, \
+                 exports.handler or a named `handler` export",
+            ));
+        }
+
+        // Check for forbidden imports
+        for specifier in imported_modules(code) {
+            let module = normalize_module(&specifier);
+            if self.forbidden_modules.iter().any(|m| m == module) {
+                return Err(invalid(
+                    ValidationErrorKind::ForbiddenModule,
+                    format!("Function imports forbidden module: {}", specifier),
+                ));
+            }
+        }
+
+        // Check for forbidden patterns
+        for pattern in &self.forbidden_patterns {
+            if code.contains(pattern.as_str()) {
+                return Err(invalid(
+                    ValidationErrorKind::ForbiddenPattern,
+                    format!("Function contains forbidden pattern: {}", pattern),
+                ));
+            }
+        }
+
+        Ok(code.to_string())
+    }
+}
+
+// TypeScript, compiled down to JavaScript. The JavaScript rules apply to
+// the compiled output, so type-only imports don't trip the module checks.
+pub struct TypeScript {
+    javascript: JavaScript,
+}
+
+impl Runtime for TypeScript {
+    fn validate(&self, code: &str) -> Result<String> {
+        self.javascript.validate(&typescript::transpile(code)?)
+    }
+}
+
+// Whether the module exports something the V8 host can call, in any of the
+// ESM or CommonJS styles bundlers and authors commonly produce
+fn exports_handler(code: &str) -> bool {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    let patterns = PATTERNS.get_or_init(|| {
+        [
+            // export default ...
+            r"\bexport\s+default\b",
+            // module.exports = ... / module.exports.handler = ...
+            r"\bmodule\.exports\b",
+            // exports.handler = ...
+            r"\bexports\.handler\s*=",
+            // export function handler / export async function handler
+            r"\bexport\s+(async\s+)?function\s*\*?\s*handler\b",
+            // export const handler = ...
+            r"\bexport\s+(const|let|var)\s+handler\b",
+            // export { handler } / export { main as default } / export { main as handler }
+            r"\bexport\s*\{[^}]*\b(handler|as\s+default)\s*(,[^}]*)?\}",
+        ]
+        .iter()
+        .map(|p| Regex::new(p).expect("valid export pattern"))
+        .collect()
+    });
+
+    patterns.iter().any(|p| p.is_match(code))
+}
+
+// Module specifiers referenced via require(), import() or import/export
+// statements, whatever the quote style or spacing
+fn imported_modules(code: &str) -> Vec<String> {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    let patterns = PATTERNS.get_or_init(|| {
+        [
+            r#"\brequire\s*\(\s*['"`]([^'"`]+)['"`]"#,
+            r#"\bimport\s*\(\s*['"`]([^'"`]+)['"`]"#,
+            r#"\bimport\s*['"`]([^'"`]+)['"`]"#,
+            r#"\bfrom\s*['"`]([^'"`]+)['"`]"#,
+        ]
+        .iter()
+        .map(|p| Regex::new(p).expect("valid import pattern"))
+        .collect()
+    });
+
+    patterns
+        .iter()
+        .flat_map(|p| p.captures_iter(code))
+        .map(|c| c[1].trim().to_string())
+        .collect()
+}
+
+// `node:fs/promises` -> `fs`; scoped packages keep their scope
+fn normalize_module(specifier: &str) -> &str {
+    let module = specifier.trim().trim_start_matches("node:");
+    if module.starts_with('@') {
+        return module;
+    }
+    module.split('/').next().unwrap_or(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function::ValidationError;
+
+    fn kind(result: Result<String>) -> ValidationErrorKind {
+        result.unwrap_err().downcast_ref::<ValidationError>().unwrap().kind
+    }
+
+    #[test]
+    fn test_runtimes() {
+        let runtimes = Runtimes::with_config(&FunctionsConfig::default());
+        let code = "export default function handler(event) { return event; }";
+        assert_eq!(runtimes.validate("v8", code).unwrap(), code);
+        assert!(runtimes.validate("ts", code).is_ok());
+
+        assert_eq!(kind(runtimes.validate("python", code)), ValidationErrorKind::UnsupportedRuntime);
+        assert_eq!(
+            kind(runtimes.validate("v8", "function handler() {}")),
+            ValidationErrorKind::MissingDefaultExport
+        );
+        assert_eq!(
+            kind(runtimes.validate("ts", "import fs from 'node:fs/promises'; export default () => fs;")),
+            ValidationErrorKind::ForbiddenModule
+        );
+
+        let strict = Runtimes::with_config(&FunctionsConfig {
+            max_line_bytes: Some(40),
+            ..Default::default()
+        });
+        assert!(strict.validate("v8", "export default (event) =>\n  event;").is_ok());
+        assert_eq!(kind(strict.validate("v8", code)), ValidationErrorKind::LineTooLong);
+    }
+
+    #[test]
+    fn test_handler_export_styles() {
+        let accepted = [
+            "export default function handler(event) { return event; }",
+            "export default async (event) => event;",
+            "module.exports = async function (event) { return event; };",
+            "module.exports.handler = (event) => event;",
+            "exports.handler = async (event) => event;",
+            "export function handler(event) { return event; }",
+            "export async function handler(event) { return event; }",
+            "export const handler = async (event) => event;",
+            "const handler = (event) => event; export { handler };",
+            "function main(event) { return event; } export { main as default };",
+            "function main(event) { return event; } export { util, main as handler };",
+        ];
+        for code in accepted {
+            assert!(exports_handler(code), "{}", code);
+        }
+
+        let rejected = [
+            "function handler(event) { return event; }",
+            "export function helper(event) { return event; }",
+            "export const handlerName = 'x';",
+            "const exports_handler = 1; export { exports_handler };",
+        ];
+        for code in rejected {
+            assert!(!exports_handler(code), "{}", code);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::types::{Function, ScheduledFunction};
+
+// Parse a cron expression. Standard five-field expressions (minute through
+// day of week) run at second zero; the six- and seven-field forms the
+// `cron` crate understands (leading seconds, trailing year) pass through.
+pub fn parse_schedule(expression: &str) -> Result<Schedule> {
+    let expression = expression.trim();
+    let normalized = match expression.split_whitespace().count() {
+        5 => format!("0 {}", expression),
+        _ => expression.to_string(),
+    };
+    Schedule::from_str(&normalized).map_err(|e| anyhow::anyhow!("Invalid schedule '{}': {}", expression, e))
+}
+
+// When each scheduled function next runs and how its last run went. The
+// scheduler task feeds it the current set of scheduled functions every tick,
+// so new, changed and deleted schedules are picked up without extra hooks.
+pub struct ScheduleTracker {
+    entries: Mutex<HashMap<(String, String), ScheduleEntry>>, // keyed by namespace, name
+}
+
+struct ScheduleEntry {
+    expression: String,
+    schedule: Schedule,
+    next_run: Option<DateTime<Utc>>, // None once the schedule has no future times
+    last_run: Option<DateTime<Utc>>,
+    last_success: Option<bool>,
+    running: bool,
+}
+
+impl ScheduleTracker {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Functions whose next run has come, marked as running. A function still
+    // running from its previous tick is skipped rather than stacked up, and
+    // ticks missed while the server was busy collapse into one run.
+    pub fn due(&self, functions: &[Function], now: DateTime<Utc>) -> Vec<Function> {
+        let mut entries = self.entries.lock();
+        entries.retain(|(namespace, name), _| {
+            functions.iter().any(|f| f.namespace == *namespace && f.name == *name)
+        });
+
+        let mut due = Vec::new();
+        for function in functions {
+            let Some(expression) = function.schedule.as_deref() else {
+                continue;
+            };
+            let key = (function.namespace.clone(), function.name.clone());
+            if entries.get(&key).is_none_or(|entry| entry.expression != expression) {
+                // Validated on create, so this only fails for functions
+                // stored before the expression became invalid
+                let Ok(schedule) = parse_schedule(expression) else {
+                    entries.remove(&key);
+                    continue;
+                };
+                let next_run = schedule.after(&now).next();
+                let previous = entries.remove(&key);
+                entries.insert(
+                    key.clone(),
+                    ScheduleEntry {
+                        expression: expression.to_string(),
+                        schedule,
+                        next_run,
+                        last_run: previous.as_ref().and_then(|e| e.last_run),
+                        last_success: previous.as_ref().and_then(|e| e.last_success),
+                        running: previous.is_some_and(|e| e.running),
+                    },
+                );
+            }
+
+            let entry = entries.get_mut(&key).expect("entry inserted above");
+            if entry.next_run.is_some_and(|at| at <= now) {
+                entry.next_run = entry.schedule.after(&now).next();
+                if entry.running {
+                    continue;
+                }
+                entry.running = true;
+                entry.last_run = Some(now);
+                due.push(function.clone());
+            }
+        }
+        due
+    }
+
+    pub fn finish(&self, namespace: &str, name: &str, success: bool) {
+        let mut entries = self.entries.lock();
+        if let Some(entry) = entries.get_mut(&(namespace.to_string(), name.to_string())) {
+            entry.running = false;
+            entry.last_success = Some(success);
+        }
+    }
+
+    // Scheduled functions in the namespace, soonest first
+    pub fn list(&self, namespace: &str) -> Vec<ScheduledFunction> {
+        let entries = self.entries.lock();
+        let mut scheduled: Vec<ScheduledFunction> = entries
+            .iter()
+            .filter(|((ns, _), _)| ns == namespace)
+            .map(|((_, name), entry)| ScheduledFunction {
+                name: name.clone(),
+                schedule: entry.expression.clone(),
+                next_run: entry.next_run.map(|at| at.to_rfc3339()),
+                last_run: entry.last_run.map(|at| at.to_rfc3339()),
+                last_success: entry.last_success,
+                running: entry.running,
+            })
+            .collect();
+        // RFC 3339 strings in UTC sort chronologically; None sorts last
+        scheduled.sort_by(|a, b| match (&a.next_run, &b.next_run) {
+            (Some(x), Some(y)) => x.cmp(y).then_with(|| a.name.cmp(&b.name)),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => a.name.cmp(&b.name),
+        });
+        scheduled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn scheduled(name: &str, schedule: &str) -> Function {
+        Function {
+            schedule: Some(schedule.to_string()),
+            ..Function::fixture(name, "export default function handler(event) { return event; }")
+        }
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        assert!(parse_schedule("*/5 * * * *").is_ok());
+        assert!(parse_schedule("30 0 12 * * Mon-Fri").is_ok());
+        assert!(parse_schedule("every minute").is_err());
+        assert!(parse_schedule("61 * * * *").is_err());
+    }
+
+    #[test]
+    fn test_due_functions() {
+        let tracker = ScheduleTracker::new();
+        let functions = vec![scheduled("minutely", "* * * * *"), scheduled("hourly", "0 * * * *")];
+        let at = |min: u32, sec: u32| Utc.with_ymd_and_hms(2024, 1, 1, 10, min, sec).unwrap();
+
+        // The first tick only works out when each function runs next
+        assert!(tracker.due(&functions, at(0, 30)).is_empty());
+        let listed = tracker.list("default");
+        assert_eq!(listed[0].name, "minutely");
+        assert_eq!(listed[0].next_run.as_deref(), Some("2024-01-01T10:01:00+00:00"));
+        assert_eq!(listed[1].next_run.as_deref(), Some("2024-01-01T11:00:00+00:00"));
+
+        let due = tracker.due(&functions, at(1, 0));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].name, "minutely");
+
+        // Still running at the next tick, so that run is skipped
+        assert!(tracker.due(&functions, at(2, 0)).is_empty());
+        tracker.finish("default", "minutely", false);
+        assert_eq!(tracker.list("default")[0].last_success, Some(false));
+        assert_eq!(tracker.due(&functions, at(3, 0)).len(), 1);
+
+        // Unscheduled functions drop out
+        assert!(tracker.due(&functions[1..], at(3, 30)).is_empty());
+        assert_eq!(tracker.list("default").len(), 1);
+    }
+}
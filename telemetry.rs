@@ -0,0 +1,70 @@
+use anyhow::{Context, Result};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use serde::Deserialize;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    // OTLP/HTTP traces endpoint, e.g. http://localhost:4318/v1/traces;
+    // unset keeps spans local to the log
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: "hyperdrive".to_string(),
+        }
+    }
+}
+
+// Install the global subscriber. Logs always go to stdout; with an OTLP
+// endpoint configured, spans are also batched out to the collector. RUST_LOG
+// overrides the default of info for both, e.g. `RUST_LOG=hyperdrive=debug`
+// for this crate (targets are module paths such as
+// `hyperdrive_rust::function`, matched by prefix).
+//
+// The returned provider has to be shut down on exit to flush spans still
+// in its batch.
+pub fn init(config: &TelemetryConfig) -> Result<Option<SdkTracerProvider>> {
+    let provider = config
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| {
+            let exporter = SpanExporter::builder()
+                .with_http()
+                .with_endpoint(endpoint)
+                .build()
+                .with_context(|| format!("Failed to build OTLP exporter for {}", endpoint))?;
+            Ok::<_, anyhow::Error>(
+                SdkTracerProvider::builder()
+                    .with_batch_exporter(exporter)
+                    .with_resource(Resource::builder().with_service_name(config.service_name.clone()).build())
+                    .build(),
+            )
+        })
+        .transpose()?;
+
+    let otel = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer("hyperdrive")));
+    tracing_subscriber::registry()
+        .with(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel)
+        .try_init()
+        .context("Failed to install tracing subscriber")?;
+
+    Ok(provider)
+}
@@ -0,0 +1,163 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+// Reshapes a payload on its way into or out of a function, so a webhook's
+// shape can be adapted without touching the function's code. A transform
+// is a JSON template: strings starting with `$` are replaced by the value
+// at that path in the input and everything else is copied as is.
+//
+//   "$"            the whole input
+//   "$.user.id"    a field
+//   "$.items[0]"   an array element
+//   "$$ off"       the literal string "$ off"
+//
+// Paths that don't resolve produce null. Serialized as the template.
+#[derive(Debug, Clone)]
+pub struct Transform {
+    template: Value,
+    root: Node,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Literal(Value),
+    Path(Vec<Segment>),
+    Array(Vec<Node>),
+    Object(Vec<(String, Node)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Segment {
+    Field(String),
+    Index(usize),
+}
+
+impl Transform {
+    pub fn compile(template: Value) -> Result<Self, String> {
+        let root = compile_node(&template)?;
+        Ok(Self { template, root })
+    }
+
+    pub fn template(&self) -> &Value {
+        &self.template
+    }
+
+    pub fn apply(&self, input: &Value) -> Value {
+        render(&self.root, input)
+    }
+}
+
+impl Serialize for Transform {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.template.serialize(serializer)
+    }
+}
+
+fn compile_node(value: &Value) -> Result<Node, String> {
+    Ok(match value {
+        Value::String(s) if s.starts_with("$$") => Node::Literal(Value::String(s[1..].to_string())),
+        Value::String(s) if s.starts_with('$') => Node::Path(parse_path(s)?),
+        Value::Array(items) => Node::Array(items.iter().map(compile_node).collect::<Result<_, _>>()?),
+        Value::Object(fields) => Node::Object(
+            fields
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), compile_node(value)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        literal => Node::Literal(literal.clone()),
+    })
+}
+
+// `$`, `$.a.b`, `$.items[0]`; also used by the output policy's redactions
+pub(crate) fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut rest = path
+        .strip_prefix('$')
+        .ok_or_else(|| format!("Path {} must start with $", path))?;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err(format!("Empty field name in path {}", path));
+            }
+            segments.push(Segment::Field(after[..end].to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(|| format!("Unclosed [ in path {}", path))?;
+            let index = after[..end]
+                .trim()
+                .parse()
+                .map_err(|_| format!("Array index must be a non-negative integer in path {}", path))?;
+            segments.push(Segment::Index(index));
+            rest = &after[end + 1..];
+        } else {
+            return Err(format!("Expected . or [ in path {} (use $$ for a literal $)", path));
+        }
+    }
+    Ok(segments)
+}
+
+fn render(node: &Node, input: &Value) -> Value {
+    match node {
+        Node::Literal(value) => value.clone(),
+        Node::Path(segments) => segments
+            .iter()
+            .try_fold(input, |value, segment| match segment {
+                Segment::Field(name) => value.get(name),
+                Segment::Index(index) => value.get(index),
+            })
+            .cloned()
+            .unwrap_or(Value::Null),
+        Node::Array(items) => Value::Array(items.iter().map(|item| render(item, input)).collect()),
+        Node::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), render(value, input)))
+                .collect::<Map<_, _>>(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_apply() {
+        let transform = Transform::compile(json!({
+            "user": "$.sender.login",
+            "first_label": "$.labels[0].name",
+            "event": "push",
+            "price": "$$5",
+            "ids": ["$.sender.id", "$.missing.field"],
+        }))
+        .unwrap();
+        let input = json!({
+            "sender": {"login": "octocat", "id": 1},
+            "labels": [{"name": "bug"}],
+        });
+        assert_eq!(
+            transform.apply(&input),
+            json!({
+                "user": "octocat",
+                "first_label": "bug",
+                "event": "push",
+                "price": "$5",
+                "ids": [1, null],
+            })
+        );
+
+        assert_eq!(Transform::compile(json!("$")).unwrap().apply(&input), input);
+    }
+
+    #[test]
+    fn test_invalid_paths() {
+        for path in ["$user", "$.", "$.a..b", "$.a[x]", "$.a[0", "$.a[-1]"] {
+            assert!(Transform::compile(json!({"v": path})).is_err(), "{}", path);
+        }
+        for path in ["", "é.a", "user.ssn"] {
+            assert!(parse_path(path).is_err(), "{}", path);
+        }
+    }
+}
@@ -1,8 +1,38 @@
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+use crate::breaker::CircuitStatus;
+use crate::function::{CodeBudget, ValidationErrorKind};
+use crate::ip_access::IpRules;
+use crate::transform::Transform;
+use crate::usage::UsageSummary;
+
 // API Request/Response types
+// Build metadata, so operators can confirm exactly what's deployed
+#[derive(Debug, Serialize)]
+pub struct VersionResponse {
+    pub version: String,
+    pub git_commit: String,
+    pub build_timestamp: String,
+    pub rustc_version: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InfoResponse {
+    pub platform: String,
+    pub version: String,
+    pub runtime: crate::runtime_info::RuntimeInfo,
+}
+
 #[derive(Debug, Serialize)]
 pub struct HealthResponse {
     pub platform: String,
@@ -10,6 +40,31 @@ pub struct HealthResponse {
     pub version: String,
     pub timestamp: String,
     pub components: HealthComponents,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostic: Option<String>, // why the server is unhealthy
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    pub status: String, // "ready", or "warming" until the warm pool has booted
+}
+
+// Outcome of the admin self-test, step by step so a failure points at the
+// stage that broke
+#[derive(Debug, Serialize)]
+pub struct SelfTestResponse {
+    pub passed: bool,
+    pub duration_ms: u64,
+    pub steps: Vec<SelfTestStep>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SelfTestStep {
+    pub step: String, // "create", "invoke" or "cleanup"
+    pub passed: bool,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -21,17 +76,151 @@ pub struct HealthComponents {
     pub monitoring: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CreateFunctionRequest {
     pub name: String,
+    #[serde(default)]
     pub code: String,
-    pub runtime: String, // "v8" for now
+    // Fetched into `code` on create; exactly one of the two is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_url: Option<String>,
+    pub runtime: String, // "v8" (JavaScript) or "ts" (TypeScript)
+    #[serde(default)]
+    pub audit_payloads: bool, // include invocation payloads in the audit log
+    #[serde(default)]
+    pub http_response: bool, // handler returns a FunctionHttpResponse
+    #[serde(default)]
+    pub idempotent: bool, // results may be cached per payload
+    #[serde(default)]
+    pub sign_responses: bool, // X-Signature on invoke responses; needs signing.key
+    #[serde(default)]
+    pub tier: FunctionTier,
+    // Highest X-Priority callers may claim; asking for more gets this
+    #[serde(default)]
+    pub max_priority: Priority,
+    // Log each invocation's payload and result at debug level. Off by
+    // default: payloads and results may contain sensitive data.
+    #[serde(default)]
+    pub debug: bool,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    // Cron expression to invoke the function on, with `schedule_payload`
+    // (default `{}`) as the event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule_payload: Option<serde_json::Value>,
+    // Smaller cap than invoke.max_payload_bytes for functions that only
+    // expect small inputs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_payload_bytes: Option<usize>,
+    // JSON Schema that invocation payloads must match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_schema: Option<serde_json::Value>,
+    // Templates reshaping the payload before it reaches the function and
+    // its result before it's returned; see Transform
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_transform: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_transform: Option<serde_json::Value>,
+    // CIDR blocks callers must come from / may not come from; denials win
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_ips: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub denied_ips: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    // Stable identifier for validation failures; `error` is for humans
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<ValidationErrorKind>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<String>, // individual problems behind `error`
+}
+
+impl ErrorResponse {
+    pub fn new(error: impl Into<String>) -> Self {
+        Self {
+            error: error.into(),
+            line: None,
+            column: None,
+            code: None,
+            details: Vec::new(),
+        }
+    }
+}
+
+// Path parameters for function routes. Routes outside
+// `/api/v1/namespaces/:namespace` fall back to the default namespace.
+#[derive(Debug, Deserialize)]
+pub struct NamespacePath {
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FunctionPath {
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayPath {
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
+    pub name: String,
+    pub invocation_id: Uuid,
+}
+
+fn default_namespace() -> String {
+    crate::function::DEFAULT_NAMESPACE.to_string()
 }
 
 #[derive(Debug, Serialize)]
 pub struct CreateFunctionResponse {
+    pub namespace: String,
     pub name: String,
     pub created: bool,
+    pub status: FunctionStatus,
+    pub warnings: Vec<String>, // advisory lint findings; never block the create
+    pub code: CodeBudget,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CloneFunctionRequest {
+    pub name: String, // the copy's name; must not exist yet
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListFunctionsQuery {
+    pub tag: Option<String>, // "key:value"
+    #[serde(default)]
+    pub sort: FunctionSort,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FunctionSort {
+    #[default]
+    Name,
+    CreatedAt,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
 }
 
 #[derive(Debug, Serialize)]
@@ -39,9 +228,281 @@ pub struct FunctionListResponse {
     pub functions: Vec<Function>,
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct DeleteFunctionQuery {
+    #[serde(default)]
+    pub hard: bool, // skip the recycle bin
+}
+
+// A soft-deleted function in the recycle bin
+#[derive(Debug, Clone, Serialize)]
+pub struct DeletedFunction {
+    pub namespace: String,
+    pub name: String,
+    pub version: u32, // latest at the time of deletion
+    pub deleted_at: chrono::DateTime<chrono::Utc>,
+    pub purge_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeletedFunctionListResponse {
+    pub functions: Vec<DeletedFunction>, // sorted by name
+}
+
+#[derive(Debug, Serialize)]
+pub struct FunctionStatsResponse {
+    pub namespace: String,
+    pub name: String,
+    pub latest_version: u32,
+    pub retained_versions: usize,
+    pub circuit: CircuitStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<UsageSummary>, // absent until the V8 host reports usage
+}
+
+#[derive(Debug, Serialize)]
+pub struct FunctionVersionsResponse {
+    pub namespace: String,
+    pub name: String,
+    pub versions: Vec<Function>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrafficSplitRequest {
+    pub weights: Vec<VersionWeight>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrafficSplitResponse {
+    pub namespace: String,
+    pub name: String,
+    pub weights: Vec<VersionWeight>, // empty when all traffic goes to latest
+}
+
+// Latest version of every function in a namespace, in the shape accepted by
+// create so a document can be imported elsewhere as-is
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FunctionExport {
+    #[serde(default)]
+    pub exported_at: String,
+    pub functions: Vec<ExportedFunction>,
+}
+
+// Counts default to zero so documents from before they were exported
+// still import
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedFunction {
+    #[serde(flatten)]
+    pub function: CreateFunctionRequest,
+    #[serde(default)]
+    pub invocation_count: u64,
+    #[serde(default)]
+    pub error_count: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub overwrite: bool, // replace functions that already exist
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResponse {
+    pub results: Vec<ImportItemResult>, // same order as the document
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportItemResult {
+    pub name: String,
+    #[serde(flatten)]
+    pub outcome: ImportOutcome,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum ImportOutcome {
+    Imported { version: u32 },
+    Skipped { reason: String },
+    Error { error: String },
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct InvokeQuery {
+    pub version: Option<u32>, // latest when omitted
+    pub timeout_ms: Option<u64>, // single invocations; see Deadline
+    #[serde(default)]
+    pub meta: bool, // include InvocationMeta in JSON responses
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CodeQuery {
+    pub version: Option<u32>, // latest when omitted
+    #[serde(default)]
+    pub download: bool, // serve as an attachment named after the function
+}
+
 #[derive(Debug, Serialize)]
 pub struct InvokeResponse {
     pub result: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<InvocationMeta>, // only with ?meta=true
+}
+
+// How one invocation ran. Built only from platform measurements, never from
+// the function's code, payload or environment.
+#[derive(Debug, Serialize)]
+pub struct InvocationMeta {
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vm_id: Option<Uuid>, // absent when no VM ran the call
+    pub cold_start: bool,
+    pub cached: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ResourceUsage>,
+}
+
+// What an `http_response` function returns instead of a bare JSON result
+#[derive(Debug, Deserialize)]
+pub struct FunctionHttpResponse {
+    #[serde(default = "default_http_status")]
+    pub status: u16,
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub base64: bool, // body is base64-encoded binary
+}
+
+fn default_http_status() -> u16 {
+    200
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchInvokeResponse {
+    pub results: Vec<BatchItemResult>, // same order as the request payloads
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum BatchItemResult {
+    Success { result: serde_json::Value },
+    Error { error: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct FunctionEnabledResponse {
+    pub namespace: String,
+    pub name: String,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WarmupResponse {
+    pub namespace: String,
+    pub name: String,
+    pub version: u32,
+    pub vm_id: Uuid,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PoolStatsResponse {
+    pub total_vms: usize,
+    pub vms_by_state: HashMap<VmState, usize>,
+    pub waiters: usize, // callers currently blocked in acquire
+    pub checked_out: usize, // invocations holding a VM; capped at max_vms x vm_concurrency
+    pub booting: usize, // VM boots in progress; capped at max_concurrent_boots
+    pub warm_target: usize,
+    pub reserved: usize, // idle VMs held for premium functions
+    pub max_vms: usize,
+    pub acquire_wait: AcquireWaitPercentiles,
+    pub queued_by_function: HashMap<String, usize>, // callers waiting for their function's turn
+    pub saturation: SaturationStats,
+    pub generation: GenerationStatus, // VM config generations, while rolling to a new one
+    pub forecast: DemandForecastStats,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduleListResponse {
+    pub functions: Vec<ScheduledFunction>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduledFunction {
+    pub name: String,
+    pub schedule: String,
+    pub next_run: Option<String>,
+    pub last_run: Option<String>,
+    pub last_success: Option<bool>, // None until the first run finishes
+    pub running: bool,
+}
+
+// Manual override of the warm pool size, e.g. ahead of a scheduled spike
+#[derive(Debug, Deserialize)]
+pub struct ScalePoolRequest {
+    pub target: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScalePoolResponse {
+    pub warm_target: usize,
+    pub max_vms: usize,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FlushPoolQuery {
+    pub force: bool, // cancel invocations on busy VMs instead of waiting
+}
+
+#[derive(Debug, Serialize)]
+pub struct FlushPoolResponse {
+    pub flushed: usize,
+    pub draining: usize, // busy, torn down once their invocations finish
+}
+
+// Busy VMs relative to pool.max_vms
+#[derive(Debug, Serialize)]
+pub struct SaturationStats {
+    pub busy_vms: usize,
+    pub current: f64,
+    pub window_average: f64,
+    pub window_secs: u64,
+    pub sustained: bool, // window average at or above pool.saturation_threshold
+}
+
+// Smoothed recent demand and the warm pool size it calls for
+#[derive(Debug, Serialize)]
+pub struct DemandForecastStats {
+    pub invocation_rate: f64, // per second
+    pub busy_vms: f64, // VM-seconds used per second
+    pub predicted_target: usize, // within min_vms..=max_vms; applied with pool.predictive_warming
+}
+
+#[derive(Debug, Serialize)]
+pub struct AcquireWaitPercentiles {
+    pub samples: usize,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EventsQuery {
+    pub types: Option<String>, // comma-separated event types; all when omitted
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct VmListQuery {
+    pub state: Option<String>, // e.g. "busy"; case-insensitive
+}
+
+// Where a rollout stands: `current` is the oldest generation still running
+// VMs and `target` the one new VMs boot from. They match once the old
+// generations have drained.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct GenerationStatus {
+    pub current: u64,
+    pub target: u64,
 }
 
 #[derive(Debug, Serialize)]
@@ -49,13 +510,425 @@ pub struct VmListResponse {
     pub vms: Option<Vec<VmInfo>>,
 }
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HealthcheckQuery {
+    pub reap: bool, // replace unreachable VMs
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VmHealth {
+    Healthy,
+    Degraded, // answered, but with an error or slowly
+    Unreachable,
+}
+
+// How a VM's V8 host answered a health probe
+#[derive(Debug, Clone, Serialize)]
+pub struct VmProbe {
+    pub health: VmHealth,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>, // absent when it never answered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VmHealthReport {
+    pub vm_id: String,
+    pub state: VmState,
+    #[serde(flatten)]
+    pub probe: VmProbe,
+    pub reaped: bool, // replaced on its next checkout
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthcheckResponse {
+    pub healthy: usize,
+    pub degraded: usize,
+    pub unreachable: usize,
+    pub reaped: usize,
+    pub vms: Vec<VmHealthReport>,
+}
+
 // Core domain types
 #[derive(Debug, Clone, Serialize)]
 pub struct Function {
+    pub namespace: String,
     pub name: String,
-    pub code: String,
+    pub version: u32,
+    pub code: String, // JavaScript executed by the V8 host
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>, // original source when `code` was compiled from it
+    pub code_hash: String, // hex SHA-256 of the code as submitted
     pub runtime: String,
-    pub created_at: String,
+    pub audit_payloads: bool,
+    pub http_response: bool,
+    pub idempotent: bool,
+    pub sign_responses: bool,
+    pub tier: FunctionTier,
+    pub max_priority: Priority,
+    pub debug: bool, // log payloads and results; may expose sensitive data
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub tags: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>, // cron expression
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule_payload: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_payload_bytes: Option<usize>, // invocation body cap below the global one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_schema: Option<InputSchema>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_transform: Option<Transform>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_transform: Option<Transform>,
+    #[serde(flatten)]
+    pub ip_rules: IpRules,
+    pub enabled: bool, // disabled functions keep their versions but can't be invoked
+    pub status: FunctionReadiness, // per version
+    #[serde(flatten)]
+    pub counters: InvocationCounters, // shared by every version of the function
+    #[serde(serialize_with = "rfc3339")]
+    pub created_at: chrono::DateTime<chrono::Utc>, // when the function was first created
+    #[serde(serialize_with = "rfc3339")]
+    pub updated_at: chrono::DateTime<chrono::Utc>, // when this version was stored
+}
+
+impl Function {
+    // `namespace/name`, unique across the store
+    pub fn qualified_name(&self) -> String {
+        format!("{}/{}", self.namespace, self.name)
+    }
+
+    // Identifies the exact code a VM has loaded
+    pub fn affinity_key(&self) -> String {
+        format!("{}@{}", self.qualified_name(), self.version)
+    }
+}
+
+// A bare function for unit tests that don't go through a FunctionStore
+#[cfg(test)]
+impl Function {
+    pub fn fixture(name: &str, code: &str) -> Self {
+        let now = chrono::Utc::now();
+        Self {
+            namespace: "default".to_string(),
+            name: name.to_string(),
+            version: 1,
+            code: code.to_string(),
+            source: None,
+            code_hash: String::new(),
+            runtime: "v8".to_string(),
+            audit_payloads: false,
+            http_response: false,
+            idempotent: false,
+            sign_responses: false,
+            tier: Default::default(),
+            max_priority: Default::default(),
+            debug: false,
+            tags: HashMap::new(),
+            schedule: None,
+            schedule_payload: None,
+            max_payload_bytes: None,
+            input_schema: None,
+            input_transform: None,
+            output_transform: None,
+            ip_rules: Default::default(),
+            enabled: true,
+            status: Default::default(),
+            counters: Default::default(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+// Strong validator for a JSON response: a hash of the body itself, so it
+// changes whenever anything the client would see does
+pub fn body_etag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..16].iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("\"{}\"", hex)
+}
+
+// A function's JSON Schema for its payloads, compiled once when the
+// version is stored. Serialized as the schema itself.
+#[derive(Clone)]
+pub struct InputSchema {
+    schema: serde_json::Value,
+    validator: Arc<jsonschema::Validator>,
+}
+
+impl InputSchema {
+    pub fn compile(schema: serde_json::Value) -> Result<Self, String> {
+        let validator = jsonschema::validator_for(&schema).map_err(|e| e.to_string())?;
+        Ok(Self {
+            schema,
+            validator: Arc::new(validator),
+        })
+    }
+
+    pub fn schema(&self) -> &serde_json::Value {
+        &self.schema
+    }
+
+    // Every way the payload breaks the schema, each prefixed with where
+    pub fn violations(&self, payload: &serde_json::Value) -> Vec<String> {
+        self.validator
+            .iter_errors(payload)
+            .map(|e| match e.instance_path.to_string() {
+                path if path.is_empty() => e.to_string(),
+                path => format!("{}: {}", path, e),
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for InputSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.schema.fmt(f)
+    }
+}
+
+impl Serialize for InputSchema {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.schema.serialize(serializer)
+    }
+}
+
+// Whether arrays and objects in the value nest more than `max` levels deep.
+// Walks without recursion, so it's safe on whatever serde_json accepted.
+pub fn exceeds_json_depth(value: &serde_json::Value, max: usize) -> bool {
+    let mut pending = vec![(value, 0)];
+    while let Some((value, depth)) = pending.pop() {
+        let children: Box<dyn Iterator<Item = &serde_json::Value>> = match value {
+            serde_json::Value::Array(items) => Box::new(items.iter()),
+            serde_json::Value::Object(fields) => Box::new(fields.values()),
+            _ => continue,
+        };
+        if depth + 1 > max {
+            return true;
+        }
+        pending.extend(children.map(|child| (child, depth + 1)));
+    }
+    false
+}
+
+// Largest integer a JavaScript number holds exactly. Payloads go to the V8
+// host as JSON and JS numbers are f64, so anything beyond ±2^53 - 1 (a
+// 64-bit ID, say) would be silently rounded there.
+pub const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+// What happens to payload integers past MAX_SAFE_INTEGER (invoke.large_integers).
+// Only integers serde_json holds as u64 or i64 are caught; a literal too big
+// for those was already parsed as a float and can't be recovered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LargeIntegers {
+    #[default]
+    Pass, // sent as numbers, for the V8 host to round
+    String, // sent as their exact decimal digits
+    Reject, // the invocation fails with a 400
+}
+
+// Apply the policy to every integer in the value. Returns the path of the
+// first unsafe integer when rejecting.
+pub fn handle_large_integers(value: &mut serde_json::Value, policy: LargeIntegers) -> Result<(), String> {
+    if policy == LargeIntegers::Pass {
+        return Ok(());
+    }
+    convert_large_integers(value, policy, &mut "$".to_string())
+}
+
+fn convert_large_integers(
+    value: &mut serde_json::Value,
+    policy: LargeIntegers,
+    path: &mut String,
+) -> Result<(), String> {
+    let len = path.len();
+    match value {
+        serde_json::Value::Number(number) if is_unsafe_integer(number) => match policy {
+            LargeIntegers::Reject => return Err(path.clone()),
+            _ => *value = serde_json::Value::String(number.to_string()),
+        },
+        serde_json::Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                path.push_str(&format!("[{}]", index));
+                convert_large_integers(item, policy, path)?;
+                path.truncate(len);
+            }
+        }
+        serde_json::Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                path.push('.');
+                path.push_str(name);
+                convert_large_integers(field, policy, path)?;
+                path.truncate(len);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn is_unsafe_integer(number: &serde_json::Number) -> bool {
+    match (number.as_u64(), number.as_i64()) {
+        (Some(n), _) => n > MAX_SAFE_INTEGER,
+        (None, Some(n)) => n.unsigned_abs() > MAX_SAFE_INTEGER,
+        _ => false, // floats make the trip unchanged
+    }
+}
+
+// Invocation and error totals for a function. Clones share the same
+// counts, so invocations update them lock-free through whichever version
+// they ran. Serialized as `invocation_count` and `error_count`.
+#[derive(Debug, Clone, Default)]
+pub struct InvocationCounters(Arc<CounterValues>);
+
+#[derive(Debug, Default)]
+struct CounterValues {
+    invocations: AtomicU64,
+    errors: AtomicU64,
+    last_invoked_ms: AtomicI64, // unix millis; 0 until the first invocation
+}
+
+impl InvocationCounters {
+    pub fn record(&self, success: bool) {
+        self.add(1, u64::from(!success));
+        let now = chrono::Utc::now().timestamp_millis();
+        self.0.last_invoked_ms.fetch_max(now, Ordering::Relaxed);
+    }
+
+    // Carry totals over from an imported function
+    pub fn add(&self, invocations: u64, errors: u64) {
+        self.0.invocations.fetch_add(invocations, Ordering::Relaxed);
+        self.0.errors.fetch_add(errors, Ordering::Relaxed);
+    }
+
+    pub fn invocations(&self) -> u64 {
+        self.0.invocations.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.0.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn last_invoked(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        match self.0.last_invoked_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => chrono::DateTime::from_timestamp_millis(ms),
+        }
+    }
+}
+
+impl Serialize for InvocationCounters {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut counters = serializer.serialize_struct("InvocationCounters", 2)?;
+        counters.serialize_field("invocation_count", &self.invocations())?;
+        counters.serialize_field("error_count", &self.errors())?;
+        counters.end()
+    }
+}
+
+// Service class for latency. Premium functions run on VMs reserved for
+// them (pool.premium_reserved_vms) when one is free, so they don't cold
+// start behind standard traffic; standard functions share the pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FunctionTier {
+    #[default]
+    Standard,
+    Premium,
+}
+
+// How soon an invocation gets a VM when the pool is contended. Callers ask
+// with `X-Priority`, capped at the function's `max_priority`, so only
+// functions deployed as interactive can jump the queue. Ordered low to high.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl std::str::FromStr for Priority {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            _ => Err(anyhow::anyhow!("Unknown priority: {} (expected high, normal or low)", s)),
+        }
+    }
+}
+
+// Where a version is in its lifecycle. A version is `Creating` until its
+// code has been primed into a VM; a new function can't be invoked until
+// then. `Error` means priming failed; invocations still go ahead and prime
+// it themselves.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub enum FunctionStatus {
+    Creating,
+    Ready,
+    Error,
+}
+
+// A version's status, shared by every copy of it so a background prime is
+// seen wherever the version was cloned out of the store. Serialized as the
+// status.
+#[derive(Debug, Clone, Default)]
+pub struct FunctionReadiness(Arc<AtomicU8>);
+
+impl FunctionReadiness {
+    const CREATING: u8 = 0;
+    const READY: u8 = 1;
+    const ERROR: u8 = 2;
+
+    pub fn get(&self) -> FunctionStatus {
+        match self.0.load(Ordering::Acquire) {
+            Self::CREATING => FunctionStatus::Creating,
+            Self::READY => FunctionStatus::Ready,
+            _ => FunctionStatus::Error,
+        }
+    }
+
+    pub fn ready(&self) {
+        self.0.store(Self::READY, Ordering::Release);
+    }
+
+    // A failed prime only marks a version that was never ready; one that
+    // has been served from a VM stays ready
+    pub fn fail(&self) {
+        let _ = self
+            .0
+            .compare_exchange(Self::CREATING, Self::ERROR, Ordering::AcqRel, Ordering::Acquire);
+    }
+}
+
+impl Serialize for FunctionReadiness {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.get().serialize(serializer)
+    }
+}
+
+// Matches `to_rfc3339()` (`+00:00` rather than serde's default `Z`) so the
+// wire format is the same as when timestamps were stored as strings
+fn rfc3339<S: serde::Serializer>(time: &chrono::DateTime<chrono::Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time.to_rfc3339())
+}
+
+// Share of invocations routed to a function version
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VersionWeight {
+    pub version: u32,
+    pub weight: u32,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -64,11 +937,16 @@ pub struct VmInfo {
     pub state: VmState,
     pub ip_address: Option<String>,
     pub port: Option<u16>,
+    pub invocation_count: u64,
     pub created_at: String,
     pub last_used: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_reason: Option<String>, // why the VM was marked `Failed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation: Option<u64>, // VM config generation it was booted from
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Hash)]
 pub enum VmState {
     Starting,
     Ready,
@@ -77,14 +955,164 @@ pub enum VmState {
     Failed,
 }
 
+impl std::str::FromStr for VmState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "starting" => Ok(VmState::Starting),
+            "ready" => Ok(VmState::Ready),
+            "busy" => Ok(VmState::Busy),
+            "stopping" => Ok(VmState::Stopping),
+            "failed" => Ok(VmState::Failed),
+            _ => Err(anyhow::anyhow!(
+                "Unknown VM state: {} (expected starting, ready, busy, stopping or failed)",
+                s
+            )),
+        }
+    }
+}
+
+// A caller's time budget for one invocation, covering both VM acquisition
+// and execution. Replaces the configured timeouts for that call.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    started: Instant,
+    budget: Duration,
+}
+
+impl Deadline {
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            started: Instant::now(),
+            budget,
+        }
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.budget.saturating_sub(self.started.elapsed())
+    }
+
+    pub fn exceeded(&self) -> DeadlineExceeded {
+        DeadlineExceeded {
+            budget: self.budget,
+            waited: self.started.elapsed(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invocation deadline of {}ms exceeded after {}ms", budget.as_millis(), waited.as_millis())]
+pub struct DeadlineExceeded {
+    pub budget: Duration,
+    pub waited: Duration,
+}
+
+// What the V8 host measured for one invocation, reported in the
+// `x-peak-memory-bytes` and `x-cpu-time-ms` response headers
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ResourceUsage {
+    pub peak_memory_bytes: u64,
+    pub cpu_time_ms: f64,
+}
+
+impl ResourceUsage {
+    // None when the host didn't report usage (older hosts don't)
+    pub fn from_headers(headers: &reqwest::header::HeaderMap) -> Option<Self> {
+        Some(Self {
+            peak_memory_bytes: host_header(headers, "x-peak-memory-bytes")?,
+            cpu_time_ms: host_header(headers, "x-cpu-time-ms")?,
+        })
+    }
+}
+
+// Where the time went in one V8 host call. Hosts that report
+// `x-load-time-ms` and `x-execution-time-ms` split their side into loading
+// the code and running the handler; for hosts that don't, `execute` is the
+// whole round trip up to the response headers and `load` is unknown.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HostTimings {
+    pub load: Option<Duration>,
+    pub execute: Duration,
+    pub response_read: Duration, // receiving and parsing the result
+}
+
+impl HostTimings {
+    fn from_headers(headers: &reqwest::header::HeaderMap, round_trip: Duration) -> Self {
+        let millis = |name| {
+            host_header::<f64>(headers, name).and_then(|ms| Duration::try_from_secs_f64(ms / 1000.0).ok())
+        };
+        match (millis("x-load-time-ms"), millis("x-execution-time-ms")) {
+            (load, Some(execute)) => Self {
+                load,
+                execute,
+                response_read: Duration::ZERO,
+            },
+            _ => Self {
+                execute: round_trip,
+                ..Self::default()
+            },
+        }
+    }
+}
+
+fn host_header<T: std::str::FromStr>(headers: &reqwest::header::HeaderMap, name: &str) -> Option<T> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
 // VM configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
 pub struct VmConfig {
     pub vcpu_count: u8,
     pub mem_size_mib: u32,
     pub kernel_path: String,
     pub rootfs_path: String,
     pub v8_host_path: String,
+    pub work_dir_base: String, // each VM gets a directory named after its id
+    // V8 host ports handed to VMs, inclusive
+    pub port_range_start: u16,
+    pub port_range_end: u16,
+    // Keep-alive connections held open to each VM's V8 host
+    pub v8_host_max_idle_connections: usize,
+    pub v8_host_idle_timeout_secs: u64,
+}
+
+impl VmConfig {
+    pub fn port_range(&self) -> std::ops::RangeInclusive<u16> {
+        self.port_range_start..=self.port_range_end
+    }
+
+    // This config with `other`'s boot settings: the image, sizing and port
+    // range each VM is booted with. A reload that changes only those rolls
+    // the pool to a new generation; anything else needs a restart.
+    pub fn with_boot_settings(&self, other: &VmConfig) -> VmConfig {
+        VmConfig {
+            vcpu_count: other.vcpu_count,
+            mem_size_mib: other.mem_size_mib,
+            kernel_path: other.kernel_path.clone(),
+            rootfs_path: other.rootfs_path.clone(),
+            v8_host_path: other.v8_host_path.clone(),
+            port_range_start: other.port_range_start,
+            port_range_end: other.port_range_end,
+            ..self.clone()
+        }
+    }
+
+    pub fn ports_overlap(&self, other: &VmConfig) -> bool {
+        self.port_range_start <= other.port_range_end && other.port_range_start <= self.port_range_end
+    }
+
+    // One client for every V8 host call, so connections to a VM are reused
+    // across invocations instead of set up each time. Timeouts are per
+    // request.
+    pub fn v8_host_client(&self) -> reqwest::Result<reqwest::Client> {
+        reqwest::Client::builder()
+            .pool_max_idle_per_host(self.v8_host_max_idle_connections)
+            .pool_idle_timeout(std::time::Duration::from_secs(self.v8_host_idle_timeout_secs))
+            .tcp_nodelay(true)
+            .build()
+    }
 }
 
 impl Default for VmConfig {
@@ -95,12 +1123,17 @@ impl Default for VmConfig {
             kernel_path: "/opt/firecracker/vmlinux.bin".to_string(),
             rootfs_path: "/opt/firecracker/rootfs.ext4".to_string(),
             v8_host_path: "/opt/firecracker/v8-host".to_string(),
+            work_dir_base: "/tmp/hyperdrive-rust-vms".to_string(),
+            port_range_start: 8100,
+            port_range_end: 8999,
+            v8_host_max_idle_connections: 8,
+            v8_host_idle_timeout_secs: 90,
         }
     }
 }
 
 // VM execution context
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct VmInstance {
     pub id: Uuid,
     pub state: VmState,
@@ -108,8 +1141,12 @@ pub struct VmInstance {
     pub port: Option<u16>,
     pub process_id: Option<u32>,
     pub work_dir: String,
+    pub loaded_function: Option<String>, // affinity key of the code in the V8 host
+    pub invocation_count: u64,
+    pub checkout_invocations: u64, // invocation_count when this slot was checked out
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub last_used: chrono::DateTime<chrono::Utc>,
+    pub generation: Option<u64>, // set when checked out of a generation's pool
 }
 
 impl VmInstance {
@@ -122,60 +1159,315 @@ impl VmInstance {
             port: None,
             process_id: None,
             work_dir,
+            loaded_function: None,
+            invocation_count: 0,
+            checkout_invocations: 0,
             created_at: now,
             last_used: now,
+            generation: None,
         }
     }
 
     pub async fn execute_function(
         &mut self,
+        client: &reqwest::Client,
         function: &Function,
         payload: serde_json::Value,
-    ) -> anyhow::Result<serde_json::Value> {
+        timeout: std::time::Duration,
+        max_response_bytes: usize,
+    ) -> anyhow::Result<(serde_json::Value, Option<ResourceUsage>, HostTimings)> {
         self.last_used = chrono::Utc::now();
         self.state = VmState::Busy;
+        self.invocation_count += 1;
 
         // Execute function via HTTP call to V8 host in VM
-        let result = self.call_v8_host(function, payload).await?;
+        let output = self
+            .call_v8_host(client, function, payload, timeout, max_response_bytes)
+            .await?;
         
+        self.loaded_function = Some(function.affinity_key());
         self.state = VmState::Ready;
-        Ok(result)
+        Ok(output)
     }
 
-    async fn call_v8_host(
-        &self,
+    // Load the function's code into the V8 host without running the handler
+    pub async fn prime_function(
+        &mut self,
+        client: &reqwest::Client,
         function: &Function,
-        payload: serde_json::Value,
-    ) -> anyhow::Result<serde_json::Value> {
-        let ip = self.ip_address.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("VM has no IP address"))?;
-        let port = self.port
-            .ok_or_else(|| anyhow::anyhow!("VM has no port"))?;
+        timeout: std::time::Duration,
+    ) -> anyhow::Result<()> {
+        self.last_used = chrono::Utc::now();
 
-        let url = format!("http://{}:{}/execute", ip, port);
-        
         let request_body = serde_json::json!({
             "code": function.code,
-            "payload": payload
         });
 
-        let client = reqwest::Client::new();
         let response = client
-            .post(&url)
+            .post(self.v8_host_url("prime")?)
             .json(&request_body)
-            .timeout(std::time::Duration::from_secs(30))
+            .timeout(timeout)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Function priming failed: {}", response.status()));
+        }
+
+        self.loaded_function = Some(function.affinity_key());
+        Ok(())
+    }
+
+    // Run the function with a raw body streamed through to the V8 host, which
+    // must already have the function loaded. The response is returned as
+    // soon as its headers arrive so the body can be streamed back too.
+    pub async fn execute_stream(
+        &mut self,
+        client: &reqwest::Client,
+        function: &Function,
+        content_type: &str,
+        body: reqwest::Body,
+        timeout: std::time::Duration,
+    ) -> anyhow::Result<reqwest::Response> {
+        self.last_used = chrono::Utc::now();
+        self.state = VmState::Busy;
+        self.invocation_count += 1;
+
+        let response = client
+            .post(self.v8_host_url("stream")?)
+            .header("x-function-id", function.affinity_key())
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(body)
+            .timeout(timeout)
             .send()
             .await?;
 
-        if response.status().is_success() {
-            let result: serde_json::Value = response.json().await?;
-            Ok(result)
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Function execution failed: {}", response.status()));
+        }
+
+        self.state = VmState::Ready;
+        Ok(response)
+    }
+
+    // Quick liveness check against the V8 host
+    pub async fn ping(&self, client: &reqwest::Client, timeout: std::time::Duration) -> bool {
+        ping_v8_host(client, self.ip_address.as_deref(), self.port, timeout).await
+    }
+
+    pub fn info(&self) -> VmInfo {
+        VmInfo {
+            id: self.id.to_string(),
+            state: self.state.clone(),
+            ip_address: self.ip_address.clone(),
+            port: self.port,
+            invocation_count: self.invocation_count,
+            created_at: self.created_at.to_rfc3339(),
+            last_used: self.last_used.to_rfc3339(),
+            failure_reason: None,
+            generation: self.generation,
+        }
+    }
+
+    fn v8_host_url(&self, path: &str) -> anyhow::Result<String> {
+        v8_host_url(self.ip_address.as_deref(), self.port, path)
+    }
+
+    async fn call_v8_host(
+        &self,
+        client: &reqwest::Client,
+        function: &Function,
+        payload: serde_json::Value,
+        timeout: std::time::Duration,
+        max_response_bytes: usize,
+    ) -> anyhow::Result<(serde_json::Value, Option<ResourceUsage>, HostTimings)> {
+        let url = self.v8_host_url("execute")?;
+        
+        let request_body = serde_json::json!({
+            "code": function.code,
+            "payload": payload,
+            "response_mode": if function.http_response { "http" } else { "json" },
+        });
+
+        let sent = Instant::now();
+        let mut response = send_to_v8_host(client.post(&url).json(&request_body), timeout).await?;
+        let answered = Instant::now();
+
+        if !response.status().is_success() {
+            // Only the start is quoted, so don't buffer an error page whole
+            let status = response.status();
+            let mut body = Vec::new();
+            while body.len() <= BODY_SNIPPET_BYTES {
+                match response.chunk().await {
+                    Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+                    _ => break,
+                }
+            }
+            return Err(HyperdriveError::FunctionExecutionFailed(format!(
+                "V8 host returned {}: {}",
+                status,
+                body_snippet(&body)
+            ))
+            .into());
+        }
+
+        let usage = ResourceUsage::from_headers(response.headers());
+        let mut timings = HostTimings::from_headers(response.headers(), answered - sent);
+
+        // Read with a cap rather than `.json()` so a function returning a
+        // huge result can't exhaust our memory
+        let too_large = HyperdriveError::ResponseTooLarge(max_response_bytes);
+        if response.content_length().is_some_and(|len| len > max_response_bytes as u64) {
+            return Err(too_large.into());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > max_response_bytes {
+                return Err(too_large.into());
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        // A crashed or misbehaving host may answer with an HTML error page
+        // or nothing at all; show what it sent rather than a bare parse error
+        let result: serde_json::Value = serde_json::from_slice(&body).map_err(|e| {
+            HyperdriveError::FunctionExecutionFailed(format!(
+                "V8 host returned invalid JSON ({}): {}",
+                e,
+                body_snippet(&body)
+            ))
+        })?;
+        timings.response_read = answered.elapsed();
+        Ok((result, usage, timings))
+    }
+}
+
+impl VmInfo {
+    // Liveness check for a VM that isn't checked out. Going through the
+    // shared client also keeps its pooled connection to the VM fresh.
+    pub async fn ping(&self, client: &reqwest::Client, timeout: std::time::Duration) -> bool {
+        ping_v8_host(client, self.ip_address.as_deref(), self.port, timeout).await
+    }
+
+    // Health check that tells a slow or failing host apart from one that
+    // doesn't answer at all. Answers slower than `slow` count as degraded.
+    pub async fn probe(&self, client: &reqwest::Client, timeout: Duration, slow: Duration) -> VmProbe {
+        let unreachable = |detail: String| VmProbe {
+            health: VmHealth::Unreachable,
+            latency_ms: None,
+            detail: Some(detail),
+        };
+        let url = match v8_host_url(self.ip_address.as_deref(), self.port, "health") {
+            Ok(url) => url,
+            Err(e) => return unreachable(e.to_string()),
+        };
+
+        let started = Instant::now();
+        let response = match client.get(url).timeout(timeout).send().await {
+            Ok(response) => response,
+            Err(e) => return unreachable(e.to_string()),
+        };
+        let latency = started.elapsed();
+
+        let (health, detail) = if !response.status().is_success() {
+            (VmHealth::Degraded, Some(format!("V8 host returned {}", response.status())))
+        } else if latency > slow {
+            (VmHealth::Degraded, Some(format!("Answered in {:?}, above {:?}", latency, slow)))
         } else {
-            Err(anyhow::anyhow!("Function execution failed: {}", response.status()))
+            (VmHealth::Healthy, None)
+        };
+        VmProbe {
+            health,
+            latency_ms: Some(latency.as_millis() as u64),
+            detail,
         }
     }
 }
 
+fn v8_host_url(ip: Option<&str>, port: Option<u16>, path: &str) -> anyhow::Result<String> {
+    let ip = ip.ok_or_else(|| anyhow::anyhow!("VM has no IP address"))?;
+    let port = port.ok_or_else(|| anyhow::anyhow!("VM has no port"))?;
+
+    Ok(format!("http://{}:{}/{}", ip, port, path))
+}
+
+async fn ping_v8_host(
+    client: &reqwest::Client,
+    ip: Option<&str>,
+    port: Option<u16>,
+    timeout: std::time::Duration,
+) -> bool {
+    let Ok(url) = v8_host_url(ip, port, "health") else {
+        return false;
+    };
+
+    match client.get(url).timeout(timeout).send().await {
+        Ok(response) => response.status().is_success(),
+        Err(_) => false,
+    }
+}
+
+// Attempts at reaching a V8 host that refuses the connection, and the wait
+// before the first retry; it doubles after each one
+const V8_CONNECT_ATTEMPTS: u32 = 4;
+const V8_CONNECT_BACKOFF: Duration = Duration::from_millis(25);
+
+// Send a request to a V8 host within `timeout`. A freshly booted VM can be
+// handed out a moment before its host is listening, so a refused connection
+// is retried briefly; every other error fails straight away.
+async fn send_to_v8_host(request: reqwest::RequestBuilder, timeout: Duration) -> reqwest::Result<reqwest::Response> {
+    let deadline = Instant::now() + timeout;
+    let mut backoff = V8_CONNECT_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let Some(retry) = request.try_clone() else {
+            return request.timeout(timeout).send().await;
+        };
+        match retry.timeout(deadline.saturating_duration_since(Instant::now())).send().await {
+            Err(e)
+                if is_connection_refused(&e)
+                    && attempt < V8_CONNECT_ATTEMPTS
+                    && Instant::now() + backoff < deadline =>
+            {
+                tracing::debug!("V8 host refused connection (attempt {}); retrying in {:?}", attempt, backoff);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            outcome => return outcome,
+        }
+    }
+}
+
+// Nothing was listening, as opposed to a timeout or a dropped connection
+fn is_connection_refused(e: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(e);
+    while let Some(cause) = source {
+        if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+            return io.kind() == std::io::ErrorKind::ConnectionRefused;
+        }
+        source = cause.source();
+    }
+    false
+}
+
+// Bytes of a V8 host response quoted in errors
+const BODY_SNIPPET_BYTES: usize = 256;
+
+// Start of a response body for error messages, lossily decoded
+fn body_snippet(body: &[u8]) -> String {
+    if body.is_empty() {
+        return "<empty body>".to_string();
+    }
+    let snippet = String::from_utf8_lossy(&body[..body.len().min(BODY_SNIPPET_BYTES)]);
+    let snippet = snippet.trim();
+    if body.len() > BODY_SNIPPET_BYTES {
+        format!("{}...", snippet)
+    } else {
+        snippet.to_string()
+    }
+}
+
 // Error types
 #[derive(Debug, thiserror::Error)]
 pub enum HyperdriveError {
@@ -191,9 +1483,248 @@ pub enum HyperdriveError {
     #[error("Pool exhausted")]
     PoolExhausted,
     
+    #[error("VM {0} was flushed from the pool mid-invocation")]
+    VmFlushed(Uuid),
+    
+    #[error("Function response exceeds {0} bytes")]
+    ResponseTooLarge(usize),
+
+    #[error("No VMs could be booted: {0}")]
+    NoBootableVms(String),
+    
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     
     #[error("Network error: {0}")]
     Network(#[from] reqwest::Error),
-}
\ No newline at end of file
+}
+
+// Error returned by HTTP handlers: a status, with a JSON body when there's
+// something useful to tell the client
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub body: Option<ErrorResponse>,
+    pub retry_after_secs: Option<u64>, // sent as Retry-After
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: Some(ErrorResponse::new(message)),
+            retry_after_secs: None,
+        }
+    }
+
+    pub fn with_body(status: StatusCode, body: ErrorResponse) -> Self {
+        Self {
+            status,
+            body: Some(body),
+            retry_after_secs: None,
+        }
+    }
+
+    pub fn retry_after(mut self, secs: u64) -> Self {
+        self.retry_after_secs = Some(secs);
+        self
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        Self {
+            status,
+            body: None,
+            retry_after_secs: None,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = match self.body {
+            Some(body) => (self.status, Json(body)).into_response(),
+            None => self.status.into_response(),
+        };
+        if let Some(secs) = self.retry_after_secs {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, header::HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_send_to_v8_host_retries_refused() {
+        let client = reqwest::Client::new();
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let url = format!("http://127.0.0.1:{}/execute", port);
+
+        // Nothing listening: every attempt is refused
+        let started = Instant::now();
+        let err = send_to_v8_host(client.post(&url), Duration::from_secs(5)).await.unwrap_err();
+        assert!(is_connection_refused(&err));
+        assert!(started.elapsed() >= V8_CONNECT_BACKOFF * 7);
+
+        // The host comes up between attempts
+        let host = tokio::spawn(async move {
+            tokio::time::sleep(V8_CONNECT_BACKOFF).await;
+            let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = tokio::io::AsyncReadExt::read(&mut stream, &mut request).await;
+            tokio::io::AsyncWriteExt::write_all(&mut stream, b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+        });
+        let response = send_to_v8_host(client.post(&url), Duration::from_secs(5)).await.unwrap();
+        assert!(response.status().is_success());
+        host.await.unwrap();
+    }
+
+    #[test]
+    fn test_host_timings() {
+        let round_trip = Duration::from_millis(40);
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(
+            HostTimings::from_headers(&headers, round_trip),
+            HostTimings {
+                load: None,
+                execute: round_trip,
+                response_read: Duration::ZERO,
+            }
+        );
+
+        headers.insert("x-load-time-ms", "2.5".parse().unwrap());
+        headers.insert("x-execution-time-ms", "30".parse().unwrap());
+        let timings = HostTimings::from_headers(&headers, round_trip);
+        assert_eq!(timings.load, Some(Duration::from_micros(2500)));
+        assert_eq!(timings.execute, Duration::from_millis(30));
+
+        // Nonsense from the host falls back to the round trip
+        headers.insert("x-execution-time-ms", "-1".parse().unwrap());
+        assert_eq!(HostTimings::from_headers(&headers, round_trip).execute, round_trip);
+    }
+
+    // Answers one request on a local port with the given status line
+    async fn one_shot_host(status: &'static str) -> u16 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = tokio::io::AsyncReadExt::read(&mut stream, &mut request).await;
+            let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\n\r\n", status);
+            let _ = tokio::io::AsyncWriteExt::write_all(&mut stream, response.as_bytes()).await;
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_probe_vm() {
+        let client = reqwest::Client::new();
+        let vm = |port| VmInfo {
+            ip_address: Some("127.0.0.1".to_string()),
+            port: Some(port),
+            ..VmInstance::new("/tmp".to_string()).info()
+        };
+        let (timeout, slow) = (Duration::from_secs(2), Duration::from_secs(1));
+
+        let healthy = vm(one_shot_host("200 OK").await).probe(&client, timeout, slow).await;
+        assert_eq!(healthy.health, VmHealth::Healthy);
+        assert!(healthy.latency_ms.is_some());
+
+        let failing = vm(one_shot_host("500 Internal Server Error").await).probe(&client, timeout, slow).await;
+        assert_eq!(failing.health, VmHealth::Degraded);
+
+        let slow_host = vm(one_shot_host("200 OK").await).probe(&client, timeout, Duration::ZERO).await;
+        assert_eq!(slow_host.health, VmHealth::Degraded);
+
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let down = vm(closed).probe(&client, timeout, slow).await;
+        assert_eq!(down.health, VmHealth::Unreachable);
+        assert!(down.latency_ms.is_none());
+    }
+
+    #[test]
+    fn test_function_readiness() {
+        let status = FunctionReadiness::default();
+        let copy = status.clone();
+        assert_eq!(status.get(), FunctionStatus::Creating);
+
+        status.fail();
+        assert_eq!(copy.get(), FunctionStatus::Error);
+        copy.ready();
+        assert_eq!(status.get(), FunctionStatus::Ready);
+
+        // A later failed prime doesn't undo readiness
+        status.fail();
+        assert_eq!(status.get(), FunctionStatus::Ready);
+        assert_eq!(serde_json::to_value(&status).unwrap(), "Ready");
+    }
+
+    #[test]
+    fn test_body_snippet() {
+        assert_eq!(body_snippet(b""), "<empty body>");
+        assert_eq!(body_snippet(b"<h1>502 Bad Gateway</h1>\n"), "<h1>502 Bad Gateway</h1>");
+
+        let long = "x".repeat(1000);
+        let snippet = body_snippet(long.as_bytes());
+        assert!(snippet.starts_with(&"x".repeat(BODY_SNIPPET_BYTES)));
+        assert_eq!(snippet.len(), BODY_SNIPPET_BYTES + 3);
+    }
+
+    #[test]
+    fn test_parse_vm_state() {
+        assert_eq!("busy".parse::<VmState>().unwrap(), VmState::Busy);
+        assert_eq!("Ready".parse::<VmState>().unwrap(), VmState::Ready);
+        assert!("stuck".parse::<VmState>().is_err());
+    }
+
+    #[test]
+    fn test_exceeds_json_depth() {
+        let value = serde_json::json!({"a": [1, {"b": "c"}], "d": 2});
+        assert!(!exceeds_json_depth(&value, 3));
+        assert!(exceeds_json_depth(&value, 2));
+        assert!(!exceeds_json_depth(&serde_json::json!("scalar"), 0));
+
+        let nested = (0..100).fold(serde_json::json!(1), |inner, _| serde_json::json!([inner]));
+        assert!(!exceeds_json_depth(&nested, 100));
+        assert!(exceeds_json_depth(&nested, 99));
+    }
+
+    #[test]
+    fn test_handle_large_integers() {
+        let payload = serde_json::json!({
+            "id": 9007199254740993u64,
+            "items": [{"n": -9007199254740993i64}, 9007199254740991u64],
+            "ratio": 1.5e300,
+        });
+
+        let mut converted = payload.clone();
+        handle_large_integers(&mut converted, LargeIntegers::String).unwrap();
+        assert_eq!(
+            converted,
+            serde_json::json!({
+                "id": "9007199254740993",
+                "items": [{"n": "-9007199254740993"}, 9007199254740991u64],
+                "ratio": 1.5e300,
+            })
+        );
+
+        let mut rejected = payload.clone();
+        assert_eq!(handle_large_integers(&mut rejected, LargeIntegers::Reject).unwrap_err(), "$.id");
+        let mut nested = serde_json::json!({"items": [1, {"n": u64::MAX}]});
+        assert_eq!(handle_large_integers(&mut nested, LargeIntegers::Reject).unwrap_err(), "$.items[1].n");
+
+        let mut passed = payload.clone();
+        handle_large_integers(&mut passed, LargeIntegers::Pass).unwrap();
+        assert_eq!(passed, payload);
+    }
+}
@@ -0,0 +1,79 @@
+use swc_core::common::{sync::Lrc, FileName, Globals, Mark, SourceMap, Spanned, GLOBALS};
+use swc_core::ecma::codegen::{text_writer::JsWriter, Config as CodegenConfig, Emitter};
+use swc_core::ecma::parser::{lexer::Lexer, Parser, StringInput, Syntax, TsConfig};
+use swc_core::ecma::transforms::base::{fixer::fixer, hygiene::hygiene, resolver};
+use swc_core::ecma::transforms::typescript::strip;
+use swc_core::ecma::visit::FoldWith;
+
+#[derive(Debug, thiserror::Error)]
+#[error("TypeScript error at {line}:{column}: {message}")]
+pub struct TranspileError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize, // 1-based
+}
+
+// Strip types from a TypeScript module, producing plain JavaScript for the
+// V8 host. Only type syntax is removed; the module shape is left as written.
+pub fn transpile(source: &str) -> Result<String, TranspileError> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Custom("function.ts".into()), source.into());
+
+    let syntax_error = |span: swc_core::common::Span, message: String| {
+        let loc = cm.lookup_char_pos(span.lo);
+        TranspileError {
+            message,
+            line: loc.line,
+            column: loc.col_display + 1,
+        }
+    };
+
+    let lexer = Lexer::new(
+        Syntax::Typescript(TsConfig::default()),
+        Default::default(),
+        StringInput::from(&*fm),
+        None,
+    );
+    let mut parser = Parser::new_from(lexer);
+
+    let module = parser
+        .parse_module()
+        .map_err(|e| syntax_error(e.span(), e.kind().msg().to_string()))?;
+
+    // The parser recovers from some errors; treat them as fatal all the same
+    if let Some(e) = parser.take_errors().into_iter().next() {
+        return Err(syntax_error(e.span(), e.kind().msg().to_string()));
+    }
+
+    GLOBALS.set(&Globals::default(), || {
+        let unresolved_mark = Mark::new();
+        let top_level_mark = Mark::new();
+
+        let module = module
+            .fold_with(&mut resolver(unresolved_mark, top_level_mark, true))
+            .fold_with(&mut strip(unresolved_mark, top_level_mark))
+            .fold_with(&mut hygiene())
+            .fold_with(&mut fixer(None));
+
+        let mut output = Vec::new();
+        {
+            let mut emitter = Emitter {
+                cfg: CodegenConfig::default(),
+                cm: cm.clone(),
+                comments: None,
+                wr: JsWriter::new(cm.clone(), "\n", &mut output, None),
+            };
+            emitter.emit_module(&module).map_err(|e| TranspileError {
+                message: format!("Failed to emit JavaScript: {}", e),
+                line: 0,
+                column: 0,
+            })?;
+        }
+
+        String::from_utf8(output).map_err(|e| TranspileError {
+            message: format!("Emitted JavaScript is not UTF-8: {}", e),
+            line: 0,
+            column: 0,
+        })
+    })
+}
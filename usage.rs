@@ -0,0 +1,75 @@
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::types::ResourceUsage;
+
+#[derive(Debug, Default)]
+struct UsageTotals {
+    samples: u64,
+    peak_memory_bytes_sum: u64,
+    peak_memory_bytes_max: u64,
+    cpu_time_ms_sum: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageSummary {
+    pub samples: u64,
+    pub avg_peak_memory_bytes: u64,
+    pub max_peak_memory_bytes: u64,
+    pub avg_cpu_time_ms: f64,
+}
+
+// Resource usage reported by the V8 host, accumulated per function so
+// operators can right-size `vm.mem_size_mib`
+pub struct UsageStats {
+    functions: Mutex<HashMap<String, UsageTotals>>,
+}
+
+impl UsageStats {
+    pub fn new() -> Self {
+        Self {
+            functions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, function: &str, usage: ResourceUsage) {
+        let mut functions = self.functions.lock();
+        let totals = functions.entry(function.to_string()).or_default();
+        totals.samples += 1;
+        totals.peak_memory_bytes_sum += usage.peak_memory_bytes;
+        totals.peak_memory_bytes_max = totals.peak_memory_bytes_max.max(usage.peak_memory_bytes);
+        totals.cpu_time_ms_sum += usage.cpu_time_ms;
+    }
+
+    pub fn summary(&self, function: &str) -> Option<UsageSummary> {
+        let functions = self.functions.lock();
+        let totals = functions.get(function)?;
+        Some(UsageSummary {
+            samples: totals.samples,
+            avg_peak_memory_bytes: totals.peak_memory_bytes_sum / totals.samples,
+            max_peak_memory_bytes: totals.peak_memory_bytes_max,
+            avg_cpu_time_ms: totals.cpu_time_ms_sum / totals.samples as f64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_averages() {
+        let stats = UsageStats::new();
+        assert!(stats.summary("default/f").is_none());
+
+        stats.record("default/f", ResourceUsage { peak_memory_bytes: 100, cpu_time_ms: 2.0 });
+        stats.record("default/f", ResourceUsage { peak_memory_bytes: 300, cpu_time_ms: 4.0 });
+
+        let summary = stats.summary("default/f").unwrap();
+        assert_eq!(summary.samples, 2);
+        assert_eq!(summary.avg_peak_memory_bytes, 200);
+        assert_eq!(summary.max_peak_memory_bytes, 300);
+        assert_eq!(summary.avg_cpu_time_ms, 3.0);
+    }
+}
@@ -0,0 +1,481 @@
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet};
+use std::net::Ipv4Addr;
+use std::ops::RangeInclusive;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::process::{Child, Command};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::types::{VmConfig, VmInfo, VmInstance, VmState};
+
+// Looked up on PATH
+const FIRECRACKER_BIN: &str = "firecracker";
+// From launching Firecracker to the guest's V8 host answering a ping
+const BOOT_TIMEOUT: Duration = Duration::from_secs(10);
+const BOOT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const BOOT_PING_TIMEOUT: Duration = Duration::from_millis(200);
+// Guest /30s are carved out of 172.16.0.0/12, one per V8 host port
+const GUEST_SUBNET_BASE: Ipv4Addr = Ipv4Addr::new(172, 16, 0, 0);
+
+// Hands out V8 host ports from the configured range. The check and the
+// claim happen under one lock, so VMs booting concurrently can never be
+// given the same port; two VMs sharing one would silently send invocations
+// to the wrong guest.
+pub struct PortAllocator {
+    range: RangeInclusive<u16>,
+    state: Mutex<PortState>,
+}
+
+struct PortState {
+    in_use: HashSet<u16>,
+    next: u16, // where the next search starts
+}
+
+impl PortAllocator {
+    pub fn new(range: RangeInclusive<u16>) -> Self {
+        let next = *range.start();
+        Self {
+            range,
+            state: Mutex::new(PortState {
+                in_use: HashSet::new(),
+                next,
+            }),
+        }
+    }
+
+    // Claim a free port. Searching round-robin from the last allocation
+    // keeps a just-released port out of use as long as possible, so stale
+    // connections to a dead VM can't reach its replacement.
+    pub fn allocate(&self) -> Result<u16> {
+        let mut state = self.state.lock();
+        let (start, end) = (*self.range.start(), *self.range.end());
+        let size = usize::from(end - start) + 1;
+
+        let mut port = state.next;
+        for _ in 0..size {
+            let candidate = port;
+            port = if port == end { start } else { port + 1 };
+
+            if state.in_use.insert(candidate) {
+                state.next = port;
+                return Ok(candidate);
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "No free V8 host ports in {}-{} ({} in use)",
+            start,
+            end,
+            state.in_use.len()
+        ))
+    }
+
+    pub fn release(&self, port: u16) {
+        self.state.lock().in_use.remove(&port);
+    }
+
+    #[cfg(test)]
+    fn in_use(&self) -> usize {
+        self.state.lock().in_use.len()
+    }
+}
+
+// Per-VM scratch directories under vm.work_dir_base, each named after its
+// VM's id. They hold the Firecracker socket and config, so only we can
+// read them.
+pub struct WorkDirs {
+    base: PathBuf,
+}
+
+impl WorkDirs {
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        Self { base: base.into() }
+    }
+
+    pub fn create(&self, id: Uuid) -> Result<String> {
+        let dir = self.base.join(id.to_string());
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&dir)
+            .with_context(|| format!("Failed to create VM work dir {}", dir.display()))?;
+        Ok(dir.to_string_lossy().into_owned())
+    }
+
+    // Delete a torn-down VM's directory. Anything outside the base is left
+    // alone, whatever the VM claims its work dir is.
+    pub async fn remove(&self, work_dir: &str) {
+        let dir = Path::new(work_dir);
+        if dir.parent() != Some(self.base.as_path()) {
+            warn!("Not removing VM work dir {} outside {}", work_dir, self.base.display());
+            return;
+        }
+        match tokio::fs::remove_dir_all(dir).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove VM work dir {}: {}", work_dir, e),
+        }
+    }
+
+    // Delete the directories of VMs that aren't running, such as those left
+    // by a crash. Run at startup, before any VM boots, and after shutdown.
+    // Only VM-id-named directories are touched, so a shared base isn't
+    // emptied. Returns how many were removed.
+    pub fn reap_orphans(&self) -> Result<usize> {
+        let entries = match std::fs::read_dir(&self.base) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", self.base.display())),
+        };
+
+        let mut reaped = 0;
+        for entry in entries {
+            let entry = entry?;
+            let is_vm_dir = entry.file_name().to_str().is_some_and(|name| name.parse::<Uuid>().is_ok());
+            if !is_vm_dir || !entry.file_type()?.is_dir() {
+                continue;
+            }
+            match std::fs::remove_dir_all(entry.path()) {
+                Ok(()) => reaped += 1,
+                Err(e) => warn!("Failed to remove stale VM work dir {}: {}", entry.path().display(), e),
+            }
+        }
+        Ok(reaped)
+    }
+}
+
+// Boots, tracks and tears down the Firecracker VMs of one config
+// generation. Each VM gets a V8 host port from the configured range, a tap
+// device and /30 derived from that port, and a work dir holding its
+// Firecracker socket, config and log.
+pub struct VmManager {
+    config: VmConfig,
+    ports: PortAllocator,
+    work_dirs: WorkDirs,
+    client: reqwest::Client, // readiness pings during boot
+    vms: Mutex<HashMap<Uuid, RunningVm>>,
+}
+
+struct RunningVm {
+    info: VmInfo,
+    child: Option<Child>,
+}
+
+// A VM that never became ready. Whatever it was given has been released.
+#[derive(Debug, thiserror::Error)]
+#[error("VM {vm_id} failed to boot: {error:#}")]
+pub struct BootFailure {
+    pub vm_id: Uuid,
+    pub error: anyhow::Error,
+}
+
+impl VmManager {
+    pub async fn new(config: VmConfig) -> Result<Self> {
+        Ok(Self {
+            ports: PortAllocator::new(config.port_range()),
+            work_dirs: WorkDirs::new(&config.work_dir_base),
+            client: config.v8_host_client().context("Failed to build V8 host client")?,
+            vms: Mutex::new(HashMap::new()),
+            config,
+        })
+    }
+
+    // Launch a VM and wait for its V8 host to come up
+    pub async fn boot(&self) -> Result<VmInstance, BootFailure> {
+        let mut vm = VmInstance::new(String::new());
+        match self.launch(&mut vm).await {
+            Ok(child) => {
+                info!("Booted VM {} in {}", vm.id, vm.work_dir);
+                let running = RunningVm {
+                    info: vm.info(),
+                    child: Some(child),
+                };
+                self.vms.lock().insert(vm.id, running);
+                Ok(vm)
+            }
+            Err(error) => {
+                // Dropping the child killed Firecracker, if it got that far
+                self.release(&vm).await;
+                Err(BootFailure { vm_id: vm.id, error })
+            }
+        }
+    }
+
+    async fn launch(&self, vm: &mut VmInstance) -> Result<Child> {
+        let images = [("Kernel image", &self.config.kernel_path), ("Root filesystem", &self.config.rootfs_path)];
+        for (image, path) in images {
+            if tokio::fs::metadata(path).await.is_err() {
+                return Err(anyhow::anyhow!("{} {} not found", image, path));
+            }
+        }
+
+        vm.work_dir = self.work_dirs.create(vm.id)?;
+        let work_dir = PathBuf::from(&vm.work_dir);
+
+        let port = self.ports.allocate()?;
+        vm.port = Some(port);
+        let network = GuestNetwork::for_port(port);
+        network.create().await?;
+        vm.ip_address = Some(network.guest_ip.to_string());
+
+        let config_path = work_dir.join("vm-config.json");
+        let firecracker_config = serde_json::to_vec_pretty(&self.firecracker_config(port, &network))?;
+        tokio::fs::write(&config_path, firecracker_config)
+            .await
+            .context("Failed to write Firecracker config")?;
+        let log = std::fs::File::create(work_dir.join("firecracker.log"))
+            .context("Failed to create Firecracker log")?;
+        let mut child = Command::new(FIRECRACKER_BIN)
+            .arg("--api-sock")
+            .arg(work_dir.join("firecracker.sock"))
+            .arg("--config-file")
+            .arg(&config_path)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to start Firecracker")?;
+        vm.process_id = child.id();
+
+        let deadline = Instant::now() + BOOT_TIMEOUT;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Err(anyhow::anyhow!(
+                    "Firecracker exited during boot ({}); see {}/firecracker.log",
+                    status,
+                    vm.work_dir
+                ));
+            }
+            if vm.ping(&self.client, BOOT_PING_TIMEOUT).await {
+                break;
+            }
+            if Instant::now() >= deadline {
+                return Err(anyhow::anyhow!("V8 host didn't come up within {:?}", BOOT_TIMEOUT));
+            }
+            tokio::time::sleep(BOOT_POLL_INTERVAL).await;
+        }
+
+        vm.state = VmState::Ready;
+        Ok(child)
+    }
+
+    fn firecracker_config(&self, port: u16, network: &GuestNetwork) -> serde_json::Value {
+        let boot_args = format!(
+            "console=ttyS0 reboot=k panic=1 pci=off ip={}::{}:255.255.255.252::eth0:off hyperdrive.port={}",
+            network.guest_ip, network.host_ip, port
+        );
+        serde_json::json!({
+            "boot-source": {
+                "kernel_image_path": self.config.kernel_path,
+                "boot_args": boot_args,
+            },
+            "drives": [{
+                "drive_id": "rootfs",
+                "path_on_host": self.config.rootfs_path,
+                "is_root_device": true,
+                "is_read_only": true,
+            }],
+            "machine-config": {
+                "vcpu_count": self.config.vcpu_count,
+                "mem_size_mib": self.config.mem_size_mib,
+            },
+            "network-interfaces": [{
+                "iface_id": "eth0",
+                "host_dev_name": network.tap,
+            }],
+        })
+    }
+
+    // Keep a VM's listing current as the pool hands it out and takes it back
+    pub fn update(&self, vm: &VmInstance) {
+        if let Some(running) = self.vms.lock().get_mut(&vm.id) {
+            running.info = vm.info();
+        }
+    }
+
+    // Stop a VM and release its port, tap device and work dir. VMs this
+    // manager isn't running are left alone, so it's safe to call twice.
+    pub async fn destroy(&self, vm: &VmInstance) {
+        let Some(running) = self.vms.lock().remove(&vm.id) else {
+            return;
+        };
+        if let Some(mut child) = running.child {
+            // Already exited if the kill fails
+            let _ = child.start_kill();
+            if let Err(e) = child.wait().await {
+                warn!("Failed to reap Firecracker for VM {}: {}", vm.id, e);
+            }
+        }
+        self.release(vm).await;
+    }
+
+    async fn release(&self, vm: &VmInstance) {
+        release_host_resources(&self.work_dirs, vm).await;
+        if let Some(port) = vm.port {
+            self.ports.release(port);
+        }
+    }
+
+    pub fn list_active_vms(&self) -> Vec<VmInfo> {
+        self.vms.lock().values().map(|running| running.info.clone()).collect()
+    }
+}
+
+// Remove a VM's tap device and work dir. Also used for a VM that outlived
+// its manager, whose Firecracker process went with it (kill on drop).
+pub async fn release_host_resources(work_dirs: &WorkDirs, vm: &VmInstance) {
+    // The guest only has an address once its tap device is up
+    if let (Some(port), Some(_)) = (vm.port, &vm.ip_address) {
+        GuestNetwork::for_port(port).remove().await;
+    }
+    if !vm.work_dir.is_empty() {
+        work_dirs.remove(&vm.work_dir).await;
+    }
+}
+
+// A VM's tap device and the /30 it shares with the host. Both are derived
+// from its V8 host port, so they're unique across generations too, whose
+// port ranges never overlap.
+struct GuestNetwork {
+    tap: String,
+    host_ip: Ipv4Addr,
+    guest_ip: Ipv4Addr,
+}
+
+impl GuestNetwork {
+    fn for_port(port: u16) -> Self {
+        let base = u32::from(GUEST_SUBNET_BASE) + u32::from(port) * 4;
+        Self {
+            tap: format!("hd-tap{}", port),
+            host_ip: Ipv4Addr::from(base + 1),
+            guest_ip: Ipv4Addr::from(base + 2),
+        }
+    }
+
+    async fn create(&self) -> Result<()> {
+        // A crashed run can leave the device behind
+        let _ = ip(&["link", "del", &self.tap]).await;
+        ip(&["tuntap", "add", "dev", &self.tap, "mode", "tap"]).await?;
+        ip(&["addr", "add", &format!("{}/30", self.host_ip), "dev", &self.tap]).await?;
+        ip(&["link", "set", &self.tap, "up"]).await
+    }
+
+    async fn remove(&self) {
+        if let Err(e) = ip(&["link", "del", &self.tap]).await {
+            warn!("Failed to remove tap device {}: {:#}", self.tap, e);
+        }
+    }
+}
+
+async fn ip(args: &[&str]) -> Result<()> {
+    let output = Command::new("ip").args(args).output().await.context("Failed to run ip")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "ip {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_port_allocator_exhaustion_and_reuse() {
+        let ports = PortAllocator::new(9000..=9002);
+
+        assert_eq!(ports.allocate().unwrap(), 9000);
+        assert_eq!(ports.allocate().unwrap(), 9001);
+        assert_eq!(ports.allocate().unwrap(), 9002);
+        assert!(ports.allocate().is_err());
+
+        // Released ports come back once the search wraps around to them
+        ports.release(9001);
+        ports.release(9000);
+        assert_eq!(ports.allocate().unwrap(), 9000);
+        assert_eq!(ports.allocate().unwrap(), 9001);
+        assert_eq!(ports.in_use(), 3);
+    }
+
+    #[test]
+    fn test_port_allocator_concurrent_allocations_are_unique() {
+        let ports = Arc::new(PortAllocator::new(20000..=20999));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let ports = ports.clone();
+                std::thread::spawn(move || (0..125).map(|_| ports.allocate().unwrap()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let mut seen = HashSet::new();
+        for handle in handles {
+            for port in handle.join().unwrap() {
+                assert!(seen.insert(port), "port {} handed out twice", port);
+            }
+        }
+        assert_eq!(seen.len(), 1000);
+        assert!(ports.allocate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_boot_releases_port() {
+        let base = tempfile::tempdir().unwrap();
+        let manager = VmManager::new(VmConfig {
+            kernel_path: base.path().join("missing-vmlinux").to_string_lossy().into_owned(),
+            work_dir_base: base.path().to_string_lossy().into_owned(),
+            port_range_start: 9100,
+            port_range_end: 9100,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        for _ in 0..2 {
+            let failure = manager.boot().await.unwrap_err();
+            assert!(failure.to_string().contains("missing-vmlinux not found"), "{}", failure);
+            assert_eq!(manager.ports.in_use(), 0);
+        }
+        assert!(manager.list_active_vms().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_work_dirs() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let base = tempfile::tempdir().unwrap();
+        let work_dirs = WorkDirs::new(base.path().join("vms"));
+
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let dir_a = work_dirs.create(a).unwrap();
+        let dir_b = work_dirs.create(b).unwrap();
+        std::fs::write(Path::new(&dir_a).join("firecracker.sock"), "").unwrap();
+        let mode = std::fs::metadata(&dir_a).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        work_dirs.remove(&dir_a).await;
+        assert!(!Path::new(&dir_a).exists());
+        work_dirs.remove(&dir_a).await; // already gone
+
+        // Only VM directories are reaped
+        let other = base.path().join("vms").join("keep");
+        std::fs::create_dir(&other).unwrap();
+        assert_eq!(work_dirs.reap_orphans().unwrap(), 1);
+        assert!(!Path::new(&dir_b).exists());
+        assert!(other.exists());
+
+        // Paths outside the base are refused
+        work_dirs.remove(base.path().to_str().unwrap()).await;
+        assert!(base.path().exists());
+    }
+}